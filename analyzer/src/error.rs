@@ -1,30 +1,344 @@
+use crate::types::Type;
+use rustelm_parser::ast::Span;
 use std::{error, fmt, fmt::Formatter};
 
 // pub type Result<T> = std::result::Result<T, TypeCheckError>;
 
+/// A type error, and the span of the offending expression.
 #[derive(Debug, Clone)]
-pub struct TypeCheckError(pub TypeCheckErrorType);
+pub struct TypeCheckError(pub TypeCheckErrorType, pub Span);
+
+impl TypeCheckError {
+    pub fn span(&self) -> Span {
+        self.1
+    }
+}
+
+/// The part an expression plays in its parent, used to word the diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Subject {
+    Expression,
+    Argument,
+    Operand,
+    Condition,
+    Branch,
+    Signal,
+    Function,
+    Field,
+    Pattern,
+    Element,
+}
 
 #[derive(Debug, Clone)]
 pub enum TypeCheckErrorType {
-    TypeMissMatch,
-    UndefinedName,
+    /// The `subject` has type `found` where `expected` is required.
+    TypeMissMatch {
+        subject: Subject,
+        expected: Type,
+        found: Type,
+    },
+    UndefinedName(String),
     ExpectIdentifier,
-    InvalidParamType,
+    /// A lambda taking a signal of type `param` returns the simple type `ret`, `o -> t`.
+    InvalidParamType {
+        param: Type,
+        ret: Type,
+    },
+    /// A signal is required, but the expression has the given type.
+    ExpectSignal(Type),
+    /// A simple type is required, but the expression has the given signal type.
+    ExpectSimple(Type),
+    /// The expression is applied, but has the given non-function type.
+    ExpectFunction(Type),
+    /// The record, of the given type, has no such field.
+    MissingField {
+        field: String,
+        found: Type,
+    },
+    /// A record defines the same field twice.
+    DuplicateField(String),
+    /// `liftn` is given a different number of signals than `n`.
+    LiftArity {
+        expected: usize,
+        found: usize,
+    },
+    UndefinedType(String),
+    UndefinedConstructor(String),
+    /// A declared type is given a different number of arguments than it has parameters.
+    TypeArity {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// A constructor pattern has a different number of arguments than the constructor.
+    ConstructorArity {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// A type or a constructor is declared twice.
+    AlreadyDefined(String),
+    /// A pattern, or the parameters of a type, use the same name twice.
+    DuplicateName(String),
+    LowercaseConstructor(String),
+    /// The patterns do not match every value, the given one is missing.
+    NonExhaustive(String),
+    /// The pattern of a branch only matches values that the branches before it match.
+    RedundantPattern,
+    /// The type annotation of a binding, `annotated`, does not match the type `found` of its
+    /// value.
+    AnnotationMismatch {
+        annotated: Type,
+        found: Type,
+    },
+    /// A recursive binding has no type annotation.
+    MissingAnnotation(String),
+    /// A recursive binding is annotated with a signal type.
+    SignalRecursion(String),
+    /// A recursive binding is not a lambda.
+    RecursiveValue(String),
+    /// A program defines no `main`.
+    MissingMain,
+    /// The `main` of a program has the given simple type.
+    MainNotSignal(Type),
+    /// A signature is not followed by the definition of its name.
+    LoneSignature(String),
+    /// The definitions refer to each other, in a cycle.
+    MutualRecursion(Vec<String>),
+    /// An imported module has no file, it is expected at `path`.
+    MissingModule {
+        name: String,
+        path: String,
+    },
+    /// The header of a module names another module than the one it is imported as.
+    ModuleName {
+        expected: String,
+        found: String,
+    },
+    /// The modules import each other, in a cycle.
+    ImportCycle(Vec<String>),
+    /// A module exposes a name it does not define.
+    UndefinedExport(String),
+    /// The type aliases refer to each other, or the only one refers to itself.
+    AliasCycle(Vec<String>),
+    /// The type declarations of a program refer to each other, at least one of them with `type`.
+    TypeCycle(Vec<String>),
+    /// The type alias, standing for a signal type, is used where a simple type is required.
+    SignalAlias(String),
+}
+
+impl fmt::Display for Subject {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use Subject::*;
+        match self {
+            Expression => write!(f, "expression"),
+            Argument => write!(f, "argument"),
+            Operand => write!(f, "operand"),
+            Condition => write!(f, "condition"),
+            Branch => write!(f, "branch"),
+            Signal => write!(f, "signal"),
+            Function => write!(f, "function"),
+            Field => write!(f, "field"),
+            Pattern => write!(f, "pattern"),
+            Element => write!(f, "element"),
+        }
+    }
 }
 
 impl fmt::Display for TypeCheckError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         use TypeCheckErrorType::*;
-        match self.0 {
-            TypeMissMatch => write!(f, "type miss matched"),
-            UndefinedName => write!(f, "undefined variable"),
+        match &self.0 {
+            TypeMissMatch {
+                subject,
+                expected,
+                found,
+            } => write!(
+                f,
+                "expected `{}` but this {} has type `{}`",
+                expected, subject, found
+            ),
+            UndefinedName(name) => write!(f, "undefined variable `{}`", name),
             ExpectIdentifier => write!(f, "expect identifier"),
-            InvalidParamType => write!(f, "lambda invalid parameter type"),
+            InvalidParamType { param, ret } => write!(
+                f,
+                "a function taking `{}` must return a signal, but this one returns `{}`",
+                param, ret
+            ),
+            ExpectSignal(found) => write!(
+                f,
+                "expected a signal but this expression has type `{}`",
+                found
+            ),
+            ExpectSimple(found) => write!(
+                f,
+                "expected a simple type but this expression has type `{}`",
+                found
+            ),
+            ExpectFunction(found) => write!(
+                f,
+                "expected a function but this expression has type `{}`",
+                found
+            ),
+            MissingField { field, found } => write!(
+                f,
+                "this record has no field `{}`, its type is `{}`",
+                field, found
+            ),
+            DuplicateField(field) => write!(f, "the field `{}` is defined more than once", field),
+            LiftArity { expected, found } => write!(
+                f,
+                "lift{} expects {} signals but was given {}",
+                expected, expected, found
+            ),
+            UndefinedType(name) => write!(f, "undefined type `{}`", name),
+            UndefinedConstructor(name) => write!(f, "undefined constructor `{}`", name),
+            TypeArity {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "the type `{}` expects {} but was given {}",
+                name,
+                arguments(*expected),
+                found
+            ),
+            ConstructorArity {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "the constructor `{}` expects {} but this pattern gives it {}",
+                name,
+                arguments(*expected),
+                found
+            ),
+            AlreadyDefined(name) => write!(f, "`{}` is already defined", name),
+            DuplicateName(name) => write!(f, "the name `{}` is bound more than once", name),
+            LowercaseConstructor(name) => write!(
+                f,
+                "the constructor `{}` must start with a capital letter",
+                name
+            ),
+            NonExhaustive(missing) => write!(
+                f,
+                "the patterns are not exhaustive, `{}` is not matched",
+                missing
+            ),
+            RedundantPattern => write!(
+                f,
+                "this pattern is unreachable, the patterns before it match all its values"
+            ),
+            AnnotationMismatch { annotated, found } => write!(
+                f,
+                "the annotation says `{}`, but the value has type `{}`",
+                annotated, found
+            ),
+            MissingAnnotation(name) => write!(
+                f,
+                "the recursive function `{}` needs a type annotation",
+                name
+            ),
+            SignalRecursion(name) => write!(
+                f,
+                "the recursive function `{}` can not have a signal type, signals can not be \
+                 defined recursively",
+                name
+            ),
+            RecursiveValue(name) => write!(
+                f,
+                "the recursive binding `{}` must be a function `\\x. e`",
+                name
+            ),
+            MissingMain => write!(f, "the program has no `main`"),
+            MainNotSignal(found) => {
+                write!(f, "`main` must be a signal, but it has type `{}`", found)
+            }
+            LoneSignature(name) => write!(
+                f,
+                "the signature of `{}` must be followed by its definition",
+                name
+            ),
+            MutualRecursion(names) => write!(
+                f,
+                "the definitions of {} depend on each other, only a function can refer to itself",
+                names
+                    .iter()
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            MissingModule { name, path } => write!(
+                f,
+                "can not find the module `{}`, it should be in `{}`",
+                name, path
+            ),
+            ModuleName { expected, found } => write!(
+                f,
+                "this file is imported as the module `{}`, but its header names `{}`",
+                expected, found
+            ),
+            ImportCycle(names) => write!(
+                f,
+                "the modules {} import each other",
+                names
+                    .iter()
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            UndefinedExport(name) => {
+                write!(
+                    f,
+                    "`{}` is exposed, but the module does not define it",
+                    name
+                )
+            }
+            AliasCycle(names) if names.len() == 1 => write!(
+                f,
+                "the type alias `{}` refers to itself, only a type declared with `type` can be \
+                 recursive",
+                names[0]
+            ),
+            AliasCycle(names) => write!(
+                f,
+                "the type aliases {} refer to each other, only a type declared with `type` can \
+                 be recursive",
+                names
+                    .iter()
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            TypeCycle(names) => write!(
+                f,
+                "the types {} refer to each other, a type declared with `type` may only refer to \
+                 itself",
+                names
+                    .iter()
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            SignalAlias(name) => write!(
+                f,
+                "the type alias `{}` stands for a signal type, it can not be used inside a simple \
+                 type",
+                name
+            ),
         }
     }
 }
 
+fn arguments(n: usize) -> String {
+    match n {
+        1 => "1 argument".to_owned(),
+        n => format!("{} arguments", n),
+    }
+}
+
 impl error::Error for TypeCheckError {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         None
@@ -0,0 +1,251 @@
+//! Exhaustiveness and redundancy of pattern matching, following Maranget's "Warnings for
+//! pattern matching". The patterns are checked once they are known to be well typed, so the
+//! patterns in a column always match values of the same type.
+
+use rustelm_parser::ast::{Atom, Located, Pattern};
+use std::fmt;
+
+/// The constructors of the type of a declared constructor, in order, with their arities.
+pub type Siblings<'a> = dyn Fn(&str) -> Vec<(String, usize)> + 'a;
+
+/// The value of `patterns`, in order, that none of them matches, printed as a pattern. `None`
+/// if they are exhaustive.
+pub fn missing(patterns: &[&Pattern], siblings: &Siblings) -> Option<String> {
+    let rows: Vec<_> = patterns.iter().map(|p| vec![Pat::from(*p)]).collect();
+    Matrix { siblings }
+        .missing(&rows, 1)
+        .map(|mut values| values.remove(0).to_string())
+}
+
+/// The index of the first of `patterns` that only matches values matched by the ones before it.
+pub fn redundant(patterns: &[&Pattern], siblings: &Siblings) -> Option<usize> {
+    let matrix = Matrix { siblings };
+    let rows: Vec<_> = patterns.iter().map(|p| vec![Pat::from(*p)]).collect();
+    (0..rows.len()).find(|&i| !matrix.useful(&rows[..i], &rows[i]))
+}
+
+/// What a pattern checks of a value, its constructor.
+#[derive(Clone, PartialEq)]
+enum Ctor {
+    Unit,
+    Bool(bool),
+    Int(String),
+    Str(String),
+    Tuple(usize),
+    Nil,
+    Cons,
+    Named(String),
+}
+
+/// A pattern, reduced to what decides which values it matches.
+#[derive(Clone)]
+enum Pat {
+    Any,
+    Ctor(Ctor, Vec<Pat>),
+}
+
+impl From<&Pattern> for Pat {
+    fn from(pattern: &Pattern) -> Pat {
+        match pattern {
+            Pattern::Var(_) | Pattern::Wildcard => Pat::Any,
+            Pattern::Literal(Atom::Unit) => Pat::Ctor(Ctor::Unit, vec![]),
+            Pattern::Literal(Atom::Bool(b)) => Pat::Ctor(Ctor::Bool(*b), vec![]),
+            Pattern::Literal(Atom::Num(n)) => Pat::Ctor(Ctor::Int(n.to_string()), vec![]),
+            Pattern::Literal(Atom::Str(s)) => Pat::Ctor(Ctor::Str(s.clone()), vec![]),
+            Pattern::Literal(_) => unreachable!(),
+            Pattern::Tuple(patterns) => Pat::Ctor(Ctor::Tuple(patterns.len()), Pat::args(patterns)),
+            // `[x, y]` is `x :: y :: []`
+            Pattern::List(patterns) => patterns
+                .iter()
+                .rev()
+                .fold(Pat::Ctor(Ctor::Nil, vec![]), |tail, head| {
+                    Pat::Ctor(Ctor::Cons, vec![Pat::from(&head.node), tail])
+                }),
+            Pattern::Cons(head, tail) => Pat::Ctor(
+                Ctor::Cons,
+                vec![Pat::from(&head.node), Pat::from(&tail.node)],
+            ),
+            Pattern::Constructor(name, patterns) => {
+                Pat::Ctor(Ctor::Named(name.clone()), Pat::args(patterns))
+            }
+            Pattern::Annotated(pattern, _) => Pat::from(&pattern.node),
+        }
+    }
+}
+
+impl Pat {
+    fn args(patterns: &[Located<Pattern>]) -> Vec<Pat> {
+        patterns.iter().map(|p| Pat::from(&p.node)).collect()
+    }
+}
+
+/// Rows of patterns, one column per value being matched.
+struct Matrix<'a> {
+    siblings: &'a Siblings<'a>,
+}
+
+impl<'a> Matrix<'a> {
+    /// All the constructors of the type of `ctor`, with their arities, or `None` if there are
+    /// too many to list, like for integers.
+    fn all(&self, ctor: &Ctor) -> Option<Vec<(Ctor, usize)>> {
+        match ctor {
+            Ctor::Unit => Some(vec![(Ctor::Unit, 0)]),
+            Ctor::Bool(_) => Some(vec![(Ctor::Bool(true), 0), (Ctor::Bool(false), 0)]),
+            Ctor::Int(_) | Ctor::Str(_) => None,
+            Ctor::Tuple(n) => Some(vec![(Ctor::Tuple(*n), *n)]),
+            Ctor::Nil | Ctor::Cons => Some(vec![(Ctor::Nil, 0), (Ctor::Cons, 2)]),
+            Ctor::Named(name) => Some(
+                (self.siblings)(name)
+                    .into_iter()
+                    .map(|(name, arity)| (Ctor::Named(name), arity))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// The constructors in the first column.
+    fn heads(rows: &[Vec<Pat>]) -> Vec<&Ctor> {
+        rows.iter()
+            .filter_map(|row| match &row[0] {
+                Pat::Ctor(ctor, _) => Some(ctor),
+                Pat::Any => None,
+            })
+            .collect()
+    }
+
+    /// All the constructors of the type of the first column, if each of them is in the column.
+    fn complete(&self, rows: &[Vec<Pat>]) -> Option<Vec<(Ctor, usize)>> {
+        let heads = Matrix::heads(rows);
+        let all = self.all(heads.first()?)?;
+        if all.iter().all(|(ctor, _)| heads.contains(&ctor)) {
+            Some(all)
+        } else {
+            None
+        }
+    }
+
+    /// The rows matching a value built with `ctor`, with the first column replaced by the
+    /// arguments of the constructor.
+    fn specialize(rows: &[Vec<Pat>], ctor: &Ctor, arity: usize) -> Vec<Vec<Pat>> {
+        rows.iter()
+            .filter_map(|row| {
+                let args = match &row[0] {
+                    Pat::Any => vec![Pat::Any; arity],
+                    Pat::Ctor(other, args) if other == ctor => args.clone(),
+                    Pat::Ctor(_, _) => return None,
+                };
+                Some(args.into_iter().chain(row[1..].iter().cloned()).collect())
+            })
+            .collect()
+    }
+
+    /// The rows matching any value in the first column, without it.
+    fn default(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+        rows.iter()
+            .filter(|row| match row[0] {
+                Pat::Any => true,
+                Pat::Ctor(_, _) => false,
+            })
+            .map(|row| row[1..].to_vec())
+            .collect()
+    }
+
+    /// Whether `row` matches some values that none of `rows` matches.
+    fn useful(&self, rows: &[Vec<Pat>], row: &[Pat]) -> bool {
+        let (first, rest) = match row.split_first() {
+            Some(split) => split,
+            None => return rows.is_empty(),
+        };
+
+        match first {
+            Pat::Ctor(ctor, args) => {
+                let row: Vec<_> = args.iter().chain(rest).cloned().collect();
+                self.useful(&Matrix::specialize(rows, ctor, args.len()), &row)
+            }
+            Pat::Any => match self.complete(rows) {
+                Some(all) => all.iter().any(|(ctor, arity)| {
+                    let row = Matrix::specialize(&[row.to_vec()], ctor, *arity).remove(0);
+                    self.useful(&Matrix::specialize(rows, ctor, *arity), &row)
+                }),
+                None => self.useful(&Matrix::default(rows), rest),
+            },
+        }
+    }
+
+    /// Values for `width` columns that none of `rows` matches, or `None` if they match all.
+    fn missing(&self, rows: &[Vec<Pat>], width: usize) -> Option<Vec<Pat>> {
+        if width == 0 {
+            return if rows.is_empty() { Some(vec![]) } else { None };
+        }
+
+        match self.complete(rows) {
+            Some(all) => all.into_iter().find_map(|(ctor, arity)| {
+                let specialized = Matrix::specialize(rows, &ctor, arity);
+                let mut values = self.missing(&specialized, arity + width - 1)?;
+                let rest = values.split_off(arity);
+                Some(
+                    Some(Pat::Ctor(ctor, values))
+                        .into_iter()
+                        .chain(rest)
+                        .collect(),
+                )
+            }),
+            None => {
+                let mut values = self.missing(&Matrix::default(rows), width - 1)?;
+
+                // Name a constructor missing from the column, if they can be listed
+                let heads = Matrix::heads(rows);
+                let value = heads
+                    .first()
+                    .and_then(|ctor| self.all(ctor))
+                    .and_then(|all| all.into_iter().find(|(ctor, _)| !heads.contains(&ctor)))
+                    .map_or(Pat::Any, |(ctor, arity)| {
+                        Pat::Ctor(ctor, vec![Pat::Any; arity])
+                    });
+                values.insert(0, value);
+                Some(values)
+            }
+        }
+    }
+}
+
+/// Printed the way patterns are written in the source, e.g. `Rect _ _`.
+impl fmt::Display for Pat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pat::Any => write!(f, "_"),
+            Pat::Ctor(Ctor::Unit, _) => write!(f, "()"),
+            Pat::Ctor(Ctor::Bool(true), _) => write!(f, "True"),
+            Pat::Ctor(Ctor::Bool(false), _) => write!(f, "False"),
+            Pat::Ctor(Ctor::Int(n), _) => write!(f, "{}", n),
+            Pat::Ctor(Ctor::Str(s), _) => write!(f, "{:?}", s),
+            Pat::Ctor(Ctor::Tuple(_), args) => {
+                write!(f, "(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Pat::Ctor(Ctor::Nil, _) => write!(f, "[]"),
+            Pat::Ctor(Ctor::Cons, args) => match &args[0] {
+                Pat::Ctor(Ctor::Cons, _) => write!(f, "({}) :: {}", args[0], args[1]),
+                head => write!(f, "{} :: {}", head, args[1]),
+            },
+            Pat::Ctor(Ctor::Named(name), args) => {
+                write!(f, "{}", name)?;
+                for arg in args {
+                    match arg {
+                        Pat::Ctor(Ctor::Named(_), nested) if !nested.is_empty() => {
+                            write!(f, " ({})", arg)?
+                        }
+                        _ => write!(f, " {}", arg)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
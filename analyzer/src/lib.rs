@@ -2,5 +2,9 @@ extern crate im;
 #[macro_use]
 extern crate lazy_static;
 
-mod error;
+pub mod error;
+mod exhaustive;
+pub mod modules;
+mod program;
 pub mod typechecker;
+pub mod types;
@@ -0,0 +1,830 @@
+//! Programs made of several files. A module is a file under the source root, `import Foo.Bar`
+//! reads the module `Foo.Bar` from `Foo/Bar.elm`, and the other modules refer to what it exposes
+//! by qualified names, `Foo.Bar.x`.
+//!
+//! The modules are typechecked one at a time, each after the modules it imports, in a `Context`
+//! made of the types these modules expose. The type declarations and the type aliases of a module
+//! are always exposed, with their constructors, they are declared around the modules importing
+//! it. Once every module
+//! is typechecked, they are linked into a single expression to evaluate.
+
+use crate::error::{TypeCheckError, TypeCheckErrorType};
+use crate::program;
+use crate::typechecker::{typecheck_definitions, typecheck_main, Context, INPUTS};
+use rustelm_parser::{
+    ast::{
+        Atom, Decl, Expr, ExprKind, Located, Pattern, Program, SignalType, SimpleType, Span,
+        TypeAlias, TypeDef, Types,
+    },
+    parser::{parse_program, ParserError},
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{error, fmt, fs, io};
+
+/// A module, parsed from the file at `path`.
+pub struct Module {
+    pub name: String,
+    pub path: PathBuf,
+    pub source: String,
+    pub program: Program,
+}
+
+/// An error in one of the modules of a program, along with the file it is in.
+#[derive(Debug, Clone)]
+pub struct ModuleError {
+    pub path: PathBuf,
+    pub source: String,
+    pub kind: Box<ModuleErrorKind>,
+}
+
+#[derive(Debug, Clone)]
+pub enum ModuleErrorKind {
+    Parse(ParserError),
+    Type(TypeCheckError),
+}
+
+impl ModuleError {
+    fn new(module: &Module, kind: ModuleErrorKind) -> ModuleError {
+        ModuleError {
+            path: module.path.clone(),
+            source: module.source.clone(),
+            kind: Box::new(kind),
+        }
+    }
+
+    /// The span of the offending input, in `source`.
+    pub fn span(&self) -> Span {
+        match &*self.kind {
+            ModuleErrorKind::Parse(e) => e.span(),
+            ModuleErrorKind::Type(e) => e.span(),
+        }
+    }
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &*self.kind {
+            ModuleErrorKind::Parse(e) => write!(f, "{}", e),
+            ModuleErrorKind::Type(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for ModuleError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+/// Typecheck the program whose entry module, at `path`, is `source`, reading the modules it
+/// imports under `root`. Returns the expression the whole program stands for.
+pub fn check(root: &Path, path: &Path, source: String) -> Result<Expr, ModuleError> {
+    check_with(root, path, source, &mut |path| fs::read_to_string(path))
+}
+
+/// Like `check`, reading the imported modules with `read`.
+pub fn check_with(
+    root: &Path,
+    path: &Path,
+    source: String,
+    read: &mut dyn FnMut(&Path) -> io::Result<String>,
+) -> Result<Expr, ModuleError> {
+    let mut loader = Loader {
+        root,
+        read,
+        modules: vec![],
+        loading: vec![],
+    };
+    loader.load(None, path.to_owned(), source)?;
+    let modules = loader.modules;
+
+    // The type declarations and the types of the definitions each module exposes
+    let mut declared: HashMap<&str, Vec<Decl>> = HashMap::new();
+    let mut exposed: HashMap<&str, Context> = HashMap::new();
+    let mut decls = vec![];
+    let (entry, libraries) = modules.split_last().unwrap();
+    for module in libraries {
+        let program = qualify(&module.program, &module.name);
+        let (context, types) = imports(module, &declared, &exposed);
+        let names = exposing(module, &program)
+            .map_err(|e| ModuleError::new(module, ModuleErrorKind::Type(e)))?;
+
+        let unit = Located {
+            node: ExprKind::Const(Located {
+                node: Atom::Unit,
+                span: Span::default(),
+            }),
+            span: Span::default(),
+        };
+        let context = program::bind_all(&program, unit)
+            .map(|expr| declare(types, expr))
+            .and_then(|expr| typecheck_definitions(&context, &expr, &names))
+            .map_err(|e| ModuleError::new(module, ModuleErrorKind::Type(e)))?;
+
+        let defs = program.decls.iter().filter_map(|decl| match decl {
+            Decl::Type(_) | Decl::Alias(_) => Some(decl.clone()),
+            _ => None,
+        });
+        declared.insert(&module.name, defs.collect());
+        exposed.insert(&module.name, context);
+        decls.extend(program.decls);
+    }
+
+    let (context, types) = imports(entry, &declared, &exposed);
+    program::lower(&entry.program)
+        .and_then(|(expr, main)| typecheck_main(&context, &declare(types, expr), main))
+        .map_err(|e| ModuleError::new(entry, ModuleErrorKind::Type(e)))?;
+
+    // Link the modules, their qualified names do not clash
+    decls.extend(entry.program.decls.iter().cloned());
+    let program = Program {
+        header: None,
+        imports: vec![],
+        decls,
+    };
+    program::lower(&program)
+        .map(|(expr, _)| expr)
+        .map_err(|e| ModuleError::new(entry, ModuleErrorKind::Type(e)))
+}
+
+/// Reads the modules a program is made of, each one after the modules it imports.
+struct Loader<'a> {
+    root: &'a Path,
+    read: &'a mut dyn FnMut(&Path) -> io::Result<String>,
+    modules: Vec<Module>,
+    /// The names of the modules being loaded, each one imported by the one before it.
+    loading: Vec<String>,
+}
+
+impl<'a> Loader<'a> {
+    /// Load the module `name`, the entry module if `None`, and the modules it imports.
+    fn load(
+        &mut self,
+        name: Option<&str>,
+        path: PathBuf,
+        source: String,
+    ) -> Result<(), ModuleError> {
+        let program = match parse_program(&source) {
+            Ok(program) => program,
+            Err(e) => {
+                let kind = Box::new(ModuleErrorKind::Parse(e));
+                return Err(ModuleError { path, source, kind });
+            }
+        };
+        let header = program.header.as_ref().map(|header| header.name.clone());
+        let module = Module {
+            name: name
+                .map(str::to_owned)
+                .or_else(|| header.as_ref().map(|name| name.node.clone()))
+                .unwrap_or_else(|| "Main".to_owned()),
+            path,
+            source,
+            program,
+        };
+        let error = |kind| Err(ModuleError::new(&module, ModuleErrorKind::Type(kind)));
+
+        // An imported module must be named after its file
+        if let (Some(name), Some(header)) = (name, &header) {
+            if header.node != name {
+                let kind = TypeCheckErrorType::ModuleName {
+                    expected: name.to_owned(),
+                    found: header.node.clone(),
+                };
+                return error(TypeCheckError(kind, header.span));
+            }
+        }
+
+        self.loading.push(module.name.clone());
+        for import in &module.program.imports {
+            let name = &import.node;
+            if let Some(i) = self.loading.iter().position(|other| other == name) {
+                let kind = TypeCheckErrorType::ImportCycle(self.loading[i..].to_vec());
+                return error(TypeCheckError(kind, import.span));
+            }
+            if self.modules.iter().any(|other| other.name == *name) {
+                continue;
+            }
+
+            let path = self.root.join(format!("{}.elm", name.replace('.', "/")));
+            let mut source = match (self.read)(&path) {
+                Ok(source) => source,
+                Err(_) => {
+                    let kind = TypeCheckErrorType::MissingModule {
+                        name: name.clone(),
+                        path: path.display().to_string(),
+                    };
+                    return error(TypeCheckError(kind, import.span));
+                }
+            };
+            source.push('\n');
+            self.load(Some(name), path, source)?;
+        }
+        self.loading.pop();
+
+        self.modules.push(module);
+        Ok(())
+    }
+}
+
+/// The types the modules imported by `module` expose under their qualified names, and their type
+/// declarations.
+fn imports(
+    module: &Module,
+    declared: &HashMap<&str, Vec<Decl>>,
+    exposed: &HashMap<&str, Context>,
+) -> (Context, Vec<Decl>) {
+    let mut context = INPUTS.clone();
+    let mut types = vec![];
+    let mut imported = vec![];
+    for import in &module.program.imports {
+        let name = import.node.as_str();
+        if !imported.contains(&name) {
+            imported.push(name);
+            context.extend(exposed[name].clone());
+            types.extend(declared[name].iter().cloned());
+        }
+    }
+    (context, types)
+}
+
+/// The definitions of `program`, the qualified program of `module`, that it exposes.
+fn exposing<'a>(
+    module: &Module,
+    program: &'a Program,
+) -> Result<Vec<&'a Located<String>>, TypeCheckError> {
+    let definitions: Vec<_> = program
+        .decls
+        .iter()
+        .filter_map(|decl| match decl {
+            Decl::Value(name, _) => Some(name),
+            _ => None,
+        })
+        .collect();
+    let names = match module
+        .program
+        .header
+        .as_ref()
+        .and_then(|h| h.exposing.as_ref())
+    {
+        Some(names) => names,
+        None => return Ok(definitions),
+    };
+
+    let mut exposed = vec![];
+    for name in names {
+        let qualified = format!("{}.{}", module.name, name.node);
+        let is_type = |decl: &Decl| match decl {
+            Decl::Type(def) => def.name.node == qualified,
+            Decl::Alias(def) => def.name.node == qualified,
+            _ => false,
+        };
+        match definitions.iter().find(|def| def.node == qualified) {
+            Some(def) => exposed.push(*def),
+            // Types are always exposed
+            None if program.decls.iter().any(is_type) => (),
+            None => {
+                let kind = TypeCheckErrorType::UndefinedExport(name.node.clone());
+                return Err(TypeCheckError(kind, name.span));
+            }
+        }
+    }
+    Ok(exposed)
+}
+
+/// `expr` in scope of the type declarations `types`.
+fn declare(types: Vec<Decl>, expr: Expr) -> Expr {
+    types
+        .iter()
+        .rev()
+        .fold(expr, |expr, decl| program::declare(decl, expr))
+}
+
+/// `program`, the module `module`, with the names of its definitions, types and constructors
+/// qualified by the name of the module, where they are declared and where they are used.
+fn qualify(program: &Program, module: &str) -> Program {
+    let mut values = vec![];
+    let mut types = vec![];
+    for decl in &program.decls {
+        match decl {
+            Decl::Type(def) => {
+                types.push(def.name.node.clone());
+                values.extend(def.constructors.iter().map(|c| c.name.node.clone()));
+            }
+            Decl::Alias(def) => types.push(def.name.node.clone()),
+            Decl::Value(name, _) => values.push(name.node.clone()),
+            Decl::Signature(..) => (),
+        }
+    }
+
+    let mut qualifier = Qualifier {
+        module,
+        values,
+        types,
+        bound: vec![],
+        bound_types: vec![],
+    };
+    let decls = program
+        .decls
+        .iter()
+        .map(|decl| match decl.clone() {
+            Decl::Type(def) => Decl::Type(qualifier.type_def(def, true)),
+            Decl::Alias(def) => Decl::Alias(qualifier.alias(def, true)),
+            Decl::Signature(name, ty) => {
+                Decl::Signature(qualifier.declared(name), qualifier.located_type(ty))
+            }
+            Decl::Value(name, e) => Decl::Value(qualifier.declared(name), qualifier.expr(e)),
+        })
+        .collect();
+    Program {
+        header: program.header.clone(),
+        imports: program.imports.clone(),
+        decls,
+    }
+}
+
+/// Qualifies the top-level names of a module, except where a local name shadows them.
+struct Qualifier<'a> {
+    module: &'a str,
+    /// The definitions and the constructors of the module.
+    values: Vec<String>,
+    types: Vec<String>,
+    /// The local names in scope, and the local types.
+    bound: Vec<String>,
+    bound_types: Vec<String>,
+}
+
+impl<'a> Qualifier<'a> {
+    fn declared(&self, name: Located<String>) -> Located<String> {
+        Located {
+            node: format!("{}.{}", self.module, name.node),
+            span: name.span,
+        }
+    }
+
+    fn value(&self, name: String) -> String {
+        if self.values.contains(&name) && !self.bound.contains(&name) {
+            format!("{}.{}", self.module, name)
+        } else {
+            name
+        }
+    }
+
+    fn type_name(&self, name: String) -> String {
+        if self.types.contains(&name) && !self.bound_types.contains(&name) {
+            format!("{}.{}", self.module, name)
+        } else {
+            name
+        }
+    }
+
+    /// A type declaration, of the module if `top`. The name of a local one is bound from there.
+    fn type_def(&mut self, def: TypeDef, top: bool) -> TypeDef {
+        let depth = self.bound_types.len();
+        let name = if top {
+            self.declared(def.name)
+        } else {
+            self.bound_types.push(def.name.node.clone());
+            def.name
+        };
+        self.bound_types
+            .extend(def.params.iter().map(|param| param.node.clone()));
+
+        let mut constructors = vec![];
+        for mut constructor in def.constructors {
+            if top {
+                constructor.name = self.declared(constructor.name);
+            }
+            constructor.args = constructor
+                .args
+                .into_iter()
+                .map(|arg| Located {
+                    node: self.simple(arg.node),
+                    span: arg.span,
+                })
+                .collect();
+            constructors.push(constructor);
+        }
+
+        self.bound_types
+            .truncate(if top { depth } else { depth + 1 });
+        TypeDef {
+            name,
+            params: def.params,
+            constructors,
+        }
+    }
+
+    /// A type alias, of the module if `top`. The name of a local one is bound from there.
+    fn alias(&mut self, def: TypeAlias, top: bool) -> TypeAlias {
+        let depth = self.bound_types.len();
+        let name = if top {
+            self.declared(def.name)
+        } else {
+            def.name
+        };
+        self.bound_types
+            .extend(def.params.iter().map(|param| param.node.clone()));
+        let ty = self.located_type(def.ty);
+        self.bound_types.truncate(depth);
+        if !top {
+            self.bound_types.push(name.node.clone());
+        }
+        TypeAlias {
+            name,
+            params: def.params,
+            ty,
+        }
+    }
+
+    fn expr(&mut self, expr: Expr) -> Expr {
+        let node = match expr.node {
+            ExprKind::Const(atom) => ExprKind::Const(self.atom(atom)),
+            ExprKind::Abs(param, ty, e) => {
+                let ty = ty.map(|ty| self.located_type(ty));
+                let depth = self.bound.len();
+                if let Atom::Var(name) = &param.node {
+                    self.bound.push(name.clone());
+                }
+                let e = self.boxed(*e);
+                self.bound.truncate(depth);
+                ExprKind::Abs(param, ty, e)
+            }
+            ExprKind::Lambda(..) => unreachable!("removed by desugaring"),
+            ExprKind::App(e1, e2) => ExprKind::App(self.boxed(*e1), self.boxed(*e2)),
+            ExprKind::BinOp(e1, op, e2) => ExprKind::BinOp(self.boxed(*e1), op, self.boxed(*e2)),
+            ExprKind::UnOp(op, e) => ExprKind::UnOp(op, self.boxed(*e)),
+            ExprKind::If(e1, e2, e3) => {
+                ExprKind::If(self.boxed(*e1), self.boxed(*e2), self.boxed(*e3))
+            }
+            ExprKind::Let(pattern, e1, e2) => {
+                let e1 = self.boxed(*e1);
+                let depth = self.bound.len();
+                let pattern = self.pattern(pattern);
+                let e2 = self.boxed(*e2);
+                self.bound.truncate(depth);
+                ExprKind::Let(pattern, e1, e2)
+            }
+            ExprKind::LetRec(name, ty, e1, e2) => {
+                let ty = ty.map(|ty| self.located_type(ty));
+                self.bound.push(name.node.clone());
+                let (e1, e2) = (self.boxed(*e1), self.boxed(*e2));
+                self.bound.pop();
+                ExprKind::LetRec(name, ty, e1, e2)
+            }
+            ExprKind::Tuple(es) => ExprKind::Tuple(self.all(es)),
+            ExprKind::List(es) => ExprKind::List(self.all(es)),
+            ExprKind::Record(fields) => ExprKind::Record(self.fields(fields)),
+            ExprKind::Field(e, field) => ExprKind::Field(self.boxed(*e), field),
+            ExprKind::Update(e, fields) => ExprKind::Update(self.boxed(*e), self.fields(fields)),
+            ExprKind::TypeDef(def, e) => {
+                let (depth, types) = (self.bound.len(), self.bound_types.len());
+                let def = self.type_def(def, false);
+                self.bound
+                    .extend(def.constructors.iter().map(|c| c.name.node.clone()));
+                let e = self.boxed(*e);
+                self.bound.truncate(depth);
+                self.bound_types.truncate(types);
+                ExprKind::TypeDef(def, e)
+            }
+            ExprKind::TypeAlias(def, e) => {
+                let types = self.bound_types.len();
+                let def = self.alias(def, false);
+                let e = self.boxed(*e);
+                self.bound_types.truncate(types);
+                ExprKind::TypeAlias(def, e)
+            }
+            ExprKind::Case(e, branches) => {
+                let e = self.boxed(*e);
+                let branches = branches
+                    .into_iter()
+                    .map(|(pattern, e)| {
+                        let depth = self.bound.len();
+                        let pattern = self.pattern(pattern);
+                        let e = self.expr(e);
+                        self.bound.truncate(depth);
+                        (pattern, e)
+                    })
+                    .collect();
+                ExprKind::Case(e, branches)
+            }
+            ExprKind::Lift(n, e, args) => {
+                let e = self.boxed(*e);
+                let args = args.into_iter().map(|arg| self.atom(arg)).collect();
+                ExprKind::Lift(n, e, args)
+            }
+            ExprKind::Foldp(e1, e2, e3) => {
+                ExprKind::Foldp(self.boxed(*e1), self.boxed(*e2), self.boxed(*e3))
+            }
+            ExprKind::Async(e) => ExprKind::Async(self.boxed(*e)),
+        };
+        Located {
+            node,
+            span: expr.span,
+        }
+    }
+
+    fn boxed(&mut self, expr: Expr) -> Box<Expr> {
+        Box::new(self.expr(expr))
+    }
+
+    fn all(&mut self, exprs: Vec<Expr>) -> Vec<Expr> {
+        exprs.into_iter().map(|e| self.expr(e)).collect()
+    }
+
+    fn fields(&mut self, fields: Vec<(Located<String>, Expr)>) -> Vec<(Located<String>, Expr)> {
+        fields
+            .into_iter()
+            .map(|(name, e)| (name, self.expr(e)))
+            .collect()
+    }
+
+    fn atom(&self, atom: Located<Atom>) -> Located<Atom> {
+        match atom.node {
+            Atom::Var(name) => Located {
+                node: Atom::Var(self.value(name)),
+                span: atom.span,
+            },
+            _ => atom,
+        }
+    }
+
+    /// Qualify the constructors of `pattern`, binding its names from there.
+    fn pattern(&mut self, pattern: Located<Pattern>) -> Located<Pattern> {
+        let node = match pattern.node {
+            Pattern::Var(name) => {
+                self.bound.push(name.clone());
+                Pattern::Var(name)
+            }
+            Pattern::Wildcard => Pattern::Wildcard,
+            Pattern::Literal(atom) => Pattern::Literal(atom),
+            Pattern::Tuple(patterns) => Pattern::Tuple(self.patterns(patterns)),
+            Pattern::List(patterns) => Pattern::List(self.patterns(patterns)),
+            Pattern::Cons(head, tail) => {
+                let head = self.pattern(*head);
+                let tail = self.pattern(*tail);
+                Pattern::Cons(Box::new(head), Box::new(tail))
+            }
+            Pattern::Constructor(name, patterns) => {
+                let name = self.value(name);
+                Pattern::Constructor(name, self.patterns(patterns))
+            }
+            Pattern::Annotated(inner, ty) => {
+                let ty = self.located_type(ty);
+                Pattern::Annotated(Box::new(self.pattern(*inner)), ty)
+            }
+        };
+        Located {
+            node,
+            span: pattern.span,
+        }
+    }
+
+    fn patterns(&mut self, patterns: Vec<Located<Pattern>>) -> Vec<Located<Pattern>> {
+        patterns.into_iter().map(|p| self.pattern(p)).collect()
+    }
+
+    fn located_type(&self, ty: Located<Types>) -> Located<Types> {
+        let node = match ty.node {
+            Types::Simple(ty) => Types::Simple(self.simple(ty)),
+            Types::Signal(ty) => Types::Signal(self.signal(ty)),
+        };
+        Located {
+            node,
+            span: ty.span,
+        }
+    }
+
+    fn signal(&self, ty: SignalType) -> SignalType {
+        match ty {
+            SignalType::Signal(ty) => SignalType::Signal(self.simple(ty)),
+            SignalType::Abs1(t, o) => SignalType::Abs1(self.simple(t), Box::new(self.signal(*o))),
+            SignalType::Abs2(o1, o2) => {
+                SignalType::Abs2(Box::new(self.signal(*o1)), Box::new(self.signal(*o2)))
+            }
+            SignalType::Named(name, args) => SignalType::Named(
+                self.type_name(name),
+                args.into_iter().map(|t| self.simple(t)).collect(),
+            ),
+        }
+    }
+
+    fn simple(&self, ty: SimpleType) -> SimpleType {
+        let boxed = |ty: Box<SimpleType>| Box::new(self.simple(*ty));
+        match ty {
+            SimpleType::Abs(t1, t2) => SimpleType::Abs(boxed(t1), boxed(t2)),
+            SimpleType::Tuple(ts) => {
+                SimpleType::Tuple(ts.into_iter().map(|t| self.simple(t)).collect())
+            }
+            SimpleType::Record(fields, rest) => SimpleType::Record(
+                fields
+                    .into_iter()
+                    .map(|(name, t)| (name, self.simple(t)))
+                    .collect(),
+                rest,
+            ),
+            SimpleType::List(t) => SimpleType::List(boxed(t)),
+            SimpleType::Named(name, args) => SimpleType::Named(
+                self.type_name(name),
+                args.into_iter().map(|t| self.simple(t)).collect(),
+            ),
+            ty => ty,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_with, ModuleError};
+    use std::collections::HashMap;
+    use std::io;
+    use std::path::Path;
+
+    /// Check the program `main`, the other modules being the files of `files`.
+    fn check(files: &[(&str, &str)], main: &str) -> Result<String, ModuleError> {
+        let files: HashMap<_, _> = files.iter().cloned().collect();
+        let mut read = |path: &Path| {
+            let path = path.to_str().unwrap();
+            match files.get(path) {
+                Some(source) => Ok(source.to_string()),
+                None => Err(io::Error::new(io::ErrorKind::NotFound, path)),
+            }
+        };
+        let expr = check_with(
+            Path::new("src"),
+            Path::new("main.elm"),
+            main.to_owned(),
+            &mut read,
+        )?;
+        Ok(format!("{:?}", expr))
+    }
+
+    fn err(files: &[(&str, &str)], main: &str) -> (String, String, usize, usize) {
+        let error = check(files, main).unwrap_err();
+        let span = error.span();
+        (
+            error.path.display().to_string(),
+            error.to_string(),
+            span.start,
+            span.end,
+        )
+    }
+
+    const SHAPE: (&str, &str) = (
+        "src/Geo/Shape.elm",
+        "module Geo.Shape exposing (area, Shape, id)\n\
+         type Shape = Square int | Rect int int\n\
+         area s = case s of { Square n -> n * n; Rect w h -> w * h }\n\
+         id x = x\n\
+         secret = 1\n",
+    );
+
+    #[test]
+    fn test_modules() {
+        // The definitions of the modules are linked under their qualified names
+        assert_eq!(
+            check(&[SHAPE], "import Geo.Shape\nmain = lift1 Geo.Shape.area: s\ns = lift1 (\\x. Geo.Shape.Square x): MouseX\n").unwrap(),
+            "type Geo.Shape.Shape = Geo.Shape.Square int | Geo.Shape.Rect int int in \
+             let \"Geo.Shape.area\" = \\\"s\". -> case \"s\" of { Geo.Shape.Square \"n\" -> (\"n\" * \"n\"); \
+             Geo.Shape.Rect \"w\" \"h\" -> (\"w\" * \"h\") } in \
+             let \"Geo.Shape.id\" = \\\"x\". -> \"x\" in let \"Geo.Shape.secret\" = 1 in \
+             let \"s\" = lift1 \\\"x\". -> (\"Geo.Shape.Square\" \"x\") \"MouseX\"! in \
+             let \"main\" = lift1 \"Geo.Shape.area\" \"s\"! in \"main\""
+        );
+
+        // Exposed definitions are polymorphic, and the types are exposed with their constructors
+        let main = "import Geo.Shape\n\
+                    f : Geo.Shape.Shape -> bool\n\
+                    f s = case s of { Geo.Shape.Rect _ _ -> Geo.Shape.id True; _ -> False }\n\
+                    main = lift1 (\\x. if f (Geo.Shape.Rect x x) then Geo.Shape.id x else 0): MouseX\n";
+        assert!(check(&[SHAPE], main).is_ok());
+
+        // A module refers to its own definitions unqualified, unless a local name shadows them
+        let util = (
+            "src/Util.elm",
+            "module Util exposing (twice, f)\n\
+             import Geo.Shape\n\
+             twice g x = g (g x)\n\
+             f x = let twice = x in Geo.Shape.area (Geo.Shape.Square twice)\n",
+        );
+        assert!(check(
+            &[SHAPE, util],
+            "import Util\nmain = lift1 (\\x. Util.twice Util.f x): MouseX\n"
+        )
+        .is_ok());
+
+        // Type aliases are exposed like types, under their qualified names
+        let input = (
+            "src/Input.elm",
+            "module Input exposing (Mouse, scaled)\n\
+             type alias Mouse = signal int.\n\
+             scaled : int -> Mouse\n\
+             scaled n = lift1 (\\x. x * n): MouseX\n",
+        );
+        assert!(check(
+            &[input],
+            "import Input\nmain : Input.Mouse\nmain = Input.scaled 2\n"
+        )
+        .is_ok());
+        assert_eq!(
+            err(&[input], "import Input\nmain : Input.Mouse\nmain = 1\n"),
+            (
+                "main.elm".to_owned(),
+                "the annotation says `Input.Mouse`, but the value has type `int`".to_owned(),
+                20,
+                31
+            )
+        );
+    }
+
+    #[test]
+    fn test_module_errors() {
+        let a = ("src/A.elm", "module A exposing (..)\nimport B\nx = 1\n");
+        let b = ("src/B.elm", "module B exposing (..)\nimport A\ny = 1\n");
+        assert_eq!(
+            err(&[a, b], "import A\nmain = MouseX\n"),
+            (
+                "src/B.elm".to_owned(),
+                "the modules `A`, `B` import each other".to_owned(),
+                30,
+                31
+            )
+        );
+        let c = ("src/C.elm", "module C exposing (..)\nimport Main\nx = 1\n");
+        assert_eq!(
+            err(&[c], "import C\nmain = MouseX\n").1,
+            "the modules `Main`, `C` import each other"
+        );
+
+        assert!(err(&[], "x = 1\nimport Foo.Bar\nmain = MouseX\n")
+            .1
+            .starts_with("unexpected token 'import'"));
+        assert_eq!(
+            err(&[], "import Foo.Bar\nmain = MouseX\n"),
+            (
+                "main.elm".to_owned(),
+                "can not find the module `Foo.Bar`, it should be in `src/Foo/Bar.elm`".to_owned(),
+                7,
+                14
+            )
+        );
+        assert_eq!(
+            err(
+                &[("src/A.elm", "module B exposing (..)\nx = 1\n")],
+                "import A\nmain = MouseX\n"
+            ),
+            (
+                "src/A.elm".to_owned(),
+                "this file is imported as the module `A`, but its header names `B`".to_owned(),
+                7,
+                8
+            )
+        );
+        assert_eq!(
+            err(
+                &[("src/A.elm", "module A exposing (x, y)\nx = 1\n")],
+                "import A\nmain = MouseX\n"
+            ),
+            (
+                "src/A.elm".to_owned(),
+                "`y` is exposed, but the module does not define it".to_owned(),
+                22,
+                23
+            )
+        );
+
+        // Only the exposed definitions can be used, qualified by the module they are in
+        assert_eq!(
+            err(
+                &[SHAPE],
+                "import Geo.Shape\nmain = lift1 (\\x. Geo.Shape.secret): MouseX\n"
+            )
+            .1,
+            "undefined variable `Geo.Shape.secret`"
+        );
+        assert_eq!(
+            err(
+                &[SHAPE],
+                "import Geo.Shape\nmain = lift1 (\\x. area x): MouseX\n"
+            )
+            .1,
+            "undefined variable `area`"
+        );
+        assert_eq!(
+            err(&[SHAPE], "main = lift1 (\\x. Geo.Shape.area x): MouseX\n").1,
+            "undefined variable `Geo.Shape.area`"
+        );
+
+        // Errors are reported in the module they are in
+        let bad = ("src/Bad.elm", "module Bad exposing (..)\nx = 1 + True\n");
+        assert_eq!(
+            err(&[bad], "import Bad\nmain = MouseX\n"),
+            (
+                "src/Bad.elm".to_owned(),
+                "expected `int` but this operand has type `bool`".to_owned(),
+                33,
+                37
+            )
+        );
+    }
+}
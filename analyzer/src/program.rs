@@ -0,0 +1,416 @@
+//! Turns a program into a single expression the typechecker and the evaluator understand. The
+//! definitions become nested `let`, ordered so that each one comes after the definitions it
+//! refers to, and the program evaluates to its `main`. The type declarations are bound around
+//! them, each one after the types it names.
+
+use crate::error::{TypeCheckError, TypeCheckErrorType};
+use rustelm_parser::ast::{
+    Atom, Decl, Expr, ExprKind, Located, Pattern, Program, SignalType, SimpleType, Span, Types,
+};
+use std::collections::HashMap;
+
+/// A top-level definition, and its signature if it has one.
+struct Definition<'a> {
+    name: &'a Located<String>,
+    signature: Option<&'a Located<Types>>,
+    expr: &'a Expr,
+    /// The other definitions it refers to, in the order they first appear.
+    uses: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mark {
+    Visiting,
+    Done,
+}
+
+/// The expression `program` stands for, and the span of the name of its `main`.
+pub fn lower(program: &Program) -> Result<(Expr, Span), TypeCheckError> {
+    let main = program.decls.iter().find_map(|decl| match decl {
+        Decl::Value(name, _) if name.node == "main" => Some(name.span),
+        _ => None,
+    });
+    let main = match main {
+        Some(main) => main,
+        None => {
+            let kind = TypeCheckErrorType::MissingMain;
+            return Err(TypeCheckError(kind, Span::default()));
+        }
+    };
+
+    let body = Located {
+        node: ExprKind::Const(Located {
+            node: Atom::Var("main".to_owned()),
+            span: main,
+        }),
+        span: main,
+    };
+    Ok((bind_all(program, body)?, main))
+}
+
+/// The definitions of `program` bound around `body`, each one after the definitions it uses.
+pub fn bind_all(program: &Program, body: Expr) -> Result<Expr, TypeCheckError> {
+    let (types, definitions) = declarations(program)?;
+    let index: HashMap<&str, usize> = definitions
+        .iter()
+        .enumerate()
+        .map(|(i, def)| (def.name.node.as_str(), i))
+        .collect();
+
+    // Order the definitions after the ones they use, keeping the written order otherwise
+    let mut marks = vec![None; definitions.len()];
+    let mut order = vec![];
+    for i in 0..definitions.len() {
+        visit(i, &definitions, &index, &mut marks, &mut vec![], &mut order)?;
+    }
+
+    let mut expr = body;
+    for &i in order.iter().rev() {
+        expr = bind(&definitions[i], expr);
+    }
+
+    let mut marks = vec![None; types.len()];
+    let mut order = vec![];
+    for i in 0..types.len() {
+        visit_type(i, &types, &mut marks, &mut vec![], &mut order)?;
+    }
+    for &i in order.iter().rev() {
+        expr = declare(types[i], expr);
+    }
+    Ok(expr)
+}
+
+/// `body` in scope of the type declaration or the type alias `decl`.
+pub fn declare(decl: &Decl, body: Expr) -> Expr {
+    let span = body.span;
+    let node = match decl {
+        Decl::Type(def) => ExprKind::TypeDef(def.clone(), Box::new(body)),
+        Decl::Alias(def) => ExprKind::TypeAlias(def.clone(), Box::new(body)),
+        _ => unreachable!("not a type declaration"),
+    };
+    Located { node, span }
+}
+
+/// The type declarations and the definitions of `program`, with each signature attached to the
+/// definition right after it.
+fn declarations(program: &Program) -> Result<(Vec<&Decl>, Vec<Definition<'_>>), TypeCheckError> {
+    let mut types = vec![];
+    let mut definitions: Vec<Definition> = vec![];
+    let mut signature: Option<(&Located<String>, &Located<Types>)> = None;
+    for decl in &program.decls {
+        if let Some((name, _)) = signature {
+            match decl {
+                Decl::Value(x, _) if x.node == name.node => (),
+                _ => {
+                    let kind = TypeCheckErrorType::LoneSignature(name.node.clone());
+                    return Err(TypeCheckError(kind, name.span));
+                }
+            }
+        }
+
+        match decl {
+            Decl::Type(_) | Decl::Alias(_) => types.push(decl),
+            Decl::Signature(name, ty) => signature = Some((name, ty)),
+            Decl::Value(name, expr) => {
+                if definitions.iter().any(|def| def.name.node == name.node) {
+                    let kind = TypeCheckErrorType::AlreadyDefined(name.node.clone());
+                    return Err(TypeCheckError(kind, name.span));
+                }
+                definitions.push(Definition {
+                    name,
+                    signature: signature.take().map(|(_, ty)| ty),
+                    expr,
+                    uses: vec![],
+                });
+            }
+        }
+    }
+    if let Some((name, _)) = signature {
+        let kind = TypeCheckErrorType::LoneSignature(name.node.clone());
+        return Err(TypeCheckError(kind, name.span));
+    }
+
+    let names: Vec<String> = definitions
+        .iter()
+        .map(|def| def.name.node.clone())
+        .collect();
+    for def in &mut definitions {
+        let mut uses = vec![];
+        free_names(def.expr, &names, &mut vec![], &mut uses);
+        def.uses = uses;
+    }
+    Ok((types, definitions))
+}
+
+/// Push `i` to `order` after the definitions it uses. A definition may only use itself, a cycle
+/// through several definitions is an error.
+fn visit(
+    i: usize,
+    definitions: &[Definition],
+    index: &HashMap<&str, usize>,
+    marks: &mut Vec<Option<Mark>>,
+    path: &mut Vec<usize>,
+    order: &mut Vec<usize>,
+) -> Result<(), TypeCheckError> {
+    match marks[i] {
+        Some(Mark::Done) => return Ok(()),
+        Some(Mark::Visiting) => {
+            let start = path.iter().position(|&j| j == i).unwrap();
+            let names = path[start..]
+                .iter()
+                .map(|&j| definitions[j].name.node.clone())
+                .collect();
+            let def = &definitions[i];
+            let kind = TypeCheckErrorType::MutualRecursion(names);
+            return Err(TypeCheckError(kind, def.name.span));
+        }
+        None => (),
+    }
+
+    marks[i] = Some(Mark::Visiting);
+    path.push(i);
+    let def = &definitions[i];
+    for name in &def.uses {
+        if *name != def.name.node {
+            visit(index[name.as_str()], definitions, index, marks, path, order)?;
+        }
+    }
+    path.pop();
+    marks[i] = Some(Mark::Done);
+    order.push(i);
+    Ok(())
+}
+
+/// Push the type declaration `i` to `order` after the ones it names. A type declared with `type`
+/// may refer to itself, but the declarations are checked one at a time, so any longer cycle is an
+/// error. A cycle made of aliases only is reported as such, they would never stop expanding.
+fn visit_type(
+    i: usize,
+    types: &[&Decl],
+    marks: &mut Vec<Option<Mark>>,
+    path: &mut Vec<usize>,
+    order: &mut Vec<usize>,
+) -> Result<(), TypeCheckError> {
+    let name = |decl: &Decl| match decl {
+        Decl::Type(def) => def.name.clone(),
+        Decl::Alias(def) => def.name.clone(),
+        _ => unreachable!("not a type declaration"),
+    };
+
+    match marks[i] {
+        Some(Mark::Done) => return Ok(()),
+        Some(Mark::Visiting) => {
+            let start = path.iter().position(|&j| j == i).unwrap();
+            let cycle = &path[start..];
+            let names = cycle.iter().map(|&j| name(types[j]).node).collect();
+            let kind = if cycle.iter().all(|&j| matches!(types[j], Decl::Alias(_))) {
+                TypeCheckErrorType::AliasCycle(names)
+            } else if cycle.len() > 1 {
+                TypeCheckErrorType::TypeCycle(names)
+            } else {
+                return Ok(());
+            };
+            return Err(TypeCheckError(kind, name(types[i]).span));
+        }
+        None => (),
+    }
+
+    marks[i] = Some(Mark::Visiting);
+    path.push(i);
+    let mut uses = vec![];
+    let params = match types[i] {
+        Decl::Type(def) => {
+            for constructor in &def.constructors {
+                for arg in &constructor.args {
+                    simple_names(&arg.node, &mut uses);
+                }
+            }
+            &def.params
+        }
+        Decl::Alias(def) => {
+            match &def.ty.node {
+                Types::Simple(ty) => simple_names(ty, &mut uses),
+                Types::Signal(ty) => signal_names(ty, &mut uses),
+            }
+            &def.params
+        }
+        _ => unreachable!("not a type declaration"),
+    };
+    for used in uses {
+        if params.iter().any(|param| param.node == used) {
+            continue;
+        }
+        if let Some(j) = types.iter().position(|decl| name(decl).node == used) {
+            visit_type(j, types, marks, path, order)?;
+        }
+    }
+    path.pop();
+    marks[i] = Some(Mark::Done);
+    order.push(i);
+    Ok(())
+}
+
+/// Push to `uses` the names of the types `ty` refers to.
+fn simple_names<'a>(ty: &'a SimpleType, uses: &mut Vec<&'a str>) {
+    match ty {
+        SimpleType::Abs(t1, t2) => {
+            simple_names(t1, uses);
+            simple_names(t2, uses);
+        }
+        SimpleType::Tuple(types) => types.iter().for_each(|ty| simple_names(ty, uses)),
+        SimpleType::Record(fields, _) => fields.iter().for_each(|(_, ty)| simple_names(ty, uses)),
+        SimpleType::List(ty) => simple_names(ty, uses),
+        SimpleType::Named(name, args) => {
+            uses.push(name);
+            args.iter().for_each(|ty| simple_names(ty, uses));
+        }
+        _ => (),
+    }
+}
+
+fn signal_names<'a>(ty: &'a SignalType, uses: &mut Vec<&'a str>) {
+    match ty {
+        SignalType::Signal(ty) => simple_names(ty, uses),
+        SignalType::Abs1(t, o) => {
+            simple_names(t, uses);
+            signal_names(o, uses);
+        }
+        SignalType::Abs2(o1, o2) => {
+            signal_names(o1, uses);
+            signal_names(o2, uses);
+        }
+        SignalType::Named(name, args) => {
+            uses.push(name);
+            args.iter().for_each(|ty| simple_names(ty, uses));
+        }
+    }
+}
+
+/// `let x = e in body` for the definition `x = e`, a `let rec` if `e` refers to `x`.
+fn bind(def: &Definition, body: Expr) -> Expr {
+    let name = def.name;
+    let span = Span::new(name.span.start, def.expr.span.end);
+    let e = Box::new(def.expr.clone());
+    let node = if def.uses.contains(&name.node) {
+        ExprKind::LetRec(name.clone(), def.signature.cloned(), e, Box::new(body))
+    } else {
+        let var = Located {
+            node: Pattern::Var(name.node.clone()),
+            span: name.span,
+        };
+        let pattern = match def.signature {
+            Some(ty) => Located {
+                node: Pattern::Annotated(Box::new(var), ty.clone()),
+                span: Span::new(name.span.start, ty.span.end),
+            },
+            None => var,
+        };
+        ExprKind::Let(pattern, e, Box::new(body))
+    };
+    Located { node, span }
+}
+
+/// Push to `uses` the names of `names` that `expr` refers to, except those shadowed by the
+/// names in `bound` or by its own binders.
+fn free_names(expr: &Expr, names: &[String], bound: &mut Vec<String>, uses: &mut Vec<String>) {
+    let visit = |name: &str, bound: &[String], uses: &mut Vec<String>| {
+        let name = name.to_owned();
+        if names.contains(&name) && !bound.contains(&name) && !uses.contains(&name) {
+            uses.push(name);
+        }
+    };
+
+    match &expr.node {
+        ExprKind::Const(atom) => {
+            if let Atom::Var(name) = &atom.node {
+                visit(name, bound, uses)
+            }
+        }
+        ExprKind::Abs(param, _, e) => {
+            let depth = bound.len();
+            if let Atom::Var(name) = &param.node {
+                bound.push(name.clone());
+            }
+            free_names(e, names, bound, uses);
+            bound.truncate(depth);
+        }
+        ExprKind::Lambda(..) => unreachable!("removed by desugaring"),
+        ExprKind::App(e1, e2) | ExprKind::BinOp(e1, _, e2) => {
+            free_names(e1, names, bound, uses);
+            free_names(e2, names, bound, uses);
+        }
+        ExprKind::UnOp(_, e) | ExprKind::Async(e) | ExprKind::Field(e, _) => {
+            free_names(e, names, bound, uses)
+        }
+        ExprKind::TypeDef(_, e) | ExprKind::TypeAlias(_, e) => free_names(e, names, bound, uses),
+        ExprKind::If(e1, e2, e3) | ExprKind::Foldp(e1, e2, e3) => {
+            free_names(e1, names, bound, uses);
+            free_names(e2, names, bound, uses);
+            free_names(e3, names, bound, uses);
+        }
+        ExprKind::Let(pattern, e1, e2) => {
+            free_names(e1, names, bound, uses);
+            let depth = bound.len();
+            pattern_names(&pattern.node, bound);
+            free_names(e2, names, bound, uses);
+            bound.truncate(depth);
+        }
+        ExprKind::LetRec(name, _, e1, e2) => {
+            bound.push(name.node.clone());
+            free_names(e1, names, bound, uses);
+            free_names(e2, names, bound, uses);
+            bound.pop();
+        }
+        ExprKind::Tuple(es) | ExprKind::List(es) => {
+            for e in es {
+                free_names(e, names, bound, uses);
+            }
+        }
+        ExprKind::Record(fields) => {
+            for (_, e) in fields {
+                free_names(e, names, bound, uses);
+            }
+        }
+        ExprKind::Update(e, fields) => {
+            free_names(e, names, bound, uses);
+            for (_, e) in fields {
+                free_names(e, names, bound, uses);
+            }
+        }
+        ExprKind::Case(e, branches) => {
+            free_names(e, names, bound, uses);
+            for (pattern, e) in branches {
+                let depth = bound.len();
+                pattern_names(&pattern.node, bound);
+                free_names(e, names, bound, uses);
+                bound.truncate(depth);
+            }
+        }
+        ExprKind::Lift(_, e, args) => {
+            free_names(e, names, bound, uses);
+            for arg in args {
+                if let Atom::Var(name) = &arg.node {
+                    visit(name, bound, uses)
+                }
+            }
+        }
+    }
+}
+
+/// Push the names `pattern` binds to `bound`.
+fn pattern_names(pattern: &Pattern, bound: &mut Vec<String>) {
+    match pattern {
+        Pattern::Var(name) => bound.push(name.clone()),
+        Pattern::Wildcard | Pattern::Literal(_) => (),
+        Pattern::Tuple(patterns) | Pattern::List(patterns) | Pattern::Constructor(_, patterns) => {
+            for pattern in patterns {
+                pattern_names(&pattern.node, bound);
+            }
+        }
+        Pattern::Cons(head, tail) => {
+            pattern_names(&head.node, bound);
+            pattern_names(&tail.node, bound);
+        }
+        Pattern::Annotated(pattern, _) => pattern_names(&pattern.node, bound),
+    }
+}
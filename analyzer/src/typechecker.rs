@@ -1,16 +1,24 @@
-use crate::error::{TypeCheckError, TypeCheckErrorType};
-use rustelm_parser::ast::SimpleType::{Int, Unit};
+use crate::error::{Subject, TypeCheckError, TypeCheckErrorType};
+use crate::exhaustive;
+use crate::program;
+use crate::types::{substitute, Scheme, Substitution, Type};
 use rustelm_parser::{
     ast,
-    ast::SignalType::{Abs1, Abs2},
-    ast::{Atom, Expr, SignalType, SimpleType, Types},
+    ast::{
+        Atom, BinOp, ExprKind, Located, Pattern, SignalType, SimpleType, Span, TypeAlias, TypeDef,
+        Types, UnOp,
+    },
 };
+use std::collections::HashMap;
 
-type Context = im::HashMap<String, ast::Types>;
+pub type Context = im::HashMap<String, ast::Types>;
+
+/// The types of the names in scope during inference, possibly generalized by `let`.
+type Env = im::HashMap<String, Scheme>;
 
 // Some Elm input signals and signal constructors
 lazy_static! {
-    static ref INPUTS: Context = {
+    pub static ref INPUTS: Context = {
         use SignalType::*;
         use SimpleType::*;
         im::hashmap! {
@@ -19,204 +27,1182 @@ lazy_static! {
             "MouseClicks".to_owned() => Types::Signal(Signal(Unit)),
         }
     };
+
+    /// The built-in functions, generalized over their type variables.
+    pub static ref PRELUDE: Context = {
+        use SimpleType::*;
+        let var = |name: &str| Var(name.to_owned());
+        let list = |ty| List(Box::new(ty));
+        let arrow = |param, ret| Abs(Box::new(param), Box::new(ret));
+        let (a, b) = (var("a"), var("b"));
+        im::hashmap! {
+            "map".to_owned() => Types::Simple(arrow(
+                arrow(a.clone(), b.clone()),
+                arrow(list(a.clone()), list(b.clone())),
+            )),
+            "foldl".to_owned() => Types::Simple(arrow(
+                arrow(a.clone(), arrow(b.clone(), b.clone())),
+                arrow(b.clone(), arrow(list(a.clone()), b)),
+            )),
+            "filter".to_owned() => Types::Simple(arrow(
+                arrow(a.clone(), Bool),
+                arrow(list(a.clone()), list(a.clone())),
+            )),
+            "length".to_owned() => Types::Simple(arrow(list(a.clone()), Int)),
+            "head".to_owned() => Types::Simple(arrow(list(a.clone()), a.clone())),
+            "tail".to_owned() => Types::Simple(arrow(list(a.clone()), list(a))),
+            "show".to_owned() => Types::Simple(arrow(Int, String)),
+            "toFloat".to_owned() => Types::Simple(arrow(Int, Float)),
+            "round".to_owned() => Types::Simple(arrow(Float, Int)),
+        }
+    };
 }
 
 /// The main entry to do typechecking. We type checking on root, and then recursively type
 /// checking children.
-pub fn typecheck_root(root: Box<ast::Expr>) -> Result<ast::Types, TypeCheckError> {
+pub fn typecheck_root(root: &ast::Expr) -> Result<ast::Types, TypeCheckError> {
     // let env: Context = INPUTS.clone();
     get_type_of(&INPUTS, root)
 }
 
-fn get_type_from_ctx(env: &Context, name: String) -> Result<ast::Types, TypeCheckError> {
-    match env.get(name.as_str()) {
-        None => Err(TypeCheckError(TypeCheckErrorType::UndefinedName)),
-        Some(ty) => Ok(ty.clone()),
+/// Typecheck a whole program, its `main` must be a signal. Returns the expression the program
+/// stands for, its definitions bound by nested `let` in the order they depend on each other.
+pub fn typecheck_program(program: &ast::Program) -> Result<ast::Expr, TypeCheckError> {
+    let (expr, main) = program::lower(program)?;
+    typecheck_main(&INPUTS, &expr, main)?;
+    Ok(expr)
+}
+
+/// Typecheck `expr`, a program lowered by `program::lower`, in `env`. Its `main`, whose name is
+/// at `main`, must be a signal.
+pub(crate) fn typecheck_main(
+    env: &Context,
+    expr: &ast::Expr,
+    main: Span,
+) -> Result<(), TypeCheckError> {
+    match get_type_of(env, expr)? {
+        Types::Signal(_) => Ok(()),
+        ty => Err(TypeCheckError(
+            TypeCheckErrorType::MainNotSignal(Type::from_ast(&ty)),
+            main,
+        )),
     }
 }
 
-fn get_type_of(env: &Context, term: Box<ast::Expr>) -> Result<ast::Types, TypeCheckError> {
-    use ast::SimpleType::*;
-    use ast::Types::*;
+/// The types of `names`, some of the definitions `expr` is made of, nested `let` and type
+/// declarations around a body that is not typechecked.
+pub(crate) fn typecheck_definitions(
+    env: &Context,
+    expr: &ast::Expr,
+    names: &[&Located<String>],
+) -> Result<Context, TypeCheckError> {
+    let mut checker = Checker::default();
+    let env = checker.environment(env);
+    let env = checker.infer_definitions(&env, expr)?;
+    checker.check_stratification()?;
 
-    match *term {
-        Expr::Const(atom) => match atom {
-            Atom::Unit => Ok(Simple(Unit)),
-            Atom::Num(_) => Ok(Simple(Int)),
-            Atom::Var(name) | Atom::Signal(name) => get_type_from_ctx(env, name),
-        },
-        Expr::Abs(atom, param_ty, expr) => match atom {
-            Atom::Var(name) | Atom::Signal(name) => {
-                // Add the new binding to the environment, then get the type of the expression in
-                // the new environment.
-                let mut new_env = env.clone();
-                new_env.insert(name, param_ty.clone());
-                let return_ty = get_type_of(new_env.as_ref(), expr)?;
-
-                // We need to manually check the lambda creates a "o -> t" type.
-                match return_ty {
-                    Simple(sim_ty) => match param_ty {
-                        // t -> t'
-                        Simple(sim_ty0) => Ok(Simple(Abs(Box::new(sim_ty0), Box::new(sim_ty)))),
-                        // o -> t, which should be prohibited
-                        Signal(_) => Err(TypeCheckError(TypeCheckErrorType::InvalidParamType)),
-                    },
-                    Signal(sig_ty) => match param_ty {
-                        // t -> o
-                        Simple(sim_ty0) => Ok(Signal(Abs1(sim_ty0, Box::new(sig_ty)))),
-                        // o -> o
-                        Signal(sig_ty0) => Ok(Signal(Abs2(Box::new(sig_ty0), Box::new(sig_ty)))),
-                    },
+    let mut types = Context::new();
+    for name in names {
+        let ty = checker
+            .subst
+            .resolve(&env[&name.node].ty)
+            .rename(&mut vec![]);
+        // The importing modules see the types the aliases stand for
+        match ty.expand().to_ast() {
+            Some(ty) => types.insert(name.node.clone(), ty),
+            None => {
+                let kind = TypeCheckErrorType::ExpectSimple(ty);
+                return Err(TypeCheckError(kind, name.span));
+            }
+        };
+    }
+    Ok(types)
+}
+
+/// Infer the type of `term`, Hindley-Milner style. Lambda parameters may be left unannotated,
+/// and `let` bindings are generalized. Annotations are checked like any other constraint.
+///
+/// FElm keeps simple and signal types apart: a function taking a signal must return a signal,
+/// and signals only carry simple values. Type variables may stand for either while inference
+/// runs, the stratification is checked once they are solved. Type variables that are still
+/// unknown by then stand for simple types.
+fn get_type_of(env: &Context, term: &ast::Expr) -> Result<ast::Types, TypeCheckError> {
+    let mut checker = Checker::default();
+    let env = checker.environment(env);
+
+    let ty = checker.infer(&env, term)?;
+    checker.check_stratification()?;
+
+    let ty = checker.subst.resolve(&ty).rename(&mut vec![]);
+    match ty.to_ast() {
+        Some(ty) => Ok(ty),
+        None => Err(TypeCheckError(
+            TypeCheckErrorType::ExpectSimple(ty),
+            term.span,
+        )),
+    }
+}
+
+#[derive(Default)]
+struct Checker {
+    subst: Substitution,
+    /// The parameter and return types of every function defined or applied, which must not
+    /// take a signal and return a simple type, `o -> t`.
+    arrows: Vec<(Type, Type, Span)>,
+    /// Types that must be simple, e.g. the values carried by signals.
+    simple: Vec<(Type, Span)>,
+    /// The declared types, and their constructors.
+    types: HashMap<String, TypeInfo>,
+    constructors: HashMap<String, ConstructorInfo>,
+    /// The type aliases, and the types they stand for.
+    aliases: HashMap<String, AliasInfo>,
+}
+
+/// A declared type, the number of parameters it takes and the names of its constructors.
+struct TypeInfo {
+    params: usize,
+    constructors: Vec<String>,
+}
+
+/// A type alias, the type variables standing for its parameters in the type it stands for.
+struct AliasInfo {
+    params: Vec<usize>,
+    ty: Type,
+}
+
+/// A constructor, the type it belongs to and the type of the function building values with it,
+/// `a -> Maybe a`.
+struct ConstructorInfo {
+    type_name: String,
+    scheme: Scheme,
+    arity: usize,
+}
+
+impl Checker {
+    /// Build an error, with the type variables of its types solved and named.
+    fn error(&self, span: Span, kind: TypeCheckErrorType) -> TypeCheckError {
+        use TypeCheckErrorType::*;
+
+        let mut names = vec![];
+        let mut name = |ty: Type| self.subst.resolve(&ty).rename(&mut names);
+        let kind = match kind {
+            TypeMissMatch {
+                subject,
+                expected,
+                found,
+            } => TypeMissMatch {
+                subject,
+                expected: name(expected),
+                found: name(found),
+            },
+            InvalidParamType { param, ret } => InvalidParamType {
+                param: name(param),
+                ret: name(ret),
+            },
+            AnnotationMismatch { annotated, found } => AnnotationMismatch {
+                annotated: name(annotated),
+                found: name(found),
+            },
+            ExpectSignal(ty) => ExpectSignal(name(ty)),
+            ExpectSimple(ty) => ExpectSimple(name(ty)),
+            ExpectFunction(ty) => ExpectFunction(name(ty)),
+            MissingField { field, found } => MissingField {
+                field,
+                found: name(found),
+            },
+            kind => kind,
+        };
+
+        TypeCheckError(kind, span)
+    }
+
+    /// The environment made of the names of `context` and the built-in functions. Like the
+    /// built-in functions, the names of `context` are generalized, so that the definitions of
+    /// other modules can be polymorphic.
+    fn environment(&mut self, context: &Context) -> Env {
+        let mut env = Env::new();
+        for (name, ty) in context.iter().chain(PRELUDE.iter()) {
+            let scheme = self.generalize_builtin(ty);
+            env.insert(name.clone(), scheme);
+        }
+        env
+    }
+
+    /// The environment of the body of the definitions `term` is made of, nested `let` and type
+    /// declarations. The body itself is not typechecked.
+    fn infer_definitions(&mut self, env: &Env, term: &ast::Expr) -> Result<Env, TypeCheckError> {
+        match &term.node {
+            ExprKind::Let(pattern, e1, e2) => {
+                let env = self.bind_let(env, pattern, e1)?;
+                self.infer_definitions(&env, e2)
+            }
+            ExprKind::LetRec(name, ty, e1, e2) => {
+                let env = self.bind_let_rec(env, name, ty, e1)?;
+                self.infer_definitions(&env, e2)
+            }
+            ExprKind::TypeDef(def, e) => {
+                let env = self.bind_type(env, def)?;
+                self.infer_definitions(&env, e)
+            }
+            ExprKind::TypeAlias(def, e) => {
+                self.alias(def)?;
+                self.infer_definitions(env, e)
+            }
+            _ => Ok(env.clone()),
+        }
+    }
+
+    /// The environment of the body of `let pattern = e1`, its bindings generalized.
+    fn bind_let(
+        &mut self,
+        env: &Env,
+        pattern: &Located<Pattern>,
+        e1: &ast::Expr,
+    ) -> Result<Env, TypeCheckError> {
+        let ty = self.infer(env, e1)?;
+
+        let mut bindings = vec![];
+        let expected = self.pattern_type(pattern, &mut bindings)?;
+        match &pattern.node {
+            Pattern::Annotated(_, annotation) => self.annotated(annotation.span, expected, ty)?,
+            _ => self.unify(e1.span, Subject::Expression, expected, ty)?,
+        }
+        self.check_patterns(pattern.span, &[pattern])?;
+
+        let mut new_env = env.clone();
+        for (name, ty) in bindings {
+            let scheme = self.subst.generalize(env.values(), &ty);
+            new_env.insert(name, scheme);
+        }
+        Ok(new_env)
+    }
+
+    /// The environment of the body of `let rec name: ty = e1`.
+    fn bind_let_rec(
+        &mut self,
+        env: &Env,
+        name: &Located<String>,
+        ty: &Option<Located<Types>>,
+        e1: &ast::Expr,
+    ) -> Result<Env, TypeCheckError> {
+        // Only simple functions may be recursive, so that the signal graph stays finite.
+        // Their type is given, it is not inferred.
+        let (ty, span) = match ty {
+            Some(ty) => {
+                let checked =
+                    self.check_declared(&Type::from_ast(&ty.node), &[], ty.span, false)?;
+                if checked.is_signal() {
+                    let kind = TypeCheckErrorType::SignalRecursion(name.node.clone());
+                    return Err(TypeCheckError(kind, ty.span));
                 }
+                (checked, ty.span)
             }
-            _ => Err(TypeCheckError(TypeCheckErrorType::ExpectIdentifier)),
-        },
-        Expr::App(e1, e2) => {
-            let arg_ty = get_type_of(env, e2)?;
-
-            match get_type_of(env, e1)? {
-                Simple(ty) => match ty {
-                    Abs(sim_ty, ty2) => {
-                        if Simple(*sim_ty) == arg_ty {
-                            Ok(Simple(*ty2))
-                        } else {
-                            Err(TypeCheckError(TypeCheckErrorType::TypeMissMatch))
-                        }
-                    }
-                    _ => Err(TypeCheckError(TypeCheckErrorType::InvalidParamType)),
+            None => {
+                let kind = TypeCheckErrorType::MissingAnnotation(name.node.clone());
+                return Err(TypeCheckError(kind, name.span));
+            }
+        };
+        if !matches!(e1.node, ExprKind::Abs(..)) {
+            let kind = TypeCheckErrorType::RecursiveValue(name.node.clone());
+            return Err(TypeCheckError(kind, e1.span));
+        }
+
+        let mut new_env = env.clone();
+        new_env.insert(name.node.clone(), Scheme::mono(ty.clone()));
+        let found = self.infer(&new_env, e1)?;
+        self.annotated(span, ty, found)?;
+        Ok(new_env)
+    }
+
+    /// Check that a value of type `found` matches its annotation, of type `annotated` and at
+    /// `span`.
+    fn annotated(
+        &mut self,
+        span: Span,
+        annotated: Type,
+        found: Type,
+    ) -> Result<(), TypeCheckError> {
+        if self.subst.unify(&annotated, &found) {
+            Ok(())
+        } else {
+            let kind = TypeCheckErrorType::AnnotationMismatch { annotated, found };
+            Err(self.error(span, kind))
+        }
+    }
+
+    /// The environment of the body of a type declaration, with its constructors.
+    fn bind_type(&mut self, env: &Env, def: &TypeDef) -> Result<Env, TypeCheckError> {
+        self.declare(def)?;
+
+        let mut new_env = env.clone();
+        for constructor in &def.constructors {
+            let name = &constructor.name.node;
+            new_env.insert(name.clone(), self.constructors[name].scheme.clone());
+        }
+        Ok(new_env)
+    }
+
+    /// The type of a built-in function, generalized over the type variables it mentions.
+    fn generalize_builtin(&mut self, ty: &Types) -> Scheme {
+        let mut vars: Vec<(String, Type)> = vec![];
+        let subst = &mut self.subst;
+        let ty = Type::from_ast_with(ty, &mut |name| match vars
+            .iter()
+            .find(|(other, _)| other == name)
+        {
+            Some((_, var)) => var.clone(),
+            None => {
+                let var = subst.fresh();
+                vars.push((name.to_owned(), var.clone()));
+                var
+            }
+        });
+
+        let vars = vars
+            .into_iter()
+            .map(|(_, var)| match var {
+                Type::Var(v) => v,
+                _ => unreachable!(),
+            })
+            .collect();
+        Scheme { vars, ty }
+    }
+
+    /// Check that `term`, playing the part of `subject` in its parent, has the `expected` type.
+    fn expect_type(
+        &mut self,
+        env: &Env,
+        term: &ast::Expr,
+        subject: Subject,
+        expected: Type,
+    ) -> Result<(), TypeCheckError> {
+        let found = self.infer(env, term)?;
+        self.unify(term.span, subject, expected, found)
+    }
+
+    fn unify(
+        &mut self,
+        span: Span,
+        subject: Subject,
+        expected: Type,
+        found: Type,
+    ) -> Result<(), TypeCheckError> {
+        if self.subst.unify(&expected, &found) {
+            Ok(())
+        } else {
+            Err(self.error(
+                span,
+                TypeCheckErrorType::TypeMissMatch {
+                    subject,
+                    expected,
+                    found,
                 },
-                Signal(ty) => match ty {
-                    Abs1(sim_ty, sig_ty) => {
-                        if Simple(sim_ty) == arg_ty {
-                            Ok(Signal(*sig_ty))
-                        } else {
-                            Err(TypeCheckError(TypeCheckErrorType::TypeMissMatch))
-                        }
-                    }
-                    Abs2(sig_ty1, sig_ty2) => {
-                        if Signal(*sig_ty1) == arg_ty {
-                            Ok(Signal(*sig_ty2))
-                        } else {
-                            Err(TypeCheckError(TypeCheckErrorType::TypeMissMatch))
-                        }
+            ))
+        }
+    }
+
+    /// Check that `term` is a signal, returning the type of its values.
+    fn expect_signal(&mut self, env: &Env, term: &ast::Expr) -> Result<Type, TypeCheckError> {
+        let ty = self.infer(env, term)?;
+        self.signal_of(term.span, ty)
+    }
+
+    fn signal_of(&mut self, span: Span, ty: Type) -> Result<Type, TypeCheckError> {
+        let value = self.subst.fresh();
+        if self.subst.unify(&ty, &Type::signal(value.clone())) {
+            Ok(value)
+        } else {
+            Err(self.error(span, TypeCheckErrorType::ExpectSignal(ty)))
+        }
+    }
+
+    /// The type of the values `pattern` matches. The names it binds are added to `bindings`,
+    /// along with their types.
+    fn pattern_type(
+        &mut self,
+        pattern: &Located<Pattern>,
+        bindings: &mut Vec<(String, Type)>,
+    ) -> Result<Type, TypeCheckError> {
+        match &pattern.node {
+            Pattern::Var(name) => {
+                if bindings.iter().any(|(other, _)| other == name) {
+                    return Err(TypeCheckError(
+                        TypeCheckErrorType::DuplicateName(name.clone()),
+                        pattern.span,
+                    ));
+                }
+
+                let ty = self.subst.fresh();
+                bindings.push((name.clone(), ty.clone()));
+                Ok(ty)
+            }
+            Pattern::Wildcard => Ok(self.subst.fresh()),
+            Pattern::Literal(Atom::Unit) => Ok(Type::Unit),
+            Pattern::Literal(Atom::Num(_)) => Ok(Type::Int),
+            Pattern::Literal(Atom::Bool(_)) => Ok(Type::Bool),
+            Pattern::Literal(Atom::Str(_)) => Ok(Type::String),
+            Pattern::Literal(_) => unreachable!(),
+            Pattern::Tuple(patterns) => {
+                let mut types = vec![];
+                for pattern in patterns {
+                    types.push(self.pattern_type(pattern, bindings)?);
+                }
+                Ok(Type::Tuple(types))
+            }
+            Pattern::List(patterns) => {
+                let elem = self.subst.fresh();
+                for pattern in patterns {
+                    let found = self.pattern_type(pattern, bindings)?;
+                    self.unify(pattern.span, Subject::Pattern, elem.clone(), found)?;
+                }
+                Ok(Type::list(elem))
+            }
+            Pattern::Cons(head, tail) => {
+                let elem = self.pattern_type(head, bindings)?;
+                let found = self.pattern_type(tail, bindings)?;
+                self.unify(tail.span, Subject::Pattern, Type::list(elem.clone()), found)?;
+                Ok(Type::list(elem))
+            }
+            Pattern::Constructor(name, args) => {
+                let (scheme, arity) = match self.constructors.get(name) {
+                    Some(info) => (info.scheme.clone(), info.arity),
+                    None => {
+                        return Err(TypeCheckError(
+                            TypeCheckErrorType::UndefinedConstructor(name.clone()),
+                            pattern.span,
+                        ))
                     }
-                    _ => Err(TypeCheckError(TypeCheckErrorType::InvalidParamType)),
-                },
+                };
+                if arity != args.len() {
+                    return Err(TypeCheckError(
+                        TypeCheckErrorType::ConstructorArity {
+                            name: name.clone(),
+                            expected: arity,
+                            found: args.len(),
+                        },
+                        pattern.span,
+                    ));
+                }
+
+                // Match each argument against a parameter of the constructor
+                let mut ty = self.subst.instantiate(&scheme);
+                for arg in args {
+                    let (param, ret) = match ty {
+                        Type::Arrow(param, ret) => (*param, *ret),
+                        _ => unreachable!(),
+                    };
+                    let found = self.pattern_type(arg, bindings)?;
+                    self.unify(arg.span, Subject::Pattern, param, found)?;
+                    ty = ret;
+                }
+                Ok(ty)
             }
+            Pattern::Annotated(inner, ty) => {
+                let expected =
+                    self.check_declared(&Type::from_ast(&ty.node), &[], ty.span, false)?;
+                let found = self.pattern_type(inner, bindings)?;
+                self.unify(inner.span, Subject::Pattern, expected.clone(), found)?;
+                Ok(expected)
+            }
+        }
+    }
+
+    /// Check that `patterns`, matched in order, match every value and that each of them matches
+    /// some value the ones before it do not. `span` is where the matching happens.
+    fn check_patterns(
+        &self,
+        span: Span,
+        patterns: &[&Located<Pattern>],
+    ) -> Result<(), TypeCheckError> {
+        let siblings = |ctor: &str| {
+            let type_name = &self.constructors[ctor].type_name;
+            self.types[type_name]
+                .constructors
+                .iter()
+                .map(|name| (name.clone(), self.constructors[name].arity))
+                .collect()
+        };
+        let nodes: Vec<_> = patterns.iter().map(|pattern| &pattern.node).collect();
+
+        if let Some(missing) = exhaustive::missing(&nodes, &siblings) {
+            return Err(TypeCheckError(
+                TypeCheckErrorType::NonExhaustive(missing),
+                span,
+            ));
+        }
+        if let Some(i) = exhaustive::redundant(&nodes, &siblings) {
+            return Err(TypeCheckError(
+                TypeCheckErrorType::RedundantPattern,
+                patterns[i].span,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Add a declared type and its constructors. The constructors are functions generalized
+    /// over the parameters of the type.
+    fn declare(&mut self, def: &TypeDef) -> Result<(), TypeCheckError> {
+        let already_defined = |name: &Located<String>| {
+            TypeCheckError(
+                TypeCheckErrorType::AlreadyDefined(name.node.clone()),
+                name.span,
+            )
+        };
+
+        if self.types.contains_key(&def.name.node) || self.aliases.contains_key(&def.name.node) {
+            return Err(already_defined(&def.name));
+        }
+
+        let mut params: Vec<(String, Type)> = vec![];
+        for param in &def.params {
+            if params.iter().any(|(name, _)| *name == param.node) {
+                return Err(TypeCheckError(
+                    TypeCheckErrorType::DuplicateName(param.node.clone()),
+                    param.span,
+                ));
+            }
+            params.push((param.node.clone(), self.subst.fresh()));
+        }
+
+        // Declared first, so that the type may refer to itself
+        self.types.insert(
+            def.name.node.clone(),
+            TypeInfo {
+                params: params.len(),
+                constructors: def
+                    .constructors
+                    .iter()
+                    .map(|c| c.name.node.clone())
+                    .collect(),
+            },
+        );
+
+        let vars = params
+            .iter()
+            .map(|(_, var)| match var {
+                Type::Var(v) => *v,
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        let ty = Type::Named(
+            def.name.node.clone(),
+            params.iter().map(|(_, var)| var.clone()).collect(),
+        );
+
+        for constructor in &def.constructors {
+            let name = &constructor.name;
+            if !name.node.starts_with(char::is_uppercase) {
+                return Err(TypeCheckError(
+                    TypeCheckErrorType::LowercaseConstructor(name.node.clone()),
+                    name.span,
+                ));
+            }
+            if self.constructors.contains_key(&name.node) {
+                return Err(already_defined(name));
+            }
+
+            let mut ctor_ty = ty.clone();
+            for arg in constructor.args.iter().rev() {
+                let arg_ty =
+                    self.check_declared(&Type::from_simple(&arg.node), &params, arg.span, true)?;
+                ctor_ty = Type::arrow(arg_ty, ctor_ty);
+            }
+
+            self.constructors.insert(
+                name.node.clone(),
+                ConstructorInfo {
+                    type_name: def.name.node.clone(),
+                    scheme: Scheme {
+                        vars: vars.clone(),
+                        ty: ctor_ty,
+                    },
+                    arity: constructor.args.len(),
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Add a type alias, the type it stands for is checked once here and expanded wherever the
+    /// alias is used.
+    fn alias(&mut self, def: &TypeAlias) -> Result<(), TypeCheckError> {
+        let name = &def.name;
+        if self.types.contains_key(&name.node) || self.aliases.contains_key(&name.node) {
+            let kind = TypeCheckErrorType::AlreadyDefined(name.node.clone());
+            return Err(TypeCheckError(kind, name.span));
         }
-        Expr::BinOp(e1, _, e2) => {
-            if Simple(Int) == get_type_of(env, e1)? && Simple(Int) == get_type_of(env, e2)? {
-                Ok(Simple(Int))
-            } else {
-                Err(TypeCheckError(TypeCheckErrorType::TypeMissMatch))
+
+        let mut params: Vec<(String, Type)> = vec![];
+        for param in &def.params {
+            if params.iter().any(|(name, _)| *name == param.node) {
+                return Err(TypeCheckError(
+                    TypeCheckErrorType::DuplicateName(param.node.clone()),
+                    param.span,
+                ));
             }
+            params.push((param.node.clone(), self.subst.fresh()));
+        }
+
+        let ty = Type::from_ast(&def.ty.node);
+        if mentions(&ty, &name.node) {
+            let kind = TypeCheckErrorType::AliasCycle(vec![name.node.clone()]);
+            return Err(TypeCheckError(kind, name.span));
         }
-        Expr::If(e1, e2, e3) => {
-            if Simple(Int) == get_type_of(env, e1)? {
-                let ty = get_type_of(env, e2)?;
-                if ty == get_type_of(env, e3)? {
-                    Ok(ty)
-                } else {
-                    Err(TypeCheckError(TypeCheckErrorType::TypeMissMatch))
+        let ty = self.check_declared(&ty, &params, def.ty.span, false)?;
+
+        let params = params
+            .into_iter()
+            .map(|(_, var)| match var {
+                Type::Var(v) => v,
+                _ => unreachable!(),
+            })
+            .collect();
+        self.aliases
+            .insert(name.node.clone(), AliasInfo { params, ty });
+        Ok(())
+    }
+
+    /// Check that the declared types named in `ty`, written at `span`, exist and are given the
+    /// right number of arguments, and expand the aliases. A name in `params` stands for the
+    /// matching type instead. Inside a `simple` type, an alias may not stand for a signal type,
+    /// and neither may the parameter of a function returning a simple type.
+    fn check_declared(
+        &self,
+        ty: &Type,
+        params: &[(String, Type)],
+        span: Span,
+        simple: bool,
+    ) -> Result<Type, TypeCheckError> {
+        if let Type::Named(name, args) = ty {
+            let param = params.iter().find(|(param, _)| param == name);
+            if let (Some((_, param)), true) = (param, args.is_empty()) {
+                return Ok(param.clone());
+            }
+
+            if let Some(alias) = self.aliases.get(name) {
+                if alias.params.len() != args.len() {
+                    return Err(TypeCheckError(
+                        TypeCheckErrorType::TypeArity {
+                            name: name.clone(),
+                            expected: alias.params.len(),
+                            found: args.len(),
+                        },
+                        span,
+                    ));
+                }
+                let args = args
+                    .iter()
+                    .map(|arg| self.check_declared(arg, params, span, true))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let map: Vec<_> = alias.params.iter().copied().zip(args.clone()).collect();
+                let expanded = substitute(&alias.ty, &map);
+                if simple && expanded.has_signal() {
+                    let kind = TypeCheckErrorType::SignalAlias(name.clone());
+                    return Err(TypeCheckError(kind, span));
+                }
+                let alias = Type::Named(name.clone(), args);
+                return Ok(Type::Alias(Box::new(alias), Box::new(expanded)));
+            }
+
+            match self.types.get(name) {
+                None => {
+                    return Err(TypeCheckError(
+                        TypeCheckErrorType::UndefinedType(name.clone()),
+                        span,
+                    ))
+                }
+                Some(info) if info.params != args.len() => {
+                    return Err(TypeCheckError(
+                        TypeCheckErrorType::TypeArity {
+                            name: name.clone(),
+                            expected: info.params,
+                            found: args.len(),
+                        },
+                        span,
+                    ))
                 }
-            } else {
-                Err(TypeCheckError(TypeCheckErrorType::TypeMissMatch))
+                Some(_) => {}
             }
         }
-        Expr::Let(atom, e1, e2) => match atom {
-            Atom::Var(name) => {
-                // Add the new binding to the environment, then get the type of the expression in
-                // the new environment.
-                let mut new_env = env.clone();
-                new_env.insert(name, get_type_of(env, e1)?);
-                get_type_of(new_env.as_ref(), e2)
+
+        // Only the sides of a function may be signals
+        let inner = simple || !matches!(ty, Type::Arrow(..));
+        let mut error = None;
+        let ty = ty.map(|ty| match self.check_declared(ty, params, span, inner) {
+            Ok(ty) => ty,
+            Err(e) => {
+                error.get_or_insert(e);
+                ty.clone()
             }
-            _ => Err(TypeCheckError(TypeCheckErrorType::ExpectIdentifier)),
-        },
-        // TODO: Fix this part
-        Expr::Lift(n, expr, vec) => {
-            // First we construct a vector of all the argument types
-            let mut types = vec![];
-            for atom in vec {
-                let ty_i = match atom {
-                    Atom::Var(input) => get_type_from_ctx(env, input),
-                    _ => Err(TypeCheckError(TypeCheckErrorType::ExpectIdentifier)),
-                }?;
-
-                // and make sure it is simple type
-                match ty_i {
-                    Simple(_) => unreachable!(),
-                    Signal(sig_ty) => match sig_ty {
-                        SignalType::Signal(s) => types.push(s),
-                        _ => unreachable!(),
+        });
+        if let (Type::Arrow(param, ret), None) = (&ty, &error) {
+            if param.is_signal() && !ret.is_signal() {
+                let kind = TypeCheckErrorType::InvalidParamType {
+                    param: (**param).clone(),
+                    ret: (**ret).clone(),
+                };
+                return Err(TypeCheckError(kind, span));
+            }
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(ty),
+        }
+    }
+
+    /// Check that `record`, the type of the expression at `span`, has a `field` of type `ty`.
+    fn expect_field(
+        &mut self,
+        span: Span,
+        record: Type,
+        field: &Located<String>,
+        ty: Type,
+    ) -> Result<(), TypeCheckError> {
+        if let Type::Record(fields, None) = self.subst.resolve(&record).unaliased() {
+            if !fields.iter().any(|(name, _)| *name == field.node) {
+                return Err(self.error(
+                    field.span,
+                    TypeCheckErrorType::MissingField {
+                        field: field.node.clone(),
+                        found: record,
+                    },
+                ));
+            }
+        }
+
+        let rest = self.subst.fresh();
+        let expected = Type::record(vec![(field.node.clone(), ty)], Some(rest));
+        self.unify(span, Subject::Expression, expected, record)
+    }
+
+    fn lookup(&mut self, env: &Env, name: &str, span: Span) -> Result<Type, TypeCheckError> {
+        match env.get(name) {
+            None => Err(TypeCheckError(
+                TypeCheckErrorType::UndefinedName(name.to_owned()),
+                span,
+            )),
+            Some(scheme) => Ok(self.subst.instantiate(scheme)),
+        }
+    }
+
+    /// Now that the type variables are solved, check that the program respects the split
+    /// between simple and signal types.
+    fn check_stratification(&self) -> Result<(), TypeCheckError> {
+        for (param, ret, span) in &self.arrows {
+            // o -> t, which should be prohibited
+            if self.subst.resolve(param).is_signal() && !self.subst.resolve(ret).is_signal() {
+                return Err(self.error(
+                    *span,
+                    TypeCheckErrorType::InvalidParamType {
+                        param: param.clone(),
+                        ret: ret.clone(),
                     },
+                ));
+            }
+        }
+
+        for (ty, span) in &self.simple {
+            if self.subst.resolve(ty).has_signal() {
+                return Err(self.error(*span, TypeCheckErrorType::ExpectSimple(ty.clone())));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn infer(&mut self, env: &Env, term: &ast::Expr) -> Result<Type, TypeCheckError> {
+        match &term.node {
+            ExprKind::Const(atom) => match &atom.node {
+                Atom::Unit => Ok(Type::Unit),
+                Atom::Num(_) => Ok(Type::Int),
+                Atom::Bool(_) => Ok(Type::Bool),
+                Atom::Float(_) => Ok(Type::Float),
+                Atom::Str(_) => Ok(Type::String),
+                Atom::Var(name) | Atom::Signal(name) => self.lookup(env, name, atom.span),
+                Atom::Wildcard => Err(TypeCheckError(
+                    TypeCheckErrorType::ExpectIdentifier,
+                    atom.span,
+                )),
+            },
+            ExprKind::Abs(atom, param_ty, expr) => {
+                let name = match &atom.node {
+                    Atom::Var(name) | Atom::Signal(name) => Some(name),
+                    Atom::Wildcard => None,
+                    _ => {
+                        return Err(TypeCheckError(
+                            TypeCheckErrorType::ExpectIdentifier,
+                            atom.span,
+                        ))
+                    }
+                };
+
+                // The annotation, if any, is taken as is, otherwise the parameter gets a fresh
+                // type that its uses will determine.
+                let param = match param_ty {
+                    Some(ty) => {
+                        self.check_declared(&Type::from_ast(&ty.node), &[], ty.span, false)?
+                    }
+                    None => self.subst.fresh(),
+                };
+
+                // Add the new binding to the environment, then get the type of the expression
+                // in the new environment. A `_` parameter binds nothing.
+                let mut new_env = env.clone();
+                if let Some(name) = name {
+                    new_env.insert(name.clone(), Scheme::mono(param.clone()));
+                }
+                let ret = self.infer(&new_env, expr)?;
+
+                self.arrows.push((param.clone(), ret.clone(), expr.span));
+                Ok(Type::arrow(param, ret))
+            }
+            ExprKind::Lambda(_, _) => unreachable!("removed by desugaring"),
+            ExprKind::App(e1, e2) => {
+                let func = self.infer(env, e1)?;
+                let (param, ret) = match self.subst.resolve(&func).unaliased() {
+                    Type::Arrow(param, ret) => (*param, *ret),
+                    Type::Var(_) => {
+                        let (param, ret) = (self.subst.fresh(), self.subst.fresh());
+                        self.subst
+                            .unify(&func, &Type::arrow(param.clone(), ret.clone()));
+                        (param, ret)
+                    }
+                    ty => return Err(self.error(e1.span, TypeCheckErrorType::ExpectFunction(ty))),
+                };
+
+                self.expect_type(env, e2, Subject::Argument, param.clone())?;
+                self.arrows.push((param, ret.clone(), term.span));
+                Ok(ret)
+            }
+            ExprKind::BinOp(e1, op, e2) => {
+                let (operand, result) = match op {
+                    BinOp::Mul
+                    | BinOp::Div
+                    | BinOp::Add
+                    | BinOp::Sub
+                    | BinOp::Le
+                    | BinOp::Ge
+                    | BinOp::Less
+                    | BinOp::Greater => {
+                        // Arithmetic and comparisons take two ints or two floats, the left operand
+                        // decides. There are no implicit conversions between the two.
+                        let ty = self.infer(env, e1)?;
+                        let operand = match self.subst.resolve(&ty).unaliased() {
+                            Type::Float => Type::Float,
+                            _ => Type::Int,
+                        };
+                        self.unify(e1.span, Subject::Operand, operand.clone(), ty)?;
+                        self.expect_type(env, e2, Subject::Operand, operand.clone())?;
+                        return Ok(match op {
+                            BinOp::Le | BinOp::Ge | BinOp::Less | BinOp::Greater => Type::Bool,
+                            _ => operand,
+                        });
+                    }
+                    BinOp::Rem | BinOp::Mod => (Type::Int, Type::Int),
+                    BinOp::And | BinOp::Or => (Type::Bool, Type::Bool),
+                    BinOp::Cons => {
+                        // Lists are simple values, they can not hold signals
+                        let elem = self.infer(env, e1)?;
+                        self.simple.push((elem.clone(), e1.span));
+                        self.expect_type(env, e2, Subject::Operand, Type::list(elem.clone()))?;
+                        return Ok(Type::list(elem));
+                    }
+                    BinOp::Eq | BinOp::Ne => {
+                        // Equality compares two ints, floats, bools or strings, the left operand
+                        // decides
+                        let ty = self.infer(env, e1)?;
+                        let operand = match self.subst.resolve(&ty).unaliased() {
+                            Type::Float => Type::Float,
+                            Type::Bool => Type::Bool,
+                            Type::String => Type::String,
+                            _ => Type::Int,
+                        };
+                        self.unify(e1.span, Subject::Operand, operand.clone(), ty)?;
+                        self.expect_type(env, e2, Subject::Operand, operand)?;
+                        return Ok(Type::Bool);
+                    }
+                    BinOp::Append => {
+                        // Appends two strings or two lists, the left operand decides
+                        let ty = self.infer(env, e1)?;
+                        let operand = match self.subst.resolve(&ty).unaliased() {
+                            Type::List(elem) => Type::list(*elem),
+                            _ => Type::String,
+                        };
+                        self.unify(e1.span, Subject::Operand, operand.clone(), ty)?;
+                        self.expect_type(env, e2, Subject::Operand, operand.clone())?;
+                        return Ok(operand);
+                    }
+                };
+
+                self.expect_type(env, e1, Subject::Operand, operand.clone())?;
+                self.expect_type(env, e2, Subject::Operand, operand)?;
+                Ok(result)
+            }
+            ExprKind::UnOp(UnOp::Not, e) => {
+                self.expect_type(env, e, Subject::Operand, Type::Bool)?;
+                Ok(Type::Bool)
+            }
+            ExprKind::UnOp(UnOp::Neg, e) => {
+                // Negates an int or a float, an int unless the operand is known to be a float
+                let ty = self.infer(env, e)?;
+                let operand = match self.subst.resolve(&ty).unaliased() {
+                    Type::Float => Type::Float,
+                    _ => Type::Int,
+                };
+                self.unify(e.span, Subject::Operand, operand.clone(), ty)?;
+                Ok(operand)
+            }
+            ExprKind::If(e1, e2, e3) => {
+                self.expect_type(env, e1, Subject::Condition, Type::Bool)?;
+
+                // Both branches must agree with the first one
+                let ty = self.infer(env, e2)?;
+                self.expect_type(env, e3, Subject::Branch, ty.clone())?;
+                Ok(ty)
+            }
+            ExprKind::Let(pattern, e1, e2) => {
+                // Generalize the types of the bindings, then get the type of the expression in
+                // the new environment.
+                let new_env = self.bind_let(env, pattern, e1)?;
+                self.infer(&new_env, e2)
+            }
+            ExprKind::LetRec(name, ty, e1, e2) => {
+                let new_env = self.bind_let_rec(env, name, ty, e1)?;
+                self.infer(&new_env, e2)
+            }
+            ExprKind::TypeDef(def, e) => {
+                let new_env = self.bind_type(env, def)?;
+                self.infer(&new_env, e)
+            }
+            ExprKind::TypeAlias(def, e) => {
+                self.alias(def)?;
+                self.infer(env, e)
+            }
+            ExprKind::Case(e, branches) => {
+                // Every pattern must match values of the type of `e`, and every branch must
+                // agree with the first one
+                let ty = self.infer(env, e)?;
+                let result = self.subst.fresh();
+                for (pattern, body) in branches {
+                    let mut bindings = vec![];
+                    let found = self.pattern_type(pattern, &mut bindings)?;
+                    self.unify(pattern.span, Subject::Pattern, ty.clone(), found)?;
+
+                    // Names bound by a pattern are not generalized, like lambda parameters
+                    let mut new_env = env.clone();
+                    for (name, ty) in bindings {
+                        new_env.insert(name, Scheme::mono(ty));
+                    }
+                    self.expect_type(&new_env, body, Subject::Branch, result.clone())?;
+                }
+
+                let patterns: Vec<_> = branches.iter().map(|(pattern, _)| pattern).collect();
+                self.check_patterns(term.span, &patterns)?;
+                Ok(result)
+            }
+            ExprKind::Tuple(es) => {
+                // Tuples are simple values, they can not hold signals
+                let mut types = vec![];
+                for e in es {
+                    let ty = self.infer(env, e)?;
+                    self.simple.push((ty.clone(), e.span));
+                    types.push(ty);
+                }
+                Ok(Type::Tuple(types))
+            }
+            ExprKind::List(es) => {
+                // Lists are simple values, they can not hold signals
+                let elem = self.subst.fresh();
+                for e in es {
+                    self.expect_type(env, e, Subject::Element, elem.clone())?;
+                    self.simple.push((elem.clone(), e.span));
+                }
+                Ok(Type::list(elem))
+            }
+            ExprKind::Record(fields) => {
+                // Records are simple values, they can not hold signals
+                let mut types: Vec<(String, Type)> = vec![];
+                for (field, e) in fields {
+                    if types.iter().any(|(name, _)| *name == field.node) {
+                        return Err(TypeCheckError(
+                            TypeCheckErrorType::DuplicateField(field.node.clone()),
+                            field.span,
+                        ));
+                    }
+
+                    let ty = self.infer(env, e)?;
+                    self.simple.push((ty.clone(), e.span));
+                    types.push((field.node.clone(), ty));
+                }
+                Ok(Type::record(types, None))
+            }
+            ExprKind::Field(e, field) => {
+                // Any record with the field will do, `{ r | x : t }`
+                let record = self.infer(env, e)?;
+                let ty = self.subst.fresh();
+                self.expect_field(e.span, record, field, ty.clone())?;
+                Ok(ty)
+            }
+            ExprKind::Update(e, fields) => {
+                // The record must already have the fields, and an update keeps their types
+                let record = self.infer(env, e)?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if fields[..i]
+                        .iter()
+                        .any(|(other, _)| other.node == field.node)
+                    {
+                        return Err(TypeCheckError(
+                            TypeCheckErrorType::DuplicateField(field.node.clone()),
+                            field.span,
+                        ));
+                    }
+
+                    let ty = self.subst.fresh();
+                    self.expect_field(e.span, record.clone(), field, ty.clone())?;
+                    self.expect_type(env, value, Subject::Field, ty)?;
                 }
+                Ok(record)
             }
+            ExprKind::Lift(n, expr, vec) => {
+                // First of all check if the number of arguments is correct.
+                if *n != vec.len() {
+                    return Err(TypeCheckError(
+                        TypeCheckErrorType::LiftArity {
+                            expected: *n,
+                            found: vec.len(),
+                        },
+                        term.span,
+                    ));
+                };
+
+                // Then we construct a vector of all the argument types, and make sure each of
+                // them is a signal of some simple type
+                let mut types = vec![];
+                for atom in vec {
+                    let ty_i = match &atom.node {
+                        Atom::Var(input) => self.lookup(env, input, atom.span),
+                        _ => Err(TypeCheckError(
+                            TypeCheckErrorType::ExpectIdentifier,
+                            atom.span,
+                        )),
+                    }?;
 
-            // First of all check if the number of arguments is correct.
-            if n != types.len() {
-                return Err(TypeCheckError(TypeCheckErrorType::TypeMissMatch));
-            };
+                    let value = self.signal_of(atom.span, ty_i)?;
+                    self.simple.push((value.clone(), atom.span));
+                    types.push((value, atom.span));
+                }
+
+                // The lifted function must be a simple function, t1 -> ... -> tn -> t
+                let func = self.infer(env, expr)?;
+                self.simple.push((func.clone(), expr.span));
 
-            let ty = get_type_of(env, expr)?;
+                // Consume one parameter per argument, what remains is the return type.
+                let mut ty = func;
+                for (arg_ty, span) in types {
+                    let (param, ret) = match self.subst.resolve(&ty).unaliased() {
+                        Type::Arrow(param, ret) => (*param, *ret),
+                        Type::Var(_) => {
+                            let ret = self.subst.fresh();
+                            self.subst
+                                .unify(&ty, &Type::arrow(arg_ty.clone(), ret.clone()));
+                            (arg_ty.clone(), ret)
+                        }
+                        ty => {
+                            return Err(
+                                self.error(expr.span, TypeCheckErrorType::ExpectFunction(ty))
+                            )
+                        }
+                    };
 
-            let mut lift_ty = vec![];
-            match ty.clone() {
-                Simple(x) => {
-                    foo(lift_ty.as_mut(), x.clone());
+                    self.unify(
+                        span,
+                        Subject::Signal,
+                        Type::signal(param),
+                        Type::signal(arg_ty),
+                    )?;
+                    ty = ret;
                 }
-                _ => unreachable!(),
-            };
 
-            // In current implementation, the last element is the return type. Thus we can
-            // Simply compare the list except the last one
-            let return_ty = lift_ty.remove(lift_ty.len() - 1);
-            if lift_ty == types {
-                Ok(Simple(return_ty))
-            } else {
-                Err(TypeCheckError(TypeCheckErrorType::TypeMissMatch))
+                Ok(Type::signal(ty))
+            }
+            ExprKind::Foldp(e1, e2, e3) => {
+                // foldp : (t -> t' -> t') -> t' -> signal t. -> signal t'.
+                let acc_ty = self.infer(env, e2)?;
+                self.simple.push((acc_ty.clone(), e2.span));
+
+                let input_ty = self.expect_signal(env, e3)?;
+
+                let step_ty = Type::arrow(input_ty, Type::arrow(acc_ty.clone(), acc_ty.clone()));
+                self.expect_type(env, e1, Subject::Function, step_ty)?;
+                Ok(Type::signal(acc_ty))
+            }
+            ExprKind::Async(e) => {
+                // async : signal t. -> signal t.
+                let ty = self.expect_signal(env, e)?;
+                Ok(Type::signal(ty))
             }
         }
-        Expr::Foldp(_, _, _) => unimplemented!(),
     }
 }
 
-fn foo(vec: &mut Vec<SimpleType>, ty: SimpleType) {
-    match ty {
-        SimpleType::Unit => vec.push(Unit),
-        SimpleType::Int => vec.push(Int),
-        SimpleType::Abs(l, r) => {
-            foo(vec, *l);
-            foo(vec, *r);
-        }
-    }
+/// Whether the type named `name` appears in `ty`.
+fn mentions(ty: &Type, name: &str) -> bool {
+    let mut found = matches!(ty, Type::Named(other, _) if other == name);
+    ty.map(|ty| {
+        found = found || mentions(ty, name);
+        ty.clone()
+    });
+    found
 }
 
 #[cfg(test)]
 mod test {
-    use super::{get_type_of, typecheck_root};
+    use super::{get_type_of, typecheck_program, typecheck_root, TypeCheckError};
     use rustelm_parser::{
         ast::{
             SignalType,
             SimpleType::{Abs, Int, Unit},
             Types::*,
         },
-        parser::parse,
+        parser::{parse, parse_program},
     };
 
+    /// The type of an expression, as it is printed.
+    fn ty(source: &str) -> String {
+        format!("{}", typecheck_root(&parse(source).unwrap()).unwrap())
+    }
+
+    /// The error of an expression that does not typecheck.
+    fn error(source: &str) -> TypeCheckError {
+        typecheck_root(&parse(source).unwrap()).unwrap_err()
+    }
+
+    /// The message of the error of an expression.
+    fn err(source: &str) -> String {
+        error(source).to_string()
+    }
+
+    /// The message of the error of an expression, and where it points.
+    fn err_at(source: &str) -> (String, usize, usize) {
+        let error = error(source);
+        (error.to_string(), error.span().start, error.span().end)
+    }
+
     #[test]
     fn test_atom() {
-        assert_eq!(typecheck_root(parse("1\n").unwrap()).unwrap(), Simple(Int));
+        assert_eq!(typecheck_root(&parse("1\n").unwrap()).unwrap(), Simple(Int));
         assert_eq!(
-            typecheck_root(parse("()\n").unwrap()).unwrap(),
+            typecheck_root(&parse("()\n").unwrap()).unwrap(),
             Simple(Unit)
         );
-        assert!(typecheck_root(parse("x\n").unwrap()).is_err());
+        assert!(typecheck_root(&parse("x\n").unwrap()).is_err());
 
         let fake_env = im::hashmap! { "x".to_owned() => Simple(Int) };
-        assert!(get_type_of(&fake_env, parse("y\n").unwrap()).is_err());
+        assert!(get_type_of(&fake_env, &parse("y\n").unwrap()).is_err());
         assert_eq!(
-            get_type_of(&fake_env, parse("x\n").unwrap()).unwrap(),
+            get_type_of(&fake_env, &parse("x\n").unwrap()).unwrap(),
             Simple(Int)
         );
     }
@@ -224,12 +1210,12 @@ mod test {
     #[test]
     fn test_signal() {
         assert_eq!(
-            typecheck_root(parse("MouseClicks\n").unwrap()).unwrap(),
+            typecheck_root(&parse("MouseClicks\n").unwrap()).unwrap(),
             Signal(SignalType::Signal(Unit))
         );
 
         assert_eq!(
-            typecheck_root(parse("MouseX\n").unwrap()).unwrap(),
+            typecheck_root(&parse("MouseX\n").unwrap()).unwrap(),
             Signal(SignalType::Signal(Int))
         );
     }
@@ -239,7 +1225,7 @@ mod test {
         assert_eq!(
             &format!(
                 "{:?}",
-                typecheck_root(parse("\\x: int. x\n").unwrap()).unwrap()
+                typecheck_root(&parse("\\x: int. x\n").unwrap()).unwrap()
             ),
             "(int -> int)"
         );
@@ -247,7 +1233,7 @@ mod test {
         assert_eq!(
             &format!(
                 "{:?}",
-                typecheck_root(parse("\\x: int. \\y: int. \\z: int. x + y + z\n").unwrap())
+                typecheck_root(&parse("\\x: int. \\y: int. \\z: int. x + y + z\n").unwrap())
                     .unwrap()
             ),
             "(int -> (int -> (int -> int)))"
@@ -257,7 +1243,7 @@ mod test {
         assert_eq!(
             &format!(
                 "{:?}",
-                typecheck_root(parse("\\x: int. MouseClicks\n").unwrap()).unwrap()
+                typecheck_root(&parse("\\x: int. MouseClicks\n").unwrap()).unwrap()
             ),
             "(int -> sig(unit))"
         );
@@ -265,93 +1251,1045 @@ mod test {
         assert_eq!(
             &format!(
                 "{:?}",
-                typecheck_root(parse("\\x: signal unit.. MouseClicks\n").unwrap()).unwrap()
+                typecheck_root(&parse("\\x: signal unit.. MouseClicks\n").unwrap()).unwrap()
             ),
             "(sig(unit) -> sig(unit))"
         );
 
-        assert!(typecheck_root(parse("\\x: signal unit.. 1\n").unwrap()).is_err());
+        assert!(typecheck_root(&parse("\\x: signal unit.. 1\n").unwrap()).is_err());
     }
 
     #[test]
     fn test_app() {
-        assert!(typecheck_root(parse("(\\x: int. x) 1\n").unwrap()).is_ok());
-        assert!(typecheck_root(parse("(\\x: int. x) ()\n").unwrap()).is_err());
-        assert!(typecheck_root(parse("(\\x: unit. x) 1\n").unwrap()).is_err());
-        assert!(typecheck_root(parse("(\\x: unit. x) ()\n").unwrap()).is_ok());
+        assert!(typecheck_root(&parse("(\\x: int. x) 1\n").unwrap()).is_ok());
+        assert!(typecheck_root(&parse("(\\x: int. x) ()\n").unwrap()).is_err());
+        assert!(typecheck_root(&parse("(\\x: unit. x) 1\n").unwrap()).is_err());
+        assert!(typecheck_root(&parse("(\\x: unit. x) ()\n").unwrap()).is_ok());
 
         assert_eq!(
-            typecheck_root(parse("(\\x: int. \\y: int. \\z: int. x + y + z) 1 2 3\n").unwrap())
+            typecheck_root(&parse("(\\x: int. \\y: int. \\z: int. x + y + z) 1 2 3\n").unwrap())
                 .unwrap(),
             Simple(Int)
         );
 
         // Because it is missing the last argument thus it return type (int -> int)
         assert_eq!(
-            typecheck_root(parse("(\\x: int. \\y: int. \\z: int. x + y + z) 1 2\n").unwrap())
+            typecheck_root(&parse("(\\x: int. \\y: int. \\z: int. x + y + z) 1 2\n").unwrap())
                 .unwrap(),
             Simple(Abs(Box::new(Int), Box::new(Int)))
         );
 
         // Signal type
         assert_eq!(
-            typecheck_root(parse("(\\x: signal int.. x) MouseX\n").unwrap()).unwrap(),
+            typecheck_root(&parse("(\\x: signal int.. x) MouseX\n").unwrap()).unwrap(),
             Signal(SignalType::Signal(Int))
         );
 
-        assert!(typecheck_root(parse("(\\x: signal int.. x) 1\n").unwrap()).is_err(),);
-        assert!(typecheck_root(parse("(\\x: signal int.. x) MouseClicks\n").unwrap()).is_err(),);
+        assert!(typecheck_root(&parse("(\\x: signal int.. x) 1\n").unwrap()).is_err(),);
+        assert!(typecheck_root(&parse("(\\x: signal int.. x) MouseClicks\n").unwrap()).is_err(),);
     }
 
     #[test]
     fn test_binop() {
-        assert!(typecheck_root(parse("1 + 1\n").unwrap()).is_ok());
-        assert!(typecheck_root(parse("1 + ()\n").unwrap()).is_err());
+        assert!(typecheck_root(&parse("1 + 1\n").unwrap()).is_ok());
+        assert!(typecheck_root(&parse("1 + ()\n").unwrap()).is_err());
 
         let fake_env = im::hashmap! { "x".to_owned() => Simple(Int) };
-        assert!(get_type_of(&fake_env, parse("x + x + 1\n").unwrap()).is_ok());
-    }
+        assert!(get_type_of(&fake_env, &parse("x + x + 1\n").unwrap()).is_ok());
 
-    #[test]
-    fn test_if() {
+        assert_eq!(ty("\\x. -x\n"), "int -> int");
+        assert_eq!(ty("-1.5\n"), "float");
+        assert_eq!(ty("\\x. x % 2 + x mod 3\n"), "int -> int");
         assert_eq!(
-            typecheck_root(parse("if 1 then 1 else 1\n").unwrap()).unwrap(),
-            Simple(Int)
+            ty("\\n. case n of { -1 -> True; _ -> False }\n"),
+            "int -> bool"
         );
-
-        assert!(typecheck_root(parse("if 1 then () else ()\n").unwrap()).is_ok());
-        assert!(typecheck_root(parse("if () then () else ()\n").unwrap()).is_err());
+        assert!(typecheck_root(&parse("-True\n").unwrap()).is_err());
+        assert!(typecheck_root(&parse("1.5 % 2.0\n").unwrap()).is_err());
     }
 
     #[test]
-    fn test_let() {
-        assert!(typecheck_root(parse("let x = 1 in x\n").unwrap()).is_ok());
-        assert!(typecheck_root(parse("let x = 1 in y\n").unwrap()).is_err());
+    fn test_function() {
+        assert_eq!(ty("\\x y. x + y\n"), "int -> (int -> int)");
+        assert_eq!(
+            ty("let add (x: int) (y: int) = x + y in add\n"),
+            "int -> (int -> int)"
+        );
+        assert_eq!(ty("let const x _ = x in const 1 True\n"), "int");
+        assert_eq!(
+            ty("let pair x y = (x, y) in (pair 1 True, pair () 2)\n"),
+            "((int, bool), (unit, int))"
+        );
 
+        // Errors point at the parameters and bodies as written
         assert_eq!(
-            typecheck_root(parse("let x = 1 + 2 in x\n").unwrap()).unwrap(),
-            Simple(Int)
+            err_at("let add (x: int) (y: bool) = x + y in add\n"),
+            (
+                "expected `int` but this operand has type `bool`".to_owned(),
+                33,
+                34
+            )
+        );
+        assert_eq!(
+            err_at("\\x (y: Foo). x\n"),
+            ("undefined type `Foo`".to_owned(), 7, 10)
+        );
+        assert_eq!(
+            err_at("let f (x: int) = x in f True\n"),
+            (
+                "expected `int` but this argument has type `bool`".to_owned(),
+                24,
+                28
+            )
         );
 
+        // Annotated patterns
+        assert_eq!(ty("\\p. let ((x: int), y) = p in y\n"), "(int, a) -> a");
         assert_eq!(
-            typecheck_root(parse("let x = 1 in let y = 1 in let z = 1 in x + y + z\n").unwrap())
-                .unwrap(),
-            Simple(Int),
+            err_at("case 1 of { (x: bool) -> x }\n"),
+            (
+                "expected `int` but this pattern has type `bool`".to_owned(),
+                12,
+                21
+            )
         );
     }
 
     #[test]
-    fn test_lift() {
-        assert!(typecheck_root(parse("lift1 (\\ x: unit. 1): MouseClicks\n").unwrap()).is_ok());
-
-        assert!(
-            typecheck_root(parse("lift2 (\\ x: int. \\y: int. ()): MouseX MouseY\n").unwrap())
-                .is_ok()
+    fn test_let_annotation() {
+        assert_eq!(ty("let speed : int = 3 in speed\n"), "int");
+        assert_eq!(
+            ty("let s : signal int. = lift1 (\\x. x + 1): MouseX in s\n"),
+            "signal int."
         );
+        assert_eq!(ty("let (x, y) : (int, bool) = (1, True) in y\n"), "bool");
+        // The annotation may be more specific than the inferred type
+        assert_eq!(ty("let f : int -> int = \\x. x in f\n"), "int -> int");
 
-        assert!(typecheck_root(
-            parse("lift2 (\\ x: int. \\y: int. ()): MouseClicks MouseClicks\n").unwrap()
-        )
-        .is_err());
+        // Mismatches are reported at the annotation
+        assert_eq!(
+            err_at("let speed : bool = 3 in speed\n"),
+            (
+                "the annotation says `bool`, but the value has type `int`".to_owned(),
+                12,
+                16
+            )
+        );
+        assert_eq!(
+            err_at("let s : int = MouseX in s\n"),
+            (
+                "the annotation says `int`, but the value has type `signal int.`".to_owned(),
+                8,
+                11
+            )
+        );
+        assert_eq!(
+            err_at("let rec f: int -> bool = \\n. n + 1 in f\n"),
+            (
+                "the annotation says `int -> bool`, but the value has type `int -> int`".to_owned(),
+                11,
+                22
+            )
+        );
+        assert_eq!(
+            err_at("let x : Shape = 1 in x\n"),
+            ("undefined type `Shape`".to_owned(), 8, 13)
+        );
+    }
+
+    #[test]
+    fn test_let_rec() {
+        let fact = "let rec fact: int -> int = \\n. if n < 1 then 1 else n * fact (n - 1) in ";
+        assert_eq!(ty(&format!("{}fact\n", fact)), "int -> int");
+        assert_eq!(ty(&format!("{}lift1 fact: MouseX\n", fact)), "signal int.");
+        assert_eq!(
+            ty("let rec sum: list int -> int = \\l. case l of { [] -> 0; x :: xs -> x + (sum xs) } in sum\n"),
+            "list int -> int"
+        );
+
+        assert_eq!(
+            err_at("let rec f = \\n. f n in f\n"),
+            (
+                "the recursive function `f` needs a type annotation".to_owned(),
+                8,
+                9
+            )
+        );
+        assert_eq!(
+            err_at("let rec s: signal int. = lift1 (\\x. x): s in s\n"),
+            (
+                "the recursive function `s` can not have a signal type, signals can not be \
+                 defined recursively"
+                    .to_owned(),
+                11,
+                22
+            )
+        );
+        assert_eq!(
+            err_at("let rec x: int = x + 1 in x\n"),
+            (
+                "the recursive binding `x` must be a function `\\x. e`".to_owned(),
+                17,
+                22
+            )
+        );
+        assert_eq!(
+            err_at("let rec f: int -> int = \\n. f True in f\n"),
+            (
+                "expected `int` but this argument has type `bool`".to_owned(),
+                30,
+                34
+            )
+        );
+    }
+
+    #[test]
+    fn test_bool() {
+        assert_eq!(ty("True\n"), "bool");
+        assert_eq!(ty("1 < 2\n"), "bool");
+        assert_eq!(ty("\\x. \\y. x == y\n"), "int -> (int -> bool)");
+        assert_eq!(ty("\\x: bool. x == True\n"), "bool -> bool");
+        assert_eq!(ty("\\x. not x || x && False\n"), "bool -> bool");
+
+        assert!(typecheck_root(&parse("True + 1\n").unwrap()).is_err());
+        assert!(typecheck_root(&parse("not 1\n").unwrap()).is_err());
+        assert!(typecheck_root(&parse("1 == True\n").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_if() {
+        assert_eq!(
+            typecheck_root(&parse("if True then 1 else 1\n").unwrap()).unwrap(),
+            Simple(Int)
+        );
+
+        assert!(typecheck_root(&parse("if 1 < 2 then () else ()\n").unwrap()).is_ok());
+        assert!(typecheck_root(&parse("if () then () else ()\n").unwrap()).is_err());
+
+        // Integers are not conditions
+        assert_eq!(
+            typecheck_root(&parse("if 1 then () else ()\n").unwrap())
+                .unwrap_err()
+                .to_string(),
+            "expected `bool` but this condition has type `int`"
+        );
+    }
+
+    #[test]
+    fn test_let() {
+        assert!(typecheck_root(&parse("let x = 1 in x\n").unwrap()).is_ok());
+        assert!(typecheck_root(&parse("let x = 1 in y\n").unwrap()).is_err());
+
+        assert_eq!(
+            typecheck_root(&parse("let x = 1 + 2 in x\n").unwrap()).unwrap(),
+            Simple(Int)
+        );
+
+        assert_eq!(
+            typecheck_root(&parse("let x = 1 in let y = 1 in let z = 1 in x + y + z\n").unwrap())
+                .unwrap(),
+            Simple(Int),
+        );
+    }
+
+    #[test]
+    fn test_lift() {
+        assert!(typecheck_root(&parse("lift1 (\\ x: unit. 1): MouseClicks\n").unwrap()).is_ok());
+
+        assert_eq!(
+            typecheck_root(&parse("lift2 (\\ x: int. \\y: int. ()): MouseX MouseY\n").unwrap())
+                .unwrap(),
+            Signal(SignalType::Signal(Unit))
+        );
+
+        // Too many arguments for the lifted function
+        assert!(typecheck_root(&parse("lift2 (\\ x: int. x): MouseX MouseY\n").unwrap()).is_err());
+
+        // Only signals can be lifted
+        assert!(typecheck_root(&parse("let x = 1 in lift1 (\\ x: int. x): x\n").unwrap()).is_err());
+
+        assert!(typecheck_root(
+            &parse("lift2 (\\ x: int. \\y: int. ()): MouseClicks MouseClicks\n").unwrap()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_foldp() {
+        // Counting the mouse clicks
+        assert_eq!(
+            typecheck_root(
+                &parse("foldp (\\x: unit. \\acc: int. acc + 1) 0 MouseClicks\n").unwrap()
+            )
+            .unwrap(),
+            Signal(SignalType::Signal(Int))
+        );
+
+        assert_eq!(
+            typecheck_root(
+                &parse("let s = lift1 (\\x: int. ()): MouseX in foldp (\\x: unit. \\acc: unit. acc) () s\n")
+                    .unwrap()
+            )
+            .unwrap(),
+            Signal(SignalType::Signal(Unit))
+        );
+
+        // The step function does not match the input signal
+        assert!(typecheck_root(
+            &parse("foldp (\\x: int. \\acc: int. acc + x) 0 MouseClicks\n").unwrap()
+        )
+        .is_err());
+
+        // The accumulator does not match the initial value
+        assert!(typecheck_root(
+            &parse("foldp (\\x: unit. \\acc: int. acc + 1) () MouseClicks\n").unwrap()
+        )
+        .is_err());
+
+        // The last argument must be a signal
+        assert!(
+            typecheck_root(&parse("foldp (\\x: int. \\acc: int. acc + x) 0 1\n").unwrap()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_async() {
+        assert_eq!(
+            typecheck_root(&parse("async MouseClicks\n").unwrap()).unwrap(),
+            Signal(SignalType::Signal(Unit))
+        );
+
+        assert_eq!(
+            typecheck_root(&parse("async (lift1 (\\x: int. x * x): MouseX)\n").unwrap()).unwrap(),
+            Signal(SignalType::Signal(Int))
+        );
+
+        assert!(typecheck_root(&parse("async 1\n").unwrap()).is_err());
+        assert!(typecheck_root(&parse("async (\\x: int. MouseX)\n").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        use crate::error::{Subject, TypeCheckErrorType};
+        use rustelm_parser::ast::Span;
+
+        let e = error("(\\x: int. x) ()\n");
+        assert_eq!(e.span(), Span::new(13, 15));
+        assert!(matches!(
+            e.0,
+            TypeCheckErrorType::TypeMissMatch {
+                subject: Subject::Argument,
+                ..
+            }
+        ));
+        assert_eq!(
+            e.to_string(),
+            "expected `int` but this argument has type `unit`"
+        );
+
+        let e = error("let f = \\x: int. x in 1 + f\n");
+        assert_eq!(e.span(), Span::new(26, 27));
+        assert_eq!(
+            e.to_string(),
+            "expected `int` but this operand has type `int -> int`"
+        );
+
+        let e = error("if True then 2 else y\n");
+        assert_eq!(e.span(), Span::new(20, 21));
+        assert_eq!(e.to_string(), "undefined variable `y`");
+
+        let e = error("\\x: signal int.. 1\n");
+        assert_eq!(
+            e.to_string(),
+            "a function taking `signal int.` must return a signal, but this one returns `int`"
+        );
+
+        let e = error("lift2 (\\x: int. \\y: int. x): MouseX MouseClicks\n");
+        assert_eq!(e.span(), Span::new(36, 47));
+        assert_eq!(
+            e.to_string(),
+            "expected `signal int.` but this signal has type `signal unit.`"
+        );
+
+        let e = error("lift2 (\\x: int. x): MouseX\n");
+        assert_eq!(e.to_string(), "lift2 expects 2 signals but was given 1");
+
+        let e = error("foldp (\\x: int. \\acc: int. acc) 0 MouseClicks\n");
+        assert_eq!(e.span(), Span::new(7, 30));
+        assert_eq!(
+            e.to_string(),
+            "expected `unit -> (int -> int)` but this function has type `int -> (int -> int)`"
+        );
+
+        let e = error("async 1\n");
+        assert_eq!(
+            e.to_string(),
+            "expected a signal but this expression has type `int`"
+        );
+    }
+
+    #[test]
+    fn test_infer() {
+        assert_eq!(ty("\\x. x\n"), "a -> a");
+        assert_eq!(ty("\\x. \\y. x\n"), "a -> (b -> a)");
+        assert_eq!(ty("\\x. x + 1\n"), "int -> int");
+        assert_eq!(ty("(\\f. f 1) (\\x. x)\n"), "int");
+
+        // Let bindings are polymorphic, and may be used on signals too
+        assert_eq!(ty("let id = \\x. x in id ()\n"), "unit");
+        assert_eq!(ty("let id = \\x. x in id MouseX\n"), "signal int.");
+
+        // Signals fix the type of the parameters they flow into
+        assert_eq!(
+            ty("\\s. lift1 (\\x. x * 2): s\n"),
+            "signal int. -> signal int."
+        );
+        assert_eq!(
+            ty("\\s. foldp (\\x. \\acc. acc + 1) 0 s\n"),
+            "signal a. -> signal int."
+        );
+        assert_eq!(
+            ty("let count = foldp (\\x. \\acc. acc + 1) 0 MouseClicks in lift2 (\\x. \\y. x + y): count MouseX\n"),
+            "signal int."
+        );
+
+        // Annotations are checked
+        assert_eq!(ty("\\x: int. \\y. x\n"), "int -> (a -> int)");
+        assert!(typecheck_root(&parse("\\x: unit. x + 1\n").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_infer_errors() {
+        // A signal flowing into a function that returns a simple value, `o -> t`
+        assert_eq!(
+            error("let f = \\x. 1 in f MouseX\n").to_string(),
+            "a function taking `signal int.` must return a signal, but this one returns `int`"
+        );
+
+        // Signals only carry simple values
+        assert_eq!(
+            error("lift1 (\\x. MouseX): MouseX\n").to_string(),
+            "expected a simple type but this expression has type `int -> signal int.`"
+        );
+        assert_eq!(
+            error("foldp (\\x. \\acc. acc) MouseX MouseClicks\n").to_string(),
+            "expected a simple type but this expression has type `signal int.`"
+        );
+
+        assert_eq!(
+            error("\\x. x x\n").to_string(),
+            "expected `a` but this argument has type `a -> b`"
+        );
+        assert_eq!(
+            error("\\x: int. x ()\n").to_string(),
+            "expected a function but this expression has type `int`"
+        );
+        assert_eq!(
+            error("\\x. if x then x else ()\n").to_string(),
+            "expected `bool` but this branch has type `unit`"
+        );
+    }
+
+    #[test]
+    fn test_tuple() {
+        assert_eq!(ty("(1, True, ())\n"), "(int, bool, unit)");
+        assert_eq!(ty("\\p. let (x, y) = p in x + 1\n"), "(int, a) -> int");
+        assert_eq!(ty("\\p: (int, int). p\n"), "(int, int) -> (int, int)");
+        assert_eq!(
+            ty("let (f, g) = (\\x. x, \\x. x) in (f 1, g True)\n"),
+            "(int, bool)"
+        );
+
+        // A foldp can keep more than one number in its state
+        assert_eq!(
+            ty("foldp (\\x. \\acc. let (n, sum) = acc in (n + 1, sum + x)) (0, 0) MouseX\n"),
+            "signal (int, int)."
+        );
+
+        assert_eq!(
+            error("let (x, y) = 1 in x\n").to_string(),
+            "expected `(a, b)` but this expression has type `int`"
+        );
+        assert_eq!(
+            error("let (x, y) = (1, 2, 3) in x\n").to_string(),
+            "expected `(a, b)` but this expression has type `(int, int, int)`"
+        );
+
+        // Tuples are simple values
+        assert_eq!(
+            error("(MouseX, 1)\n").to_string(),
+            "expected a simple type but this expression has type `signal int.`"
+        );
+    }
+
+    #[test]
+    fn test_record() {
+        assert_eq!(ty("{ y = True, x = 1 }\n"), "{ x : int, y : bool }");
+        assert_eq!(ty("{ x = 1, y = 2 }.y\n"), "int");
+
+        // Field access and update work on any record with the fields
+        assert_eq!(ty("\\r. r.x\n"), "{ a | x : b } -> b");
+        assert_eq!(ty("\\r. r.x + r.y\n"), "{ a | x : int, y : int } -> int");
+        assert_eq!(
+            ty("\\r. { r | x = 1 }\n"),
+            "{ a | x : int } -> { a | x : int }"
+        );
+        assert_eq!(
+            ty("let get = \\r. r.x in (get { x = 1 }, get { x = True, y = () })\n"),
+            "(int, bool)"
+        );
+
+        // Annotated records are closed
+        assert_eq!(
+            err("(\\r: { x : int }. r.x) { x = 1, y = 2 }\n"),
+            "expected `{ x : int }` but this argument has type `{ x : int, y : int }`"
+        );
+
+        assert_eq!(
+            err("{ x = 1 }.y\n"),
+            "this record has no field `y`, its type is `{ x : int }`"
+        );
+        assert_eq!(
+            err("{ { x = 1 } | x = True }\n"),
+            "expected `int` but this field has type `bool`"
+        );
+        assert_eq!(
+            err("{ x = 1, x = 2 }\n"),
+            "the field `x` is defined more than once"
+        );
+
+        // A GUI state model
+        assert_eq!(
+            ty("foldp (\\x. \\s. { s | clicks = s.clicks + 1 }) { clicks = 0, x = 0 } MouseClicks\n"),
+            "signal { clicks : int, x : int }."
+        );
+    }
+
+    #[test]
+    fn test_type_def() {
+        assert_eq!(
+            ty("type Shape = Circle int | Rect int int in Rect\n"),
+            "int -> (int -> Shape)"
+        );
+        assert_eq!(
+            ty("type Maybe a = Nothing | Just a in (Just 1, Just True, Nothing)\n"),
+            "(Maybe int, Maybe bool, Maybe a)"
+        );
+        assert_eq!(
+            ty("type List = Nil | Cons int List in Cons 1 (Cons 2 Nil)\n"),
+            "List"
+        );
+        assert_eq!(
+            ty("type Maybe a = Nothing | Just a in \\m: Maybe (Maybe int). m\n"),
+            "Maybe (Maybe int) -> Maybe (Maybe int)"
+        );
+
+        assert_eq!(
+            err("type Shape = Circle int in Circle True\n"),
+            "expected `int` but this argument has type `bool`"
+        );
+        assert_eq!(err("type T = A Shape in A\n"), "undefined type `Shape`");
+        assert_eq!(
+            err("type Maybe a = Nothing | Just a in \\m: Maybe. m\n"),
+            "the type `Maybe` expects 1 argument but was given 0"
+        );
+        assert_eq!(err("type T = A | A in A\n"), "`A` is already defined");
+        assert_eq!(
+            err("type T = a int in 1\n"),
+            "the constructor `a` must start with a capital letter"
+        );
+
+        // Constructors build simple values
+        assert_eq!(
+            err("type Maybe a = Nothing | Just a in Just MouseX\n"),
+            "a function taking `signal int.` must return a signal, but this one returns `Maybe (signal int.)`"
+        );
+    }
+
+    #[test]
+    fn test_type_alias() {
+        // The aliases are kept in the types printed back
+        assert_eq!(
+            ty("type alias Point = (int, int) in \\p: Point. p\n"),
+            "Point -> Point"
+        );
+        assert_eq!(
+            ty("type alias Point = (int, int) in let p : Point = (1, 2) in p\n"),
+            "Point"
+        );
+        assert_eq!(
+            ty("type alias Pair a = (a, a) in \\p: Pair int. p\n"),
+            "Pair int -> Pair int"
+        );
+        assert_eq!(
+            ty("type alias Point = (int, int) in type Shape = Circle Point int in Circle (1, 2) 3\n"),
+            "Shape"
+        );
+
+        // A signal type may be aliased, and used where a signal may appear
+        let mouse = "type alias Mouse = signal int. in ";
+        assert_eq!(
+            ty(&format!("{}let m : Mouse = MouseX in m\n", mouse)),
+            "Mouse"
+        );
+        assert_eq!(
+            ty(&format!("{}\\m: Mouse. lift1 (\\x. x + 1): m\n", mouse)),
+            "Mouse -> signal int."
+        );
+        assert_eq!(
+            ty("type alias Scaled = int -> signal int. in \
+                let f : Scaled = \\n. lift1 (\\x. x * n): MouseX in f\n"),
+            "Scaled"
+        );
+
+        assert_eq!(
+            err_at("type alias Point = (int, int) in let p : Point = True in p\n"),
+            (
+                "the annotation says `Point`, but the value has type `bool`".to_owned(),
+                41,
+                46
+            )
+        );
+        assert_eq!(
+            err_at("type alias Pair a = (a, a) in \\p: Pair. p\n"),
+            (
+                "the type `Pair` expects 1 argument but was given 0".to_owned(),
+                34,
+                38
+            )
+        );
+        assert_eq!(
+            err_at("type alias Tree = list Tree in 1\n"),
+            (
+                "the type alias `Tree` refers to itself, only a type declared with `type` can be \
+                 recursive"
+                    .to_owned(),
+                11,
+                15
+            )
+        );
+        assert_eq!(
+            err_at("type alias Point = int in type Point = P in 1\n").0,
+            "`Point` is already defined"
+        );
+
+        // A signal alias can not be smuggled into a simple type
+        let signal_alias = "the type alias `Mouse` stands for a signal type, it can not be used \
+                            inside a simple type";
+        assert_eq!(
+            err_at(&format!("{}\\p: (Mouse, int). p\n", mouse)),
+            (signal_alias.to_owned(), 38, 50)
+        );
+        assert_eq!(
+            err_at(&format!("{}\\p: list Mouse. p\n", mouse)).0,
+            signal_alias
+        );
+        assert_eq!(
+            err_at(&format!("{}type alias Pair = (Mouse, int) in 1\n", mouse)),
+            (signal_alias.to_owned(), 52, 64)
+        );
+        assert_eq!(
+            err_at(&format!("{}type Box = Box Mouse in 1\n", mouse)).0,
+            signal_alias
+        );
+        assert_eq!(
+            err_at(&format!("{}type alias Bad = Mouse -> int in 1\n", mouse)).0,
+            "a function taking `Mouse` must return a signal, but this one returns `int`"
+        );
+        assert_eq!(
+            err_at(&format!(
+                "{}let rec f : int -> Mouse = \\n. MouseX in f\n",
+                mouse
+            ))
+            .0,
+            "the recursive function `f` can not have a signal type, signals can not be defined \
+             recursively"
+        );
+    }
+
+    #[test]
+    fn test_case() {
+        let shape = "type Shape = Circle int | Rect int int in ";
+        assert_eq!(
+            ty(&format!(
+                "{}\\s. case s of {{ Circle r -> 3 * r * r; Rect w h -> w * h }}\n",
+                shape
+            )),
+            "Shape -> int"
+        );
+        assert_eq!(
+            ty("type Maybe a = Nothing | Just a in \\d. \\m. case m of { Just x -> x; Nothing -> d }\n"),
+            "a -> (Maybe a -> a)"
+        );
+        assert_eq!(
+            ty("\\p. case p of { (0, b) -> b; (_, True) -> True; (n, False) -> n > 0 }\n"),
+            "(int, bool) -> bool"
+        );
+        assert_eq!(
+            ty("\\b. case b of { True -> 1; False -> 0 }\n"),
+            "bool -> int"
+        );
+        assert_eq!(ty("case 1 of { 0 -> 1; n -> n }\n"), "int");
+
+        // A let pattern must match every value
+        assert_eq!(
+            ty("type Point = Point int int in let (Point x y, z) = (Point 1 2, 3) in x + y + z\n"),
+            "int"
+        );
+        let e = error(&format!("{}let Circle r = Rect 1 2 in r\n", shape));
+        assert_eq!(
+            e.to_string(),
+            "the patterns are not exhaustive, `Rect _ _` is not matched"
+        );
+        assert_eq!((e.span().start, e.span().end), (46, 54));
+
+        let e = error(&format!("{}\\s. case s of {{ Circle r -> r }}\n", shape));
+        assert_eq!(
+            e.to_string(),
+            "the patterns are not exhaustive, `Rect _ _` is not matched"
+        );
+        assert_eq!((e.span().start, e.span().end), (46, 73));
+        assert_eq!(
+            error("type Maybe a = Nothing | Just a in \\m. case m of { Just (Just x) -> x; Nothing -> 0 }\n")
+                .to_string(),
+            "the patterns are not exhaustive, `Just Nothing` is not matched"
+        );
+        assert_eq!(
+            error("\\p. case p of { (True, _) -> 1; (_, True) -> 2 }\n").to_string(),
+            "the patterns are not exhaustive, `(False, False)` is not matched"
+        );
+        assert_eq!(
+            error("case 1 of { 0 -> 1; 1 -> 2 }\n").to_string(),
+            "the patterns are not exhaustive, `_` is not matched"
+        );
+
+        // A literal parses as the pattern of a `let`, but it can never match every value
+        assert_eq!(
+            err_at("let 1 = 1 + 2 in x\n"),
+            (
+                "the patterns are not exhaustive, `_` is not matched".to_owned(),
+                4,
+                5
+            )
+        );
+
+        let e = error("\\b. case b of { x -> 1; True -> 2 }\n");
+        assert_eq!(
+            e.to_string(),
+            "this pattern is unreachable, the patterns before it match all its values"
+        );
+        assert_eq!((e.span().start, e.span().end), (24, 28));
+        assert_eq!(
+            error("\\p. case p of { (True, _) -> 1; (_, 0) -> 2; (False, 0) -> 3; _ -> 4 }\n")
+                .to_string(),
+            "this pattern is unreachable, the patterns before it match all its values"
+        );
+
+        assert_eq!(
+            error(&format!(
+                "{}case Circle 1 of {{ Circle True -> 1; _ -> 2 }}\n",
+                shape
+            ))
+            .to_string(),
+            "expected `int` but this pattern has type `bool`"
+        );
+        assert_eq!(
+            error(&format!("{}case 1 of {{ Circle r -> r; _ -> 2 }}\n", shape)).to_string(),
+            "expected `int` but this pattern has type `Shape`"
+        );
+        assert_eq!(
+            error(&format!(
+                "{}case Circle 1 of {{ Circle r -> r; Rect w h -> True }}\n",
+                shape
+            ))
+            .to_string(),
+            "expected `int` but this branch has type `bool`"
+        );
+        assert_eq!(
+            error(&format!(
+                "{}\\s. case s of {{ Rect w -> w; _ -> 0 }}\n",
+                shape
+            ))
+            .to_string(),
+            "the constructor `Rect` expects 2 arguments but this pattern gives it 1"
+        );
+        assert_eq!(
+            error("\\s. case s of { Square w -> w }\n").to_string(),
+            "undefined constructor `Square`"
+        );
+        assert_eq!(
+            error("\\p. case p of { (x, x) -> x }\n").to_string(),
+            "the name `x` is bound more than once"
+        );
+    }
+
+    #[test]
+    fn test_list() {
+        assert_eq!(ty("[1, 2, 3]\n"), "list int");
+        assert_eq!(ty("[]\n"), "list a");
+        assert_eq!(ty("[[True], []]\n"), "list (list bool)");
+        assert_eq!(ty("\\x. x :: [1]\n"), "int -> list int");
+        assert_eq!(
+            ty("\\l: list (int, bool). l\n"),
+            "list (int, bool) -> list (int, bool)"
+        );
+
+        // The prelude
+        assert_eq!(ty("map\n"), "(a -> b) -> (list a -> list b)");
+        assert_eq!(ty("map (\\x. x > 1) [1, 2]\n"), "list bool");
+        assert_eq!(ty("foldl (\\x. \\sum. x + sum) 0 [1, 2]\n"), "int");
+        assert_eq!(ty("filter (\\x. x) [True]\n"), "list bool");
+        assert_eq!(
+            ty("(length [()], head [1], tail [1])\n"),
+            "(int, int, list int)"
+        );
+
+        // The history of a signal
+        assert_eq!(
+            ty("foldp (\\x. \\history. x :: history) [] MouseX\n"),
+            "signal list int."
+        );
+        assert_eq!(
+            ty("let h = foldp (\\x. \\h. x :: h) [] MouseClicks in lift1 length: h\n"),
+            "signal int."
+        );
+
+        assert_eq!(
+            ty("\\l. case l of { [] -> 0; [x] -> x; x :: y :: rest -> x + y }\n"),
+            "list int -> int"
+        );
+        assert_eq!(
+            err("\\l. case l of { [] -> 0; [x, y] -> x }\n"),
+            "the patterns are not exhaustive, `_ :: []` is not matched"
+        );
+        assert_eq!(
+            err("\\l. case l of { x :: xs -> x }\n"),
+            "the patterns are not exhaustive, `[]` is not matched"
+        );
+        assert_eq!(
+            err("\\l. case l of { _ :: _ -> 0; [x] -> x; [] -> 0 }\n"),
+            "this pattern is unreachable, the patterns before it match all its values"
+        );
+
+        assert_eq!(
+            err("[1, True]\n"),
+            "expected `int` but this element has type `bool`"
+        );
+        assert_eq!(
+            err("1 :: [True]\n"),
+            "expected `list int` but this operand has type `list bool`"
+        );
+        assert_eq!(
+            err("\\l. case l of { x :: 1 -> x; _ -> 0 }\n"),
+            "expected `list a` but this pattern has type `int`"
+        );
+
+        // Lists are simple values
+        assert_eq!(
+            err("[MouseX]\n"),
+            "expected a simple type but this expression has type `signal int.`"
+        );
+        assert_eq!(
+            err("map (\\x. x) MouseX\n"),
+            "expected `list a` but this argument has type `signal int.`"
+        );
+    }
+
+    #[test]
+    fn test_string() {
+        assert_eq!(ty("\"hello\"\n"), "string");
+        assert_eq!(ty("\\x: string. x ++ \"!\"\n"), "string -> string");
+        assert_eq!(ty("\\x. \"x = \" ++ (show x)\n"), "int -> string");
+        assert_eq!(ty("[1] ++ [2]\n"), "list int");
+        assert_eq!(ty("\"a\" == \"b\"\n"), "bool");
+        assert_eq!(
+            ty("lift1 (\\x. \"x = \" ++ (show x)): MouseX\n"),
+            "signal string."
+        );
+        assert_eq!(
+            ty("\\s. case s of { \"yes\" -> True; _ -> False }\n"),
+            "string -> bool"
+        );
+
+        assert_eq!(
+            err("\"x = \" ++ 1\n"),
+            "expected `string` but this operand has type `int`"
+        );
+        assert_eq!(
+            err("[1] ++ \"a\"\n"),
+            "expected `list int` but this operand has type `string`"
+        );
+        assert_eq!(
+            err("\\s. case s of { \"yes\" -> True }\n"),
+            "the patterns are not exhaustive, `_` is not matched"
+        );
+    }
+
+    #[test]
+    fn test_float() {
+        assert_eq!(ty("1.5 * 2e-1\n"), "float");
+        assert_eq!(ty("\\x: float. x / 2.0\n"), "float -> float");
+        assert_eq!(ty("\\x: float. \\y. x < y\n"), "float -> (float -> bool)");
+        assert_eq!(ty("1.0 == 1.0\n"), "bool");
+        assert_eq!(ty("\\x. x + 1\n"), "int -> int");
+        assert_eq!(ty("\\x. round ((toFloat x) * 0.5)\n"), "int -> int");
+        assert_eq!(
+            ty("lift1 (\\x. (toFloat x) / 100.0): MouseX\n"),
+            "signal float."
+        );
+
+        // There are no implicit conversions
+        assert_eq!(
+            err("1.5 + 1\n"),
+            "expected `float` but this operand has type `int`"
+        );
+        assert_eq!(
+            err("1 + 1.5\n"),
+            "expected `int` but this operand has type `float`"
+        );
+        assert_eq!(
+            err("round 1\n"),
+            "expected `float` but this argument has type `int`"
+        );
+    }
+
+    #[test]
+    fn test_program() {
+        let lower = |source: &str| {
+            let program = parse_program(source).unwrap();
+            format!("{:?}", typecheck_program(&program).unwrap())
+        };
+        let program_err = |source: &str| {
+            let program = parse_program(source).unwrap();
+            let error = typecheck_program(&program).unwrap_err();
+            (error.to_string(), error.span().start, error.span().end)
+        };
+
+        // The definitions are bound after the ones they use, in written order otherwise
+        assert_eq!(
+            lower("main = lift1 f: MouseX\nf x = x + y\ny = 1\nz = 2\n"),
+            "let \"y\" = 1 in let \"f\" = \\\"x\". -> (\"x\" + \"y\") in \
+             let \"main\" = lift1 \"f\" \"MouseX\"! in let \"z\" = 2 in \"main\""
+        );
+        assert_eq!(
+            lower("type T = A | B\nmain : signal int.\nmain = lift1 (\\x. case x of { A -> 0; B -> 1 }): s\ns = lift1 (\\x. A): MouseX\n"),
+            "type T = A | B in let \"s\" = lift1 \\\"x\". -> \"A\" \"MouseX\"! in \
+             let (\"main\": signal int.) = lift1 \\\"x\". -> case \"x\" of { A -> 0; B -> 1 } \"s\"! in \"main\""
+        );
+
+        // Local names shadow the definitions
+        assert!(
+            parse_program("x = \\x. x\nmain = let x = 1 in lift1 (\\y. y + x): MouseX\n")
+                .map(|p| typecheck_program(&p).is_ok())
+                .unwrap()
+        );
+
+        // A function can refer to itself, given a signature
+        let count = "count : int -> int\ncount n = if n < 1 then 0 else 1 + count (n - 1)\n";
+        assert!(lower(&format!("{}main = lift1 count: MouseX\n", count)).starts_with("let rec"));
+        assert_eq!(
+            program_err("f n = f n\nmain = MouseX\n"),
+            (
+                "the recursive function `f` needs a type annotation".to_owned(),
+                0,
+                1
+            )
+        );
+        assert_eq!(
+            program_err("a = b\nb = \\x. a x\nmain = MouseX\n"),
+            (
+                "the definitions of `a`, `b` depend on each other, only a function can refer to \
+                 itself"
+                    .to_owned(),
+                0,
+                1
+            )
+        );
+
+        assert_eq!(
+            program_err("x = 1\n"),
+            ("the program has no `main`".to_owned(), 0, 0)
+        );
+
+        // The type declarations are bound after the types they name
+        assert_eq!(
+            lower("main : Mouse\nmain = MouseX\ntype alias Mouse = Input int\ntype alias Input a = signal a.\n"),
+            "type alias Input a = signal a. in type alias Mouse = Input int in \
+             let (\"main\": Mouse) = \"MouseX\" in \"main\""
+        );
+        assert_eq!(
+            program_err("type alias A = list B\ntype alias B = (A, int)\nmain = MouseX\n"),
+            (
+                "the type aliases `A`, `B` refer to each other, only a type declared with `type` \
+                 can be recursive"
+                    .to_owned(),
+                11,
+                12
+            )
+        );
+        assert_eq!(
+            program_err("type A = A1 | A2 B\ntype B = B1 A\nmain = MouseX\n"),
+            (
+                "the types `A`, `B` refer to each other, a type declared with `type` may only \
+                 refer to itself"
+                    .to_owned(),
+                5,
+                6
+            )
+        );
+        assert_eq!(
+            program_err("type T = Leaf | Node P\ntype alias P = (T, T)\nmain = MouseX\n").0,
+            "the types `T`, `P` refer to each other, a type declared with `type` may only refer \
+             to itself"
+        );
+        assert!(parse_program(
+            "type List = Nil | Cons int List\nmain = lift1 (\\x. Cons x Nil): MouseX\n"
+        )
+        .map(|p| typecheck_program(&p).is_ok())
+        .unwrap());
+        assert!(
+            parse_program("type Shape = Circle Point\ntype alias Point = (int, int)\nmain = lift1 (\\x. Circle (x, x)): MouseX\n")
+                .map(|p| typecheck_program(&p).is_ok())
+                .unwrap()
+        );
+        assert_eq!(
+            program_err("x = 1\nmain = x\n"),
+            (
+                "`main` must be a signal, but it has type `int`".to_owned(),
+                6,
+                10
+            )
+        );
+        assert_eq!(
+            program_err("main = MouseX\nmain = MouseY\n"),
+            ("`main` is already defined".to_owned(), 14, 18)
+        );
+        assert_eq!(
+            program_err("x : int\nmain = MouseX\n"),
+            (
+                "the signature of `x` must be followed by its definition".to_owned(),
+                0,
+                1
+            )
+        );
+        // A signature is checked like the annotation of a `let`
+        assert_eq!(
+            program_err("x : bool\nx = 1\nmain = MouseX\n"),
+            (
+                "the annotation says `bool`, but the value has type `int`".to_owned(),
+                4,
+                8
+            )
+        );
+        assert_eq!(
+            program_err(
+                "f : int -> int\nf n = if n < 1 then False else (f (n - 1)) > 0\nmain = MouseX\n"
+            ),
+            (
+                "the annotation says `int -> int`, but the value has type `int -> bool`".to_owned(),
+                4,
+                14
+            )
+        );
     }
 }
@@ -0,0 +1,555 @@
+use rustelm_parser::ast::{SignalType, SimpleType, Types};
+use std::fmt;
+
+/// The types seen during inference. Unlike `ast::Types` they are not split into simple and
+/// signal types, the split is checked once the type variables are solved.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Var(usize),
+    Unit,
+    Int,
+    Bool,
+    Float,
+    String,
+    Arrow(Box<Type>, Box<Type>),
+    Tuple(Vec<Type>),
+    /// The fields of a record, sorted by name, and the type of its other fields if the record
+    /// is open. The rest is a type variable, or another record once it is solved.
+    Record(Vec<(String, Type)>, Option<Box<Type>>),
+    List(Box<Type>),
+    /// A declared type applied to its arguments, `Maybe int`.
+    Named(String, Vec<Type>),
+    Signal(Box<Type>),
+    /// A type alias applied to its arguments, written as a declared type, and the type it stands
+    /// for. Only printing tells them apart, everything else sees through the alias.
+    Alias(Box<Type>, Box<Type>),
+}
+
+impl Type {
+    pub fn arrow(param: Type, ret: Type) -> Type {
+        Type::Arrow(Box::new(param), Box::new(ret))
+    }
+
+    pub fn signal(ty: Type) -> Type {
+        Type::Signal(Box::new(ty))
+    }
+
+    pub fn list(ty: Type) -> Type {
+        Type::List(Box::new(ty))
+    }
+
+    pub fn record(mut fields: Vec<(String, Type)>, rest: Option<Type>) -> Type {
+        fields.sort_by(|a, b| a.0.cmp(&b.0));
+        Type::Record(fields, rest.map(Box::new))
+    }
+
+    /// Apply `f` to every type directly inside this one.
+    pub(crate) fn map(&self, mut f: impl FnMut(&Type) -> Type) -> Type {
+        match self {
+            Type::Arrow(param, ret) => Type::arrow(f(param), f(ret)),
+            Type::Tuple(types) => Type::Tuple(types.iter().map(f).collect()),
+            Type::Record(fields, rest) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), f(ty)))
+                    .collect();
+                Type::Record(fields, rest.as_ref().map(|rest| Box::new(f(rest))))
+            }
+            Type::List(ty) => Type::list(f(ty)),
+            Type::Named(name, args) => Type::Named(name.clone(), args.iter().map(f).collect()),
+            Type::Signal(ty) => Type::signal(f(ty)),
+            Type::Alias(alias, ty) => Type::Alias(Box::new(f(alias)), Box::new(f(ty))),
+            Type::Var(_) | Type::Unit | Type::Int | Type::Bool | Type::Float | Type::String => {
+                self.clone()
+            }
+        }
+    }
+
+    /// Whether this is a signal type, `signal t.` or a function returning one.
+    pub fn is_signal(&self) -> bool {
+        match self {
+            Type::Signal(_) => true,
+            Type::Arrow(_, ret) => ret.is_signal(),
+            Type::Alias(_, ty) => ty.is_signal(),
+            _ => false,
+        }
+    }
+
+    /// Whether a signal appears anywhere in this type.
+    pub fn has_signal(&self) -> bool {
+        match self {
+            Type::Signal(_) => true,
+            Type::Arrow(param, ret) => param.has_signal() || ret.has_signal(),
+            Type::Tuple(types) => types.iter().any(Type::has_signal),
+            Type::Record(fields, _) => fields.iter().any(|(_, ty)| ty.has_signal()),
+            Type::List(ty) => ty.has_signal(),
+            Type::Named(_, args) => args.iter().any(Type::has_signal),
+            Type::Alias(_, ty) => ty.has_signal(),
+            _ => false,
+        }
+    }
+
+    fn vars(&self, out: &mut Vec<usize>) {
+        match self {
+            Type::Var(v) => {
+                if !out.contains(v) {
+                    out.push(*v)
+                }
+            }
+            Type::Arrow(param, ret) => {
+                param.vars(out);
+                ret.vars(out);
+            }
+            Type::Tuple(types) | Type::Named(_, types) => types.iter().for_each(|ty| ty.vars(out)),
+            Type::Record(fields, rest) => {
+                fields.iter().for_each(|(_, ty)| ty.vars(out));
+                rest.iter().for_each(|ty| ty.vars(out));
+            }
+            Type::List(ty) | Type::Signal(ty) => ty.vars(out),
+            Type::Alias(alias, ty) => {
+                alias.vars(out);
+                ty.vars(out);
+            }
+            Type::Unit | Type::Int | Type::Bool | Type::Float | Type::String => {}
+        }
+    }
+
+    /// Rename the type variables in order of appearance, so that they print as `a`, `b`, ...
+    /// `names` is shared by types printed together.
+    pub fn rename(&self, names: &mut Vec<usize>) -> Type {
+        match self {
+            Type::Var(v) => match names.iter().position(|n| n == v) {
+                Some(i) => Type::Var(i),
+                None => {
+                    names.push(*v);
+                    Type::Var(names.len() - 1)
+                }
+            },
+            // In the order they are printed, the rest of a record comes first
+            Type::Record(fields, rest) => {
+                let rest = rest.as_ref().map(|rest| Box::new(rest.rename(names)));
+                let fields = fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), ty.rename(names)))
+                    .collect();
+                Type::Record(fields, rest)
+            }
+            _ => self.map(|ty| ty.rename(names)),
+        }
+    }
+
+    /// The type this one stands for, seen through the aliases at its top.
+    pub fn unaliased(self) -> Type {
+        match self {
+            Type::Alias(_, ty) => ty.unaliased(),
+            ty => ty,
+        }
+    }
+
+    /// The same type with every alias replaced by the type it stands for.
+    pub fn expand(&self) -> Type {
+        match self {
+            Type::Alias(_, ty) => ty.expand(),
+            _ => self.map(Type::expand),
+        }
+    }
+
+    /// Convert an annotation, annotations never mention type variables.
+    pub fn from_ast(ty: &Types) -> Type {
+        Type::from_ast_with(ty, &mut |_| unreachable!())
+    }
+
+    pub fn from_simple(ty: &SimpleType) -> Type {
+        Type::from_simple_with(ty, &mut |_| unreachable!())
+    }
+
+    /// Convert a type that may mention type variables, each of them replaced by `var(name)`.
+    pub fn from_ast_with(ty: &Types, var: &mut dyn FnMut(&str) -> Type) -> Type {
+        match ty {
+            Types::Simple(ty) => Type::from_simple_with(ty, var),
+            Types::Signal(ty) => Type::from_signal_with(ty, var),
+        }
+    }
+
+    fn from_simple_with(ty: &SimpleType, var: &mut dyn FnMut(&str) -> Type) -> Type {
+        match ty {
+            SimpleType::Unit => Type::Unit,
+            SimpleType::Int => Type::Int,
+            SimpleType::Bool => Type::Bool,
+            SimpleType::Float => Type::Float,
+            SimpleType::String => Type::String,
+            SimpleType::Abs(param, ret) => Type::arrow(
+                Type::from_simple_with(param, var),
+                Type::from_simple_with(ret, var),
+            ),
+            SimpleType::Tuple(types) => Type::Tuple(
+                types
+                    .iter()
+                    .map(|ty| Type::from_simple_with(ty, var))
+                    .collect(),
+            ),
+            SimpleType::Record(fields, rest) => Type::record(
+                fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), Type::from_simple_with(ty, var)))
+                    .collect(),
+                rest.as_ref().map(|rest| var(rest)),
+            ),
+            SimpleType::List(ty) => Type::list(Type::from_simple_with(ty, var)),
+            SimpleType::Named(name, args) => Type::Named(
+                name.clone(),
+                args.iter()
+                    .map(|ty| Type::from_simple_with(ty, var))
+                    .collect(),
+            ),
+            SimpleType::Var(name) => var(name),
+        }
+    }
+
+    fn from_signal_with(ty: &SignalType, var: &mut dyn FnMut(&str) -> Type) -> Type {
+        match ty {
+            SignalType::Signal(ty) => Type::signal(Type::from_simple_with(ty, var)),
+            SignalType::Abs1(param, ret) => Type::arrow(
+                Type::from_simple_with(param, var),
+                Type::from_signal_with(ret, var),
+            ),
+            SignalType::Abs2(param, ret) => Type::arrow(
+                Type::from_signal_with(param, var),
+                Type::from_signal_with(ret, var),
+            ),
+            SignalType::Named(name, args) => Type::Named(
+                name.clone(),
+                args.iter()
+                    .map(|ty| Type::from_simple_with(ty, var))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Convert back to a stratified type, or `None` if this type takes a signal but returns a
+    /// simple type, or carries a signal of signals. The variables should be renamed first.
+    pub fn to_ast(&self) -> Option<Types> {
+        if self.is_signal() {
+            self.to_signal().map(Types::Signal)
+        } else {
+            self.to_simple().map(Types::Simple)
+        }
+    }
+
+    fn to_simple(&self) -> Option<SimpleType> {
+        match self {
+            Type::Var(v) => Some(SimpleType::Var(var_name(*v))),
+            Type::Unit => Some(SimpleType::Unit),
+            Type::Int => Some(SimpleType::Int),
+            Type::Bool => Some(SimpleType::Bool),
+            Type::Float => Some(SimpleType::Float),
+            Type::String => Some(SimpleType::String),
+            Type::Arrow(param, ret) => Some(SimpleType::Abs(
+                Box::new(param.to_simple()?),
+                Box::new(ret.to_simple()?),
+            )),
+            Type::Tuple(types) => Some(SimpleType::Tuple(
+                types.iter().map(Type::to_simple).collect::<Option<_>>()?,
+            )),
+            Type::Record(fields, rest) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, ty)| Some((name.clone(), ty.to_simple()?)))
+                    .collect::<Option<_>>()?;
+                let rest = match rest.as_deref() {
+                    Some(Type::Var(v)) => Some(var_name(*v)),
+                    Some(_) => return None,
+                    None => None,
+                };
+                Some(SimpleType::Record(fields, rest))
+            }
+            Type::List(ty) => Some(SimpleType::List(Box::new(ty.to_simple()?))),
+            Type::Named(name, args) => Some(SimpleType::Named(
+                name.clone(),
+                args.iter().map(Type::to_simple).collect::<Option<_>>()?,
+            )),
+            Type::Signal(_) => None,
+            Type::Alias(alias, ty) => {
+                ty.to_simple()?;
+                alias.to_simple()
+            }
+        }
+    }
+
+    fn to_signal(&self) -> Option<SignalType> {
+        match self {
+            Type::Signal(ty) => Some(SignalType::Signal(ty.to_simple()?)),
+            Type::Arrow(param, ret) if param.is_signal() => Some(SignalType::Abs2(
+                Box::new(param.to_signal()?),
+                Box::new(ret.to_signal()?),
+            )),
+            Type::Arrow(param, ret) => Some(SignalType::Abs1(
+                param.to_simple()?,
+                Box::new(ret.to_signal()?),
+            )),
+            Type::Alias(alias, ty) => match (ty.to_signal()?, alias.to_simple()?) {
+                (_, SimpleType::Named(name, args)) => Some(SignalType::Named(name, args)),
+                _ => unreachable!("an alias is written as a declared type"),
+            },
+            _ => None,
+        }
+    }
+}
+
+fn var_name(v: usize) -> String {
+    let letter = (b'a' + (v % 26) as u8) as char;
+    match v / 26 {
+        0 => letter.to_string(),
+        n => format!("{}{}", letter, n),
+    }
+}
+
+impl Type {
+    fn fmt_operand(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Arrow(_, _) => write!(f, "({})", self),
+            _ => write!(f, "{}", self),
+        }
+    }
+
+    /// Write the type as the argument of a declared type or a list, in parentheses unless it is
+    /// atomic.
+    fn fmt_arg(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Named(_, args) if !args.is_empty() => write!(f, "({})", self),
+            Type::Alias(alias, _) => alias.fmt_arg(f),
+            Type::List(_) | Type::Signal(_) => write!(f, "({})", self),
+            _ => self.fmt_operand(f),
+        }
+    }
+}
+
+/// Printed the same way as `ast::Types`, e.g. `int -> signal int.`.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Var(v) => write!(f, "{}", var_name(*v)),
+            Type::Unit => write!(f, "unit"),
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Arrow(param, ret) => {
+                param.fmt_operand(f)?;
+                write!(f, " -> ")?;
+                ret.fmt_operand(f)
+            }
+            Type::Tuple(types) => {
+                write!(f, "(")?;
+                for (i, ty) in types.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", ty)?;
+                }
+                write!(f, ")")
+            }
+            Type::Record(fields, rest) => {
+                if fields.is_empty() && rest.is_none() {
+                    return write!(f, "{{}}");
+                }
+                write!(f, "{{ ")?;
+                if let Some(rest) = rest {
+                    write!(f, "{} | ", rest)?;
+                }
+                for (i, (name, ty)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{} : {}", name, ty)?;
+                }
+                write!(f, " }}")
+            }
+            Type::List(ty) => {
+                write!(f, "list ")?;
+                ty.fmt_arg(f)
+            }
+            Type::Alias(alias, _) => write!(f, "{}", alias),
+            Type::Named(name, args) => {
+                write!(f, "{}", name)?;
+                for arg in args {
+                    write!(f, " ")?;
+                    arg.fmt_arg(f)?;
+                }
+                Ok(())
+            }
+            Type::Signal(ty) => {
+                write!(f, "signal ")?;
+                ty.fmt_operand(f)?;
+                write!(f, ".")
+            }
+        }
+    }
+}
+
+/// A type generalized over some of its variables, `forall a b. t`.
+#[derive(Clone, Debug)]
+pub struct Scheme {
+    pub vars: Vec<usize>,
+    pub ty: Type,
+}
+
+impl Scheme {
+    /// A type that is not generalized over any variable.
+    pub fn mono(ty: Type) -> Scheme {
+        Scheme { vars: vec![], ty }
+    }
+}
+
+/// The solved type variables.
+#[derive(Default)]
+pub struct Substitution {
+    bindings: Vec<Option<Type>>,
+}
+
+impl Substitution {
+    pub fn fresh(&mut self) -> Type {
+        self.bindings.push(None);
+        Type::Var(self.bindings.len() - 1)
+    }
+
+    /// Apply the substitution all the way down.
+    pub fn resolve(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(v) => match &self.bindings[*v] {
+                Some(ty) => self.resolve(ty),
+                None => ty.clone(),
+            },
+            // A solved rest contributes its fields to the record
+            Type::Record(fields, Some(rest)) => match self.resolve(rest).unaliased() {
+                Type::Record(more, rest) => {
+                    let mut fields: Vec<_> = fields
+                        .iter()
+                        .map(|(name, ty)| (name.clone(), self.resolve(ty)))
+                        .collect();
+                    fields.extend(more);
+                    Type::record(fields, rest.map(|rest| *rest))
+                }
+                rest => Type::record(
+                    fields
+                        .iter()
+                        .map(|(name, ty)| (name.clone(), self.resolve(ty)))
+                        .collect(),
+                    Some(rest),
+                ),
+            },
+            _ => ty.map(|ty| self.resolve(ty)),
+        }
+    }
+
+    /// Make both types equal, or return `false` if they can not be. On failure some variables
+    /// may have been bound already.
+    pub fn unify(&mut self, t1: &Type, t2: &Type) -> bool {
+        match (self.resolve(t1), self.resolve(t2)) {
+            (Type::Var(v1), Type::Var(v2)) if v1 == v2 => true,
+            (Type::Var(v), ty) | (ty, Type::Var(v)) => {
+                // The occurs check, `a = a -> a` has no finite solution.
+                let mut vars = vec![];
+                ty.vars(&mut vars);
+                if vars.contains(&v) {
+                    return false;
+                }
+                self.bindings[v] = Some(ty);
+                true
+            }
+            (Type::Alias(_, t1), t2) | (t2, Type::Alias(_, t1)) => self.unify(&t1, &t2),
+            (Type::Unit, Type::Unit)
+            | (Type::Int, Type::Int)
+            | (Type::Bool, Type::Bool)
+            | (Type::Float, Type::Float)
+            | (Type::String, Type::String) => true,
+            (Type::Arrow(p1, r1), Type::Arrow(p2, r2)) => {
+                self.unify(&p1, &p2) && self.unify(&r1, &r2)
+            }
+            (Type::Tuple(ts1), Type::Tuple(ts2)) if ts1.len() == ts2.len() => {
+                ts1.iter().zip(&ts2).all(|(t1, t2)| self.unify(t1, t2))
+            }
+            (Type::Record(fs1, r1), Type::Record(fs2, r2)) => self.unify_records(fs1, r1, fs2, r2),
+            (Type::Named(n1, args1), Type::Named(n2, args2)) if n1 == n2 => {
+                args1.iter().zip(&args2).all(|(t1, t2)| self.unify(t1, t2))
+            }
+            (Type::List(t1), Type::List(t2)) | (Type::Signal(t1), Type::Signal(t2)) => {
+                self.unify(&t1, &t2)
+            }
+            _ => false,
+        }
+    }
+
+    /// Unify two resolved records: the fields they share must agree, and the fields only one of
+    /// them has must fit in the rest of the other one.
+    fn unify_records(
+        &mut self,
+        fs1: Vec<(String, Type)>,
+        r1: Option<Box<Type>>,
+        fs2: Vec<(String, Type)>,
+        r2: Option<Box<Type>>,
+    ) -> bool {
+        let only1: Vec<_> = fs1
+            .iter()
+            .filter(|(name, _)| !fs2.iter().any(|(other, _)| other == name))
+            .cloned()
+            .collect();
+        let only2: Vec<_> = fs2
+            .iter()
+            .filter(|(name, _)| !fs1.iter().any(|(other, _)| other == name))
+            .cloned()
+            .collect();
+
+        for (name, t1) in &fs1 {
+            if let Some((_, t2)) = fs2.iter().find(|(other, _)| other == name) {
+                if !self.unify(t1, t2) {
+                    return false;
+                }
+            }
+        }
+
+        match (r1, r2) {
+            (None, None) => only1.is_empty() && only2.is_empty(),
+            (Some(r1), None) => only1.is_empty() && self.unify(&r1, &Type::record(only2, None)),
+            (None, Some(r2)) => only2.is_empty() && self.unify(&r2, &Type::record(only1, None)),
+            (Some(r1), Some(r2)) if r1 == r2 => only1.is_empty() && only2.is_empty(),
+            (Some(r1), Some(r2)) => {
+                let rest = self.fresh();
+                self.unify(&r1, &Type::record(only2, Some(rest.clone())))
+                    && self.unify(&r2, &Type::record(only1, Some(rest)))
+            }
+        }
+    }
+
+    /// Replace the generalized variables of `scheme` by fresh ones.
+    pub fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let fresh: Vec<_> = scheme.vars.iter().map(|v| (*v, self.fresh())).collect();
+        substitute(&self.resolve(&scheme.ty), &fresh)
+    }
+
+    /// Generalize `ty` over the variables that are not bound in the environment.
+    pub fn generalize<'a>(&self, env: impl Iterator<Item = &'a Scheme>, ty: &Type) -> Scheme {
+        let mut env_vars = vec![];
+        for scheme in env {
+            let mut vars = vec![];
+            self.resolve(&scheme.ty).vars(&mut vars);
+            env_vars.extend(vars.into_iter().filter(|v| !scheme.vars.contains(v)));
+        }
+
+        let ty = self.resolve(ty);
+        let mut vars = vec![];
+        ty.vars(&mut vars);
+        vars.retain(|v| !env_vars.contains(v));
+        Scheme { vars, ty }
+    }
+}
+
+pub(crate) fn substitute(ty: &Type, map: &[(usize, Type)]) -> Type {
+    match ty {
+        Type::Var(v) => match map.iter().find(|(from, _)| from == v) {
+            Some((_, to)) => to.clone(),
+            None => ty.clone(),
+        },
+        _ => ty.map(|ty| substitute(ty, map)),
+    }
+}
@@ -0,0 +1,87 @@
+use std::{error, fmt, fmt::Formatter};
+
+#[derive(Debug, Clone)]
+pub struct RuntimeError(pub RuntimeErrorType);
+
+#[derive(Debug, Clone)]
+pub enum RuntimeErrorType {
+    DivisionByZero,
+    UndefinedName,
+    ExpectIdentifier,
+    ExpectFunction,
+    ExpectInteger,
+    ExpectFloat,
+    NotFinite,
+    ExpectBool,
+    ExpectString,
+    NoMatch,
+    ExpectRecord,
+    ExpectList,
+    EmptyList,
+    ExpectSignal,
+    UndefinedInput,
+    Disconnected,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use RuntimeErrorType::*;
+        match self.0 {
+            DivisionByZero => write!(f, "division by zero"),
+            UndefinedName => write!(f, "undefined variable"),
+            ExpectIdentifier => write!(f, "expect identifier"),
+            ExpectFunction => write!(f, "expect function"),
+            ExpectInteger => write!(f, "expect integer"),
+            ExpectFloat => write!(f, "expect float"),
+            NotFinite => write!(f, "the float is not a finite number"),
+            ExpectBool => write!(f, "expect bool"),
+            ExpectString => write!(f, "expect string"),
+            NoMatch => write!(f, "no pattern matches the value"),
+            ExpectRecord => write!(f, "expect record"),
+            ExpectList => write!(f, "expect list"),
+            EmptyList => write!(f, "the list is empty"),
+            ExpectSignal => write!(f, "expect signal"),
+            UndefinedInput => write!(f, "undefined input signal"),
+            Disconnected => write!(f, "the runtime has stopped"),
+        }
+    }
+}
+
+impl error::Error for RuntimeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+/// An error in an event trace, along with the line it occurred on.
+#[derive(Debug, Clone)]
+pub struct TraceError(pub usize, pub TraceErrorType);
+
+#[derive(Debug, Clone)]
+pub enum TraceErrorType {
+    UndefinedInput,
+    MissingValue,
+    UnexpectedValue,
+    InvalidValue,
+    InvalidRow,
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use TraceErrorType::*;
+        write!(f, "line {}: ", self.0)?;
+        match self.1 {
+            UndefinedInput => write!(f, "undefined input signal"),
+            MissingValue => write!(f, "missing value for input"),
+            UnexpectedValue => write!(f, "input does not take a value"),
+            InvalidValue => write!(f, "invalid value for input"),
+            InvalidRow => write!(f, "expected a row `input,value`"),
+        }
+    }
+}
+
+impl error::Error for TraceError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
@@ -0,0 +1,689 @@
+use crate::error::{RuntimeError, RuntimeErrorType};
+use crate::value::{Builtin, Env, Signal, Value};
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Signed, ToPrimitive, Zero};
+use rustelm_analyzer::typechecker::{INPUTS, PRELUDE};
+use rustelm_parser::ast::{Atom, BinOp, Expr, ExprKind, Located, Pattern, UnOp};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// The main entry to do evaluation. The root expression is reduced to a value using big-step
+/// (call-by-value) semantics, in an environment where every input is bound to its signal and
+/// the functions of the prelude are defined. Signal expressions are not run here, they reduce to
+/// signal terms.
+pub fn eval_root(root: &Expr) -> Result<Value, RuntimeError> {
+    let mut env: Env = INPUTS
+        .keys()
+        .map(|name| {
+            let input = Value::Signal(Arc::new(Signal::Input(name.clone())));
+            (name.clone(), input)
+        })
+        .collect();
+    for name in PRELUDE.keys() {
+        let builtin =
+            Builtin::from_name(name).ok_or(RuntimeError(RuntimeErrorType::UndefinedName))?;
+        env.insert(name.clone(), Value::Builtin(builtin, vec![]));
+    }
+
+    eval(&env, root)
+}
+
+fn get_value_from_env(env: &Env, name: &str) -> Result<Value, RuntimeError> {
+    match env.get(name) {
+        None => Err(RuntimeError(RuntimeErrorType::UndefinedName)),
+        Some(value) => Ok(value.clone()),
+    }
+}
+
+fn eval(env: &Env, term: &Expr) -> Result<Value, RuntimeError> {
+    match &term.node {
+        ExprKind::Const(atom) => match &atom.node {
+            Atom::Unit => Ok(Value::Unit),
+            Atom::Num(n) => Ok(Value::Int(n.clone())),
+            Atom::Float(f) => Ok(Value::Float(*f)),
+            Atom::Bool(b) => Ok(Value::Bool(*b)),
+            Atom::Str(s) => Ok(Value::Str(s.clone())),
+            Atom::Var(name) | Atom::Signal(name) => get_value_from_env(env, name),
+            Atom::Wildcard => Err(RuntimeError(RuntimeErrorType::ExpectIdentifier)),
+        },
+        ExprKind::Abs(atom, _, body) => {
+            let param = match &atom.node {
+                Atom::Var(name) => Some(name.clone()),
+                Atom::Wildcard => None,
+                _ => return Err(RuntimeError(RuntimeErrorType::ExpectIdentifier)),
+            };
+            Ok(Value::Closure(
+                param,
+                Arc::new((**body).clone()),
+                env.clone(),
+            ))
+        }
+        ExprKind::Lambda(_, _) => unreachable!("removed by desugaring"),
+        ExprKind::App(e1, e2) => {
+            let func = eval(env, e1)?;
+            let arg = eval(env, e2)?;
+            apply(func, arg)
+        }
+        ExprKind::BinOp(e1, op @ BinOp::And, e2) | ExprKind::BinOp(e1, op @ BinOp::Or, e2) => {
+            // The right operand is only evaluated when the left one does not decide the result.
+            match (op, expect_bool(eval(env, e1)?)?) {
+                (BinOp::And, false) => Ok(Value::Bool(false)),
+                (BinOp::Or, true) => Ok(Value::Bool(true)),
+                _ => eval(env, e2),
+            }
+        }
+        ExprKind::BinOp(e1, BinOp::Cons, e2) => match eval(env, e2)? {
+            Value::List(mut values) => {
+                values.insert(0, eval(env, e1)?);
+                Ok(Value::List(values))
+            }
+            _ => Err(RuntimeError(RuntimeErrorType::ExpectList)),
+        },
+        ExprKind::BinOp(e1, BinOp::Append, e2) => match (eval(env, e1)?, eval(env, e2)?) {
+            (Value::Str(l), Value::Str(r)) => Ok(Value::Str(l + &r)),
+            (Value::List(mut l), Value::List(r)) => {
+                l.extend(r);
+                Ok(Value::List(l))
+            }
+            _ => Err(RuntimeError(RuntimeErrorType::ExpectString)),
+        },
+        ExprKind::BinOp(e1, op, e2) => match (eval(env, e1)?, eval(env, e2)?) {
+            (Value::Int(l), Value::Int(r)) => eval_binop(*op, l, r),
+            (Value::Float(l), Value::Float(r)) => eval_float_binop(*op, l, r),
+            (Value::Bool(l), Value::Bool(r)) => match op {
+                BinOp::Eq => Ok(Value::Bool(l == r)),
+                BinOp::Ne => Ok(Value::Bool(l != r)),
+                _ => Err(RuntimeError(RuntimeErrorType::ExpectInteger)),
+            },
+            (Value::Str(l), Value::Str(r)) => match op {
+                BinOp::Eq => Ok(Value::Bool(l == r)),
+                BinOp::Ne => Ok(Value::Bool(l != r)),
+                _ => Err(RuntimeError(RuntimeErrorType::ExpectInteger)),
+            },
+            _ => Err(RuntimeError(RuntimeErrorType::ExpectInteger)),
+        },
+        ExprKind::UnOp(UnOp::Not, e) => Ok(Value::Bool(!expect_bool(eval(env, e)?)?)),
+        ExprKind::UnOp(UnOp::Neg, e) => match eval(env, e)? {
+            Value::Int(n) => Ok(Value::Int(-n)),
+            Value::Float(f) => Ok(Value::Float(-f)),
+            _ => Err(RuntimeError(RuntimeErrorType::ExpectInteger)),
+        },
+        ExprKind::If(e1, e2, e3) => {
+            if expect_bool(eval(env, e1)?)? {
+                eval(env, e2)
+            } else {
+                eval(env, e3)
+            }
+        }
+        ExprKind::Let(pattern, e1, e2) => {
+            // Evaluate the bound expression first, then evaluate the body in the extended
+            // environment.
+            let value = eval(env, e1)?;
+            match bind(env.clone(), pattern, value) {
+                Some(env) => eval(&env, e2),
+                None => Err(RuntimeError(RuntimeErrorType::NoMatch)),
+            }
+        }
+        ExprKind::LetRec(name, _, e1, e2) => {
+            // The function only refers to itself once it is applied
+            let func = Value::Recursive(name.node.clone(), Arc::new(eval(env, e1)?));
+            eval(&env.update(name.node.clone(), func), e2)
+        }
+        ExprKind::TypeDef(def, e) => {
+            // Bind each constructor, the ones without arguments are values already
+            let mut env = env.clone();
+            for constructor in &def.constructors {
+                let name = constructor.name.node.clone();
+                let value = match constructor.args.len() {
+                    0 => Value::Data(name.clone(), vec![]),
+                    arity => Value::Constructor(name.clone(), arity, vec![]),
+                };
+                env.insert(name, value);
+            }
+            eval(&env, e)
+        }
+        // Aliases only matter to the typechecker
+        ExprKind::TypeAlias(_, e) => eval(env, e),
+        ExprKind::Case(e, branches) => {
+            // Take the first branch whose pattern matches
+            let value = eval(env, e)?;
+            for (pattern, body) in branches {
+                if let Some(env) = bind(env.clone(), pattern, value.clone()) {
+                    return eval(&env, body);
+                }
+            }
+            Err(RuntimeError(RuntimeErrorType::NoMatch))
+        }
+        ExprKind::Tuple(es) => {
+            let values = es.iter().map(|e| eval(env, e)).collect::<Result<_, _>>()?;
+            Ok(Value::Tuple(values))
+        }
+        ExprKind::List(es) => {
+            let values = es.iter().map(|e| eval(env, e)).collect::<Result<_, _>>()?;
+            Ok(Value::List(values))
+        }
+        ExprKind::Record(fields) => {
+            let mut record = BTreeMap::new();
+            for (field, e) in fields {
+                record.insert(field.node.clone(), eval(env, e)?);
+            }
+            Ok(Value::Record(record))
+        }
+        ExprKind::Field(e, field) => match eval(env, e)? {
+            Value::Record(mut record) => record
+                .remove(&field.node)
+                .ok_or(RuntimeError(RuntimeErrorType::ExpectRecord)),
+            _ => Err(RuntimeError(RuntimeErrorType::ExpectRecord)),
+        },
+        ExprKind::Update(e, fields) => match eval(env, e)? {
+            Value::Record(mut record) => {
+                for (field, e) in fields {
+                    record.insert(field.node.clone(), eval(env, e)?);
+                }
+                Ok(Value::Record(record))
+            }
+            _ => Err(RuntimeError(RuntimeErrorType::ExpectRecord)),
+        },
+        ExprKind::Lift(_, expr, vec) => {
+            let func = eval(env, expr)?;
+
+            let mut args = vec![];
+            for atom in vec {
+                match &atom.node {
+                    Atom::Var(name) => args.push(expect_signal(get_value_from_env(env, name)?)?),
+                    _ => return Err(RuntimeError(RuntimeErrorType::ExpectIdentifier)),
+                }
+            }
+
+            Ok(Value::Signal(Arc::new(Signal::Lift(func, args))))
+        }
+        ExprKind::Foldp(e1, e2, e3) => {
+            let func = eval(env, e1)?;
+            let init = eval(env, e2)?;
+            let signal = expect_signal(eval(env, e3)?)?;
+            Ok(Value::Signal(Arc::new(Signal::Foldp(func, init, signal))))
+        }
+        ExprKind::Async(e) => {
+            let signal = expect_signal(eval(env, e)?)?;
+            Ok(Value::Signal(Arc::new(Signal::Async(signal))))
+        }
+    }
+}
+
+/// Bind the names in `pattern` to the matching parts of `value`, or return `None` if the
+/// pattern does not match.
+fn bind(env: Env, pattern: &Located<Pattern>, value: Value) -> Option<Env> {
+    match (&pattern.node, value) {
+        (Pattern::Var(name), value) => Some(env.update(name.clone(), value)),
+        (Pattern::Wildcard, _) => Some(env),
+        (Pattern::Literal(Atom::Unit), Value::Unit) => Some(env),
+        (Pattern::Literal(Atom::Num(n)), Value::Int(value)) if *n == value => Some(env),
+        (Pattern::Literal(Atom::Bool(b)), Value::Bool(value)) if *b == value => Some(env),
+        (Pattern::Literal(Atom::Str(s)), Value::Str(value)) if *s == value => Some(env),
+        (Pattern::Tuple(patterns), Value::Tuple(values)) if patterns.len() == values.len() => {
+            patterns
+                .iter()
+                .zip(values)
+                .try_fold(env, |env, (pattern, value)| bind(env, pattern, value))
+        }
+        (Pattern::List(patterns), Value::List(values)) if patterns.len() == values.len() => {
+            patterns
+                .iter()
+                .zip(values)
+                .try_fold(env, |env, (pattern, value)| bind(env, pattern, value))
+        }
+        (Pattern::Cons(head, tail), Value::List(mut values)) if !values.is_empty() => {
+            let first = values.remove(0);
+            bind(bind(env, head, first)?, tail, Value::List(values))
+        }
+        (Pattern::Constructor(name, patterns), Value::Data(ctor, values)) if *name == ctor => {
+            patterns
+                .iter()
+                .zip(values)
+                .try_fold(env, |env, (pattern, value)| bind(env, pattern, value))
+        }
+        (Pattern::Annotated(pattern, _), value) => bind(env, pattern, value),
+        _ => None,
+    }
+}
+
+fn expect_bool(value: Value) -> Result<bool, RuntimeError> {
+    match value {
+        Value::Bool(b) => Ok(b),
+        _ => Err(RuntimeError(RuntimeErrorType::ExpectBool)),
+    }
+}
+
+fn expect_signal(value: Value) -> Result<Arc<Signal>, RuntimeError> {
+    match value {
+        Value::Signal(signal) => Ok(signal),
+        _ => Err(RuntimeError(RuntimeErrorType::ExpectSignal)),
+    }
+}
+
+/// The environment of a closure once its parameter is bound to `arg`.
+fn bind_param(env: Env, param: Option<String>, arg: Value) -> Env {
+    match param {
+        Some(name) => env.update(name, arg),
+        None => env,
+    }
+}
+
+/// Apply a closure, a constructor or a built-in function to an argument.
+pub fn apply(func: Value, arg: Value) -> Result<Value, RuntimeError> {
+    match func {
+        Value::Closure(param, body, env) => eval(&bind_param(env, param, arg), &body),
+        Value::Recursive(ref name, ref func) => match &**func {
+            Value::Closure(param, body, env) => {
+                let env = env.update(name.clone(), Value::Recursive(name.clone(), func.clone()));
+                eval(&bind_param(env, param.clone(), arg), body)
+            }
+            _ => Err(RuntimeError(RuntimeErrorType::ExpectFunction)),
+        },
+        Value::Constructor(name, arity, mut args) => {
+            args.push(arg);
+            if args.len() == arity {
+                Ok(Value::Data(name, args))
+            } else {
+                Ok(Value::Constructor(name, arity, args))
+            }
+        }
+        Value::Builtin(builtin, mut args) => {
+            args.push(arg);
+            if args.len() == builtin.arity() {
+                call(builtin, args)
+            } else {
+                Ok(Value::Builtin(builtin, args))
+            }
+        }
+        _ => Err(RuntimeError(RuntimeErrorType::ExpectFunction)),
+    }
+}
+
+/// Run a built-in function on all of its arguments.
+fn call(builtin: Builtin, mut args: Vec<Value>) -> Result<Value, RuntimeError> {
+    // All the other functions take a list last
+    let list = match (builtin, args.pop().unwrap()) {
+        (Builtin::Show, Value::Int(n)) => return Ok(Value::Str(n.to_string())),
+        (Builtin::ToFloat, Value::Int(n)) => return Ok(Value::Float(n.to_f64().unwrap())),
+        // Halfway cases round away from zero
+        (Builtin::Round, Value::Float(f)) => {
+            return BigInt::from_f64(f.round())
+                .map(Value::Int)
+                .ok_or(RuntimeError(RuntimeErrorType::NotFinite))
+        }
+        (Builtin::Show, _) | (Builtin::ToFloat, _) => {
+            return Err(RuntimeError(RuntimeErrorType::ExpectInteger))
+        }
+        (Builtin::Round, _) => return Err(RuntimeError(RuntimeErrorType::ExpectFloat)),
+        (_, value) => expect_list(value)?,
+    };
+    match builtin {
+        Builtin::Map => {
+            let func = args.remove(0);
+            let values = list
+                .into_iter()
+                .map(|value| apply(func.clone(), value))
+                .collect::<Result<_, _>>()?;
+            Ok(Value::List(values))
+        }
+        Builtin::Foldl => {
+            let (func, init) = (args.remove(0), args.remove(0));
+            list.into_iter()
+                .try_fold(init, |acc, value| apply(apply(func.clone(), value)?, acc))
+        }
+        Builtin::Filter => {
+            let func = args.remove(0);
+            let mut values = vec![];
+            for value in list {
+                if expect_bool(apply(func.clone(), value.clone())?)? {
+                    values.push(value);
+                }
+            }
+            Ok(Value::List(values))
+        }
+        Builtin::Length => Ok(Value::Int(BigInt::from(list.len()))),
+        Builtin::Head => list
+            .into_iter()
+            .next()
+            .ok_or(RuntimeError(RuntimeErrorType::EmptyList)),
+        Builtin::Tail => match list.len() {
+            0 => Err(RuntimeError(RuntimeErrorType::EmptyList)),
+            _ => Ok(Value::List(list[1..].to_vec())),
+        },
+        Builtin::Show | Builtin::ToFloat | Builtin::Round => unreachable!(),
+    }
+}
+
+fn expect_list(value: Value) -> Result<Vec<Value>, RuntimeError> {
+    match value {
+        Value::List(values) => Ok(values),
+        _ => Err(RuntimeError(RuntimeErrorType::ExpectList)),
+    }
+}
+
+fn eval_binop(op: BinOp, l: BigInt, r: BigInt) -> Result<Value, RuntimeError> {
+    match op {
+        BinOp::Mul => Ok(Value::Int(l * r)),
+        BinOp::Div | BinOp::Rem | BinOp::Mod if r.is_zero() => {
+            Err(RuntimeError(RuntimeErrorType::DivisionByZero))
+        }
+        // Truncates toward zero, `-7 / 2` is `-3`
+        BinOp::Div => Ok(Value::Int(l / r)),
+        // Takes the sign of the dividend, `-7 % 2` is `-1`
+        BinOp::Rem => Ok(Value::Int(l % r)),
+        // Takes the sign of the divisor, `-7 mod 2` is `1`
+        BinOp::Mod => {
+            let rem = &l % &r;
+            if !rem.is_zero() && rem.is_negative() != r.is_negative() {
+                Ok(Value::Int(rem + r))
+            } else {
+                Ok(Value::Int(rem))
+            }
+        }
+        BinOp::Add => Ok(Value::Int(l + r)),
+        BinOp::Sub => Ok(Value::Int(l - r)),
+        BinOp::Eq => Ok(Value::Bool(l == r)),
+        BinOp::Ne => Ok(Value::Bool(l != r)),
+        BinOp::Le => Ok(Value::Bool(l <= r)),
+        BinOp::Ge => Ok(Value::Bool(l >= r)),
+        BinOp::Less => Ok(Value::Bool(l < r)),
+        BinOp::Greater => Ok(Value::Bool(l > r)),
+        BinOp::And | BinOp::Or => Err(RuntimeError(RuntimeErrorType::ExpectBool)),
+        BinOp::Cons => Err(RuntimeError(RuntimeErrorType::ExpectList)),
+        BinOp::Append => Err(RuntimeError(RuntimeErrorType::ExpectString)),
+    }
+}
+
+/// Floats follow IEEE 754, dividing by zero gives an infinity instead of an error.
+fn eval_float_binop(op: BinOp, l: f64, r: f64) -> Result<Value, RuntimeError> {
+    match op {
+        BinOp::Mul => Ok(Value::Float(l * r)),
+        BinOp::Div => Ok(Value::Float(l / r)),
+        BinOp::Rem | BinOp::Mod => Err(RuntimeError(RuntimeErrorType::ExpectInteger)),
+        BinOp::Add => Ok(Value::Float(l + r)),
+        BinOp::Sub => Ok(Value::Float(l - r)),
+        BinOp::Eq => Ok(Value::Bool(l == r)),
+        BinOp::Ne => Ok(Value::Bool(l != r)),
+        BinOp::Le => Ok(Value::Bool(l <= r)),
+        BinOp::Ge => Ok(Value::Bool(l >= r)),
+        BinOp::Less => Ok(Value::Bool(l < r)),
+        BinOp::Greater => Ok(Value::Bool(l > r)),
+        BinOp::And | BinOp::Or => Err(RuntimeError(RuntimeErrorType::ExpectBool)),
+        BinOp::Cons => Err(RuntimeError(RuntimeErrorType::ExpectList)),
+        BinOp::Append => Err(RuntimeError(RuntimeErrorType::ExpectString)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::eval_root;
+    use crate::error::RuntimeErrorType;
+    use rustelm_parser::parser::parse;
+
+    fn eval_str(source: &str) -> String {
+        format!("{:?}", eval_root(&parse(source).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn test_atom() {
+        assert_eq!(&eval_str("1\n"), "1");
+        assert_eq!(&eval_str("()\n"), "()");
+        assert!(eval_root(&parse("x\n").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_abs() {
+        assert_eq!(&eval_str("\\x: int. x\n"), "<function \\x>");
+        assert_eq!(&eval_str("(\\x: int. x) 1\n"), "1");
+        assert_eq!(
+            &eval_str("(\\x: int. \\y: int. \\z: int. x + y + z) 1 2 3\n"),
+            "6"
+        );
+
+        // Closures capture the environment they are created in
+        assert_eq!(
+            &eval_str("let x = 1 in let f = \\y: int. x + y in let x = 10 in f x\n"),
+            "11"
+        );
+    }
+
+    #[test]
+    fn test_binop() {
+        assert_eq!(&eval_str("1 + 2 * 3\n"), "7");
+        assert_eq!(&eval_str("7 / 2 - 1\n"), "2");
+        assert_eq!(&eval_str("1 + 2 * 3 == 3 * 2 + 1\n"), "True");
+        assert_eq!(&eval_str("1 > 2\n"), "False");
+        assert_eq!(&eval_str("not (1 > 2) && True != False\n"), "True");
+
+        // The right operand is only evaluated when needed
+        assert_eq!(&eval_str("False && 1 / 0 == 0\n"), "False");
+        assert_eq!(&eval_str("True || 1 / 0 == 0\n"), "True");
+
+        match eval_root(&parse("1 / (1 - 1)\n").unwrap()) {
+            Err(e) => assert!(matches!(e.0, RuntimeErrorType::DivisionByZero)),
+            Ok(_) => panic!("expected a division by zero"),
+        }
+
+        // Division truncates, `%` takes the sign of the dividend and `mod` of the divisor
+        assert_eq!(&eval_str("-5 + -(2 * 3)\n"), "-11");
+        assert_eq!(&eval_str("(7 / 2, -7 / 2, 7 / -2)\n"), "(3, -3, -3)");
+        assert_eq!(&eval_str("(7 % 2, -7 % 2, 7 % -2)\n"), "(1, -1, 1)");
+        assert_eq!(
+            &eval_str("(7 mod 2, -7 mod 2, 7 mod -2, -6 mod 3)\n"),
+            "(1, 1, -1, 0)"
+        );
+        assert_eq!(
+            &eval_str("case 0 - 1 of { -1 -> True; _ -> False }\n"),
+            "True"
+        );
+        for source in &["1 % 0\n", "1 mod 0\n"] {
+            match eval_root(&parse(source).unwrap()) {
+                Err(e) => assert!(matches!(e.0, RuntimeErrorType::DivisionByZero)),
+                Ok(_) => panic!("expected a division by zero"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_if() {
+        assert_eq!(&eval_str("if True then 2 else 3\n"), "2");
+        assert_eq!(&eval_str("if 1 > 2 then 2 else 3\n"), "3");
+
+        // Only the taken branch is evaluated
+        assert_eq!(&eval_str("if True then 2 else 1 / 0\n"), "2");
+        assert!(eval_root(&parse("if 1 then 2 else 3\n").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_signal() {
+        assert_eq!(&eval_str("MouseX\n"), "MouseX");
+        assert_eq!(
+            &eval_str("let f = \\x: int. \\y: int. x + y in lift2 f: MouseX MouseY\n"),
+            "lift2 <function \\x>: MouseX MouseY"
+        );
+        assert_eq!(
+            &eval_str("foldp (\\x: unit. \\acc: int. acc + 1) 0 MouseClicks\n"),
+            "foldp <function \\x> 0 MouseClicks"
+        );
+        assert_eq!(
+            &eval_str("let s = lift1 (\\x: int. x): MouseX in foldp (\\x: int. \\y: int. x) 0 s\n"),
+            "foldp <function \\x> 0 (lift1 <function \\x>: MouseX)"
+        );
+        assert_eq!(
+            &eval_str("async (lift1 (\\x: int. x): MouseX)\n"),
+            "async (lift1 <function \\x>: MouseX)"
+        );
+    }
+
+    #[test]
+    fn test_let() {
+        assert_eq!(&eval_str("let x = 1 + 2 in x\n"), "3");
+        assert_eq!(
+            &eval_str("let x = 1 in let y = 2 in let z = 3 in x + y + z\n"),
+            "6"
+        );
+        assert_eq!(&eval_str("let speed : int = 3 in speed * 2\n"), "6");
+    }
+
+    #[test]
+    fn test_function() {
+        assert_eq!(&eval_str("(\\x y. x - y) 5 3\n"), "2");
+        assert_eq!(
+            &eval_str("let add (x: int) (y: int) = x + y in add 1 2\n"),
+            "3"
+        );
+        assert_eq!(
+            &eval_str("let twice f x = f (f x) in twice (\\x. x * 3) 2\n"),
+            "18"
+        );
+        assert_eq!(&eval_str("let const x _ = x in const 1 ()\n"), "1");
+        assert_eq!(&eval_str("\\_. 1\n"), "<function \\_>");
+    }
+
+    #[test]
+    fn test_let_rec() {
+        let fact = "let rec fact: int -> int = \\n. if n < 1 then 1 else n * fact (n - 1) in ";
+        assert_eq!(&eval_str(&format!("{}fact 10\n", fact)), "3628800");
+        assert_eq!(&eval_str(&format!("{}fact\n", fact)), "<function \\n>");
+
+        // The function sees itself, not a later binding of its name
+        assert_eq!(
+            &eval_str(&format!(
+                "{}let f = fact in let fact = \\n. 0 in f 3\n",
+                fact
+            )),
+            "6"
+        );
+
+        let fib =
+            "let rec fib: int -> int = \\n. if n < 2 then n else (fib (n - 1)) + (fib (n - 2)) in ";
+        assert_eq!(
+            &eval_str(&format!("{}map fib [0, 1, 2, 10]\n", fib)),
+            "[0, 1, 1, 55]"
+        );
+    }
+
+    #[test]
+    fn test_tuple() {
+        assert_eq!(&eval_str("(1 > 2, (), 3)\n"), "(False, (), 3)");
+        assert_eq!(
+            &eval_str("let (x, (y, z)) = (1, (2, 3)) in x + y * z\n"),
+            "7"
+        );
+        assert_eq!(
+            &eval_str("let swap = \\p. let (x, y) = p in (y, x) in swap (1, True)\n"),
+            "(True, 1)"
+        );
+    }
+
+    #[test]
+    fn test_record() {
+        assert_eq!(&eval_str("{ y = 1, x = () }\n"), "{ x = (), y = 1 }");
+        assert_eq!(&eval_str("{ x = 1, y = 2 }.y\n"), "2");
+        assert_eq!(
+            &eval_str("let r = { x = 1, y = 2 } in { r | x = r.y + 10 }\n"),
+            "{ x = 12, y = 2 }"
+        );
+    }
+
+    #[test]
+    fn test_case() {
+        let shape = "type Shape = Circle int | Rect int int in ";
+        assert_eq!(
+            &eval_str(&format!("{}Rect 2\n", shape)),
+            "<constructor Rect>"
+        );
+        assert_eq!(
+            &eval_str(&format!("{}(Rect 2 3, Circle)\n", shape)),
+            "(Rect 2 3, <constructor Circle>)"
+        );
+        assert_eq!(
+            &eval_str("type Maybe a = Nothing | Just a in Just (Just Nothing)\n"),
+            "Just (Just Nothing)"
+        );
+
+        let area = "let area = \\s. case s of { Circle r -> 3 * r * r; Rect w h -> w * h } in ";
+        assert_eq!(
+            &eval_str(&format!("{}{}area (Circle 2)\n", shape, area)),
+            "12"
+        );
+        assert_eq!(
+            &eval_str(&format!("{}{}area (Rect 2 5)\n", shape, area)),
+            "10"
+        );
+
+        // The first matching branch is taken
+        let sign = "\\n. case (n > 0, n) of { (True, _) -> 1; (_, 0) -> 0; _ -> 0 - 1 }";
+        assert_eq!(&eval_str(&format!("({}) 5\n", sign)), "1");
+        assert_eq!(&eval_str(&format!("({}) 0\n", sign)), "0");
+        assert_eq!(&eval_str(&format!("({}) (0 - 5)\n", sign)), "-1");
+
+        assert_eq!(
+            &eval_str("type Point = Point int int in let Point x y = Point 1 2 in x + y\n"),
+            "3"
+        );
+    }
+
+    #[test]
+    fn test_list() {
+        assert_eq!(&eval_str("[1, 2, 3]\n"), "[1, 2, 3]");
+        assert_eq!(&eval_str("0 :: 1 :: [2]\n"), "[0, 1, 2]");
+        assert_eq!(&eval_str("map (\\x. x * 2) [1, 2, 3]\n"), "[2, 4, 6]");
+        assert_eq!(
+            &eval_str("foldl (\\x. \\acc. x :: acc) [] [1, 2, 3]\n"),
+            "[3, 2, 1]"
+        );
+        assert_eq!(&eval_str("filter (\\x. x > 1) [1, 2, 3]\n"), "[2, 3]");
+        assert_eq!(
+            &eval_str("(length [1, 2], head [1, 2], tail [1, 2])\n"),
+            "(2, 1, [2])"
+        );
+        assert_eq!(&eval_str("map\n"), "<function map>");
+
+        let sum = "let sum = \\l. case l of { [] -> 0; x :: rest -> x + (head rest) } in ";
+        assert_eq!(&eval_str(&format!("{}sum [1, 2, 3]\n", sum)), "3");
+        assert_eq!(
+            &eval_str("case [1, 2] of { [x] -> x; [x, y] -> x + y; _ -> 0 }\n"),
+            "3"
+        );
+
+        match eval_root(&parse("head []\n").unwrap()) {
+            Err(e) => match e.0 {
+                RuntimeErrorType::EmptyList => {}
+                _ => panic!("unexpected error {}", e),
+            },
+            Ok(value) => panic!("unexpected value {:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_string() {
+        assert_eq!(&eval_str("\"a\\tb\\n\"\n"), "\"a\\tb\\n\"");
+        assert_eq!(&eval_str("\"x = \" ++ (show (1 + 2))\n"), "\"x = 3\"");
+        assert_eq!(&eval_str("[1] ++ [2, 3]\n"), "[1, 2, 3]");
+        assert_eq!(&eval_str("\"a\" == \"a\" && \"a\" != \"b\"\n"), "True");
+        assert_eq!(&eval_str("show (0 - 4)\n"), "\"-4\"");
+        assert_eq!(&eval_str("case \"no\" of { \"yes\" -> 1; _ -> 0 }\n"), "0");
+    }
+
+    #[test]
+    fn test_float() {
+        assert_eq!(&eval_str("1.5 * 2.0 + 0.25\n"), "3.25");
+        assert_eq!(&eval_str("1e-3 * 1e3\n"), "1.0");
+        assert_eq!(&eval_str("7.0 / 2.0\n"), "3.5");
+        assert_eq!(&eval_str("1.0 / 0.0\n"), "inf");
+        assert_eq!(&eval_str("-(0.5)\n"), "-0.5");
+        assert_eq!(&eval_str("0.1 + 0.2 > 0.3\n"), "True");
+        assert_eq!(&eval_str("(toFloat 3) / 2.0\n"), "1.5");
+        assert_eq!(
+            &eval_str("(round 2.5, round (0.0 - 2.5), round 2.4)\n"),
+            "(3, -3, 2)"
+        );
+
+        match eval_root(&parse("round (1.0 / 0.0)\n").unwrap()) {
+            Err(e) => assert!(matches!(e.0, RuntimeErrorType::NotFinite)),
+            Ok(value) => panic!("unexpected value {:?}", value),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use crate::value::{Signal, Value};
+use std::collections::HashMap;
+use std::fmt::{Debug, Error, Formatter};
+use std::sync::Arc;
+
+/// Identifies a node in a `SignalGraph`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub usize);
+
+/// What a node computes from the values of its parents.
+#[derive(Clone)]
+pub enum NodeKind {
+    /// One of the inputs, it has no parents and only changes on its own events.
+    Input(String),
+    /// Applies the function to the values of its parents whenever one of them changes.
+    Lift(Value),
+    /// Folds the function over the values of its only parent, starting from the accumulator.
+    Foldp(Value, Value),
+    /// Boundary of an async subgraph. Whenever its only parent changes, the new value enters
+    /// the graph again as a fresh event, for which this node acts as an input.
+    Async,
+}
+
+#[derive(Clone)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub parents: Vec<NodeId>,
+}
+
+/// The signal graph of a program, a DAG whose nodes are stored in topological order: the
+/// parents of a node always come before the node itself.
+pub struct SignalGraph {
+    nodes: Vec<Node>,
+    root: NodeId,
+}
+
+impl SignalGraph {
+    /// Lower a signal term to its graph. Signals shared through let bindings become a single
+    /// node, as does every use of the same input.
+    pub fn new(signal: &Arc<Signal>) -> SignalGraph {
+        let mut builder = Builder {
+            nodes: vec![],
+            shared: HashMap::new(),
+            inputs: HashMap::new(),
+        };
+        let root = builder.lower(signal);
+
+        SignalGraph {
+            nodes: builder.nodes,
+            root,
+        }
+    }
+
+    /// The node producing the value of the whole program.
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// All nodes, in topological order.
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (NodeId(i), node))
+    }
+
+    /// All edges, from parent to child.
+    pub fn edges(&self) -> Vec<(NodeId, NodeId)> {
+        self.nodes()
+            .flat_map(|(id, node)| node.parents.iter().map(move |parent| (*parent, id)))
+            .collect()
+    }
+
+    /// The nodes reading the value of `id`.
+    pub fn children(&self, id: NodeId) -> Vec<NodeId> {
+        self.nodes()
+            .filter(|(_, node)| node.parents.contains(&id))
+            .map(|(child, _)| child)
+            .collect()
+    }
+
+    /// The input nodes and the names of their inputs.
+    pub fn inputs(&self) -> Vec<(NodeId, &str)> {
+        self.nodes()
+            .filter_map(|(id, node)| match node.kind {
+                NodeKind::Input(ref name) => Some((id, name.as_str())),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+struct Builder {
+    nodes: Vec<Node>,
+    /// Signal terms already lowered, by address.
+    shared: HashMap<*const Signal, NodeId>,
+    /// Input nodes already created, by name.
+    inputs: HashMap<String, NodeId>,
+}
+
+impl Builder {
+    fn lower(&mut self, signal: &Arc<Signal>) -> NodeId {
+        let key = Arc::as_ptr(signal);
+        if let Some(id) = self.shared.get(&key) {
+            return *id;
+        }
+
+        let id = match **signal {
+            Signal::Input(ref name) => match self.inputs.get(name) {
+                Some(id) => *id,
+                None => {
+                    let id = self.push(NodeKind::Input(name.clone()), vec![]);
+                    self.inputs.insert(name.clone(), id);
+                    id
+                }
+            },
+            Signal::Lift(ref func, ref args) => {
+                let parents = args.iter().map(|arg| self.lower(arg)).collect();
+                self.push(NodeKind::Lift(func.clone()), parents)
+            }
+            Signal::Foldp(ref func, ref init, ref signal) => {
+                let parent = self.lower(signal);
+                self.push(NodeKind::Foldp(func.clone(), init.clone()), vec![parent])
+            }
+            Signal::Async(ref signal) => {
+                let parent = self.lower(signal);
+                self.push(NodeKind::Async, vec![parent])
+            }
+        };
+
+        self.shared.insert(key, id);
+        id
+    }
+
+    fn push(&mut self, kind: NodeKind, parents: Vec<NodeId>) -> NodeId {
+        self.nodes.push(Node { kind, parents });
+        NodeId(self.nodes.len() - 1)
+    }
+}
+
+impl Debug for NodeKind {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        use self::NodeKind::*;
+        match *self {
+            Input(ref name) => write!(fmt, "{}", name),
+            Lift(ref func) => write!(fmt, "lift {:?}", func),
+            Foldp(ref func, ref init) => write!(fmt, "foldp {:?} {:?}", func, init),
+            Async => write!(fmt, "async"),
+        }
+    }
+}
+
+/// One node per line, followed by its parents, e.g. `#2 = lift <function \x>: #0 #1`.
+impl Debug for SignalGraph {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        for (id, node) in self.nodes() {
+            write!(fmt, "#{} = {:?}", id.0, node.kind)?;
+            if !node.parents.is_empty() {
+                write!(fmt, ":")?;
+                for parent in &node.parents {
+                    write!(fmt, " #{}", parent.0)?;
+                }
+            }
+            writeln!(fmt)?;
+        }
+        write!(fmt, "root #{}", self.root.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{NodeId, SignalGraph};
+    use crate::evaluator::eval_root;
+    use crate::value::Value;
+    use rustelm_parser::parser::parse;
+
+    fn graph(source: &str) -> SignalGraph {
+        match eval_root(&parse(source).unwrap()).unwrap() {
+            Value::Signal(signal) => SignalGraph::new(&signal),
+            _ => panic!("expected a signal"),
+        }
+    }
+
+    #[test]
+    fn test_input() {
+        let g = graph("MouseX\n");
+        assert_eq!(g.len(), 1);
+        assert_eq!(g.inputs(), vec![(NodeId(0), "MouseX")]);
+        assert_eq!(&format!("{:?}", g), "#0 = MouseX\nroot #0");
+    }
+
+    #[test]
+    fn test_lift() {
+        let g = graph("lift2 (\\x: int. \\y: int. x + y): MouseX MouseY\n");
+        assert_eq!(
+            &format!("{:?}", g),
+            "#0 = MouseX\n#1 = MouseY\n#2 = lift <function \\x>: #0 #1\nroot #2"
+        );
+        assert_eq!(
+            g.edges(),
+            vec![(NodeId(0), NodeId(2)), (NodeId(1), NodeId(2))]
+        );
+        assert_eq!(g.children(NodeId(0)), vec![NodeId(2)]);
+    }
+
+    #[test]
+    fn test_sharing() {
+        // The same input is only one node
+        let g = graph("lift2 (\\x: int. \\y: int. x + y): MouseX MouseX\n");
+        assert_eq!(g.len(), 2);
+        assert_eq!(g.node(NodeId(1)).parents, vec![NodeId(0), NodeId(0)]);
+
+        // A let bound signal is computed once, and read by both of its users
+        let g = graph(
+            "let c = foldp (\\x: unit. \\acc: int. acc + 1) 0 MouseClicks in \
+             lift2 (\\x: int. \\y: int. x * y): c c\n",
+        );
+        assert_eq!(
+            &format!("{:?}", g),
+            "#0 = MouseClicks\n#1 = foldp <function \\x> 0: #0\n#2 = lift <function \\x>: #1 #1\nroot #2"
+        );
+    }
+
+    #[test]
+    fn test_async() {
+        let g = graph(
+            "let slow = async (lift1 (\\x: int. x * 100): MouseX) in \
+             lift2 (\\x: int. \\y: int. x + y): slow MouseY\n",
+        );
+        assert_eq!(
+            &format!("{:?}", g),
+            "#0 = MouseX\n#1 = lift <function \\x>: #0\n#2 = async: #1\n#3 = MouseY\n#4 = lift <function \\x>: #2 #3\nroot #4"
+        );
+    }
+}
@@ -0,0 +1,9 @@
+extern crate im;
+
+pub mod error;
+pub mod evaluator;
+pub mod graph;
+pub mod reactor;
+pub mod runtime;
+pub mod simulator;
+pub mod value;
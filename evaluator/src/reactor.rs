@@ -0,0 +1,540 @@
+use crate::error::{RuntimeError, RuntimeErrorType};
+use crate::evaluator::apply;
+use crate::graph::{Node, NodeId, NodeKind, SignalGraph};
+use crate::value::Value;
+use num_bigint::BigInt;
+use rustelm_analyzer::typechecker::INPUTS;
+use rustelm_parser::ast::{SignalType, SimpleType, Types};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+/// An input event, the input signal `input` now has the value `value`.
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub input: String,
+    pub value: Value,
+}
+
+/// After every event, each signal tells its dependents whether its value changed, along with
+/// its current value.
+#[derive(Clone, Debug)]
+pub enum Message {
+    Change(Value),
+    NoChange(Value),
+}
+
+impl Message {
+    pub fn value(&self) -> &Value {
+        match self {
+            Message::Change(value) | Message::NoChange(value) => value,
+        }
+    }
+
+    pub fn is_change(&self) -> bool {
+        match self {
+            Message::Change(_) => true,
+            Message::NoChange(_) => false,
+        }
+    }
+}
+
+/// The value an input signal holds before its first event.
+pub fn default_input(name: &str) -> Result<Value, RuntimeError> {
+    match INPUTS.get(name) {
+        Some(Types::Signal(SignalType::Signal(SimpleType::Int))) => Ok(Value::Int(BigInt::from(0))),
+        Some(Types::Signal(SignalType::Signal(SimpleType::Unit))) => Ok(Value::Unit),
+        _ => Err(RuntimeError(RuntimeErrorType::UndefinedInput)),
+    }
+}
+
+/// Where an event originates from, either one of the inputs, or an async node whose subgraph
+/// produced a new value.
+#[derive(Clone, PartialEq)]
+pub(crate) enum Source {
+    Input(String),
+    Async(NodeId),
+}
+
+pub(crate) fn apply_all(func: Value, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    args.into_iter().try_fold(func, apply)
+}
+
+/// The value of every node before the first event, given the default values of the inputs. A
+/// node whose value fails to compute holds the error, which is only reported once the node
+/// produces a value. An event that recomputes the node before that never sees the error.
+pub(crate) fn initial_values(graph: &SignalGraph) -> Vec<Result<Value, RuntimeError>> {
+    let mut values = Vec::with_capacity(graph.len());
+    for (id, node) in graph.nodes() {
+        let value = match node.kind {
+            NodeKind::Input(ref name) => default_input(name),
+            NodeKind::Lift(_) => recompute(id, node, &values),
+            NodeKind::Foldp(_, ref init) => Ok(init.clone()),
+            NodeKind::Async => values[node.parents[0].0].clone(),
+        };
+        values.push(value);
+    }
+
+    values
+}
+
+/// The new value of the lift or foldp node `id`, after one of its parents changed.
+fn recompute(
+    id: NodeId,
+    node: &Node,
+    values: &[Result<Value, RuntimeError>],
+) -> Result<Value, RuntimeError> {
+    let arg = |parent: &NodeId| values[parent.0].clone();
+    match node.kind {
+        NodeKind::Lift(ref func) => {
+            let args = node.parents.iter().map(arg).collect::<Result<_, _>>()?;
+            apply_all(func.clone(), args)
+        }
+        NodeKind::Foldp(ref func, _) => {
+            let acc = values[id.0].clone()?;
+            apply_all(func.clone(), vec![arg(&node.parents[0])?, acc])
+        }
+        NodeKind::Input(_) | NodeKind::Async => unreachable!("only changes on its own events"),
+    }
+}
+
+/// The values of the boundary of a subgraph after an event, and whether they changed.
+type Update = Vec<(bool, Result<Value, RuntimeError>)>;
+
+/// The new value of an async node, if its subgraph changed.
+type Answer = (NodeId, Option<Result<Value, RuntimeError>>);
+
+/// The nodes behind an async node that the rest of the graph only reads through it. They run
+/// on a thread of their own, so a slow computation does not hold up the events of the rest of
+/// the graph.
+struct Subgraph {
+    /// The async node fed by the subgraph.
+    id: NodeId,
+    /// The parent of the async node, inside of the subgraph unless the subgraph is empty.
+    top: NodeId,
+    /// The nodes outside of the subgraph that it reads, or the parent of the async node.
+    boundary: Vec<NodeId>,
+    /// The nodes of the subgraph, in topological order.
+    nodes: Vec<(NodeId, Node)>,
+}
+
+impl Subgraph {
+    /// Collect the subgraph of the async node `id`, where `main` marks the nodes the reactor
+    /// computes itself.
+    fn new(graph: &SignalGraph, main: &[bool], id: NodeId) -> Subgraph {
+        let top = graph.node(id).parents[0];
+        let mut inside = vec![false; graph.len()];
+        let mut stack = vec![top];
+        while let Some(node) = stack.pop() {
+            if !main[node.0] && !inside[node.0] {
+                inside[node.0] = true;
+                stack.extend(&graph.node(node).parents);
+            }
+        }
+
+        let nodes: Vec<_> = graph
+            .nodes()
+            .filter(|(node, _)| inside[node.0])
+            .map(|(node, kind)| (node, kind.clone()))
+            .collect();
+        let boundary = graph
+            .nodes()
+            .map(|(node, _)| node)
+            .filter(|node| main[node.0])
+            .filter(|node| *node == top || nodes.iter().any(|(_, n)| n.parents.contains(node)))
+            .collect();
+
+        Subgraph {
+            id,
+            top,
+            boundary,
+            nodes,
+        }
+    }
+
+    /// Update the subgraph once for every event that reaches it, and answer each of them with
+    /// the new value of the parent of the async node, if it changed.
+    fn run(
+        self,
+        mut values: Vec<Result<Value, RuntimeError>>,
+        updates: Receiver<Update>,
+        answers: Sender<Answer>,
+    ) {
+        let top = self.top;
+        let mut changed = vec![false; values.len()];
+        while let Ok(update) = updates.recv() {
+            for (node, (c, value)) in self.boundary.iter().zip(update) {
+                changed[node.0] = c;
+                values[node.0] = value;
+            }
+            for (id, node) in &self.nodes {
+                changed[id.0] = node.parents.iter().any(|p| changed[p.0]);
+                if changed[id.0] {
+                    values[id.0] = recompute(*id, node, &values);
+                }
+            }
+
+            let answer = if changed[top.0] {
+                Some(values[top.0].clone())
+            } else {
+                None
+            };
+            if answers.send((self.id, answer)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// The reactor's end of the thread running a subgraph.
+struct Worker {
+    boundary: Vec<NodeId>,
+    updates: Sender<Update>,
+}
+
+/// Runs a signal graph by pushing input events through it one at a time. Every event reaches
+/// every input, and the nodes are updated in topological order, so all signals see the events
+/// in the order they occurred, and foldp accumulators persist from one event to the next.
+///
+/// The subgraph of every async node runs on a thread of its own. Events are passed on to it
+/// without waiting for its result, which enters the graph later as an event of the async node.
+pub struct Reactor {
+    graph: SignalGraph,
+    /// The current value of every node, foldp nodes use it as their accumulator.
+    values: Vec<Result<Value, RuntimeError>>,
+    /// Whether the reactor computes the node itself, rather than the subgraph of an async node.
+    main: Vec<bool>,
+    workers: Vec<Worker>,
+    answers: Receiver<Answer>,
+    /// The number of updates sent to the subgraphs that have not been answered yet.
+    pending: usize,
+}
+
+impl Reactor {
+    /// Set up the graph with the default values of the inputs, and start the subgraphs of the
+    /// async nodes.
+    pub fn new(graph: SignalGraph) -> Reactor {
+        let values = initial_values(&graph);
+
+        // The inputs, the async nodes, and whatever the root reads without going through an
+        // async node.
+        let mut main: Vec<_> = graph
+            .nodes()
+            .map(|(_, node)| matches!(node.kind, NodeKind::Input(_) | NodeKind::Async))
+            .collect();
+        let mut stack = vec![graph.root()];
+        while let Some(id) = stack.pop() {
+            let node = graph.node(id);
+            if !matches!(node.kind, NodeKind::Async) {
+                stack.extend(&node.parents);
+            }
+            main[id.0] = true;
+        }
+
+        let (answers_tx, answers) = channel();
+        let mut workers = vec![];
+        for (id, node) in graph.nodes() {
+            if let NodeKind::Async = node.kind {
+                let subgraph = Subgraph::new(&graph, &main, id);
+                let (updates_tx, updates) = channel();
+                workers.push(Worker {
+                    boundary: subgraph.boundary.clone(),
+                    updates: updates_tx,
+                });
+
+                let (values, answers) = (values.clone(), answers_tx.clone());
+                thread::spawn(move || subgraph.run(values, updates, answers));
+            }
+        }
+
+        Reactor {
+            graph,
+            values,
+            main,
+            workers,
+            answers,
+            pending: 0,
+        }
+    }
+
+    pub fn graph(&self) -> &SignalGraph {
+        &self.graph
+    }
+
+    /// The current value of the graph.
+    pub fn value(&self) -> Result<Value, RuntimeError> {
+        self.values[self.graph.root().0].clone()
+    }
+
+    /// Propagate one input event, returning the message produced by the root. The subgraphs of
+    /// the async nodes it reaches are not waited for, see `settle`.
+    pub fn dispatch(&mut self, event: &Event) -> Result<Message, RuntimeError> {
+        if INPUTS.get(&event.input).is_none() {
+            return Err(RuntimeError(RuntimeErrorType::UndefinedInput));
+        }
+
+        self.step(&Source::Input(event.input.clone()), Ok(event.value.clone()))
+    }
+
+    /// Wait until the subgraphs of the async nodes have caught up with the events dispatched so
+    /// far, and dispatch their results as events of the async nodes, returning the messages
+    /// produced by the root. Results of the same round are dispatched in the order of their
+    /// nodes, so the outcome does not depend on which thread finished first.
+    pub fn settle(&mut self) -> Result<Vec<Message>, RuntimeError> {
+        let mut messages = vec![];
+        while self.pending > 0 {
+            let mut answers = vec![];
+            for _ in 0..self.pending {
+                match self.answers.recv() {
+                    Ok(answer) => answers.push(answer),
+                    Err(_) => return Err(RuntimeError(RuntimeErrorType::Disconnected)),
+                }
+            }
+            self.pending = 0;
+
+            answers.sort_by_key(|(id, _)| *id);
+            for (id, answer) in answers {
+                if let Some(value) = answer {
+                    messages.push(self.step(&Source::Async(id), value)?);
+                }
+            }
+        }
+
+        Ok(messages)
+    }
+
+    /// React to one event, and pass it on to the subgraphs it reaches.
+    fn step(
+        &mut self,
+        source: &Source,
+        event_value: Result<Value, RuntimeError>,
+    ) -> Result<Message, RuntimeError> {
+        let mut changed = vec![false; self.graph.len()];
+
+        for (id, node) in self.graph.nodes() {
+            // The subgraphs of the async nodes are left to their own threads.
+            if !self.main[id.0] {
+                continue;
+            }
+
+            changed[id.0] = match node.kind {
+                NodeKind::Input(ref name) => {
+                    matches!(source, Source::Input(input) if input == name)
+                }
+                NodeKind::Async => *source == Source::Async(id),
+                // Only recompute when at least one of the arguments changed.
+                NodeKind::Lift(_) | NodeKind::Foldp(_, _) => {
+                    node.parents.iter().any(|p| changed[p.0])
+                }
+            };
+
+            if changed[id.0] {
+                self.values[id.0] = match node.kind {
+                    NodeKind::Input(_) | NodeKind::Async => event_value.clone(),
+                    NodeKind::Lift(_) | NodeKind::Foldp(_, _) => recompute(id, node, &self.values),
+                };
+            }
+        }
+
+        // The subgraphs see every event, but their changes only reach the rest of the graph
+        // through a fresh event.
+        for worker in &self.workers {
+            if worker.boundary.iter().any(|b| changed[b.0]) {
+                let update = worker
+                    .boundary
+                    .iter()
+                    .map(|b| (changed[b.0], self.values[b.0].clone()))
+                    .collect();
+                if worker.updates.send(update).is_ok() {
+                    self.pending += 1;
+                }
+            }
+        }
+
+        let root = self.graph.root();
+        let value = self.values[root.0].clone()?;
+        if changed[root.0] {
+            Ok(Message::Change(value))
+        } else {
+            Ok(Message::NoChange(value))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Event, Reactor};
+    use crate::evaluator::eval_root;
+    use crate::graph::SignalGraph;
+    use crate::value::Value;
+    use num_bigint::BigInt;
+    use rustelm_parser::parser::parse;
+
+    fn reactor(source: &str) -> Reactor {
+        match eval_root(&parse(source).unwrap()).unwrap() {
+            Value::Signal(signal) => Reactor::new(SignalGraph::new(&signal)),
+            _ => panic!("expected a signal"),
+        }
+    }
+
+    fn mouse_x(x: i32) -> Event {
+        Event {
+            input: "MouseX".to_owned(),
+            value: Value::Int(BigInt::from(x)),
+        }
+    }
+
+    fn mouse_y(y: i32) -> Event {
+        Event {
+            input: "MouseY".to_owned(),
+            value: Value::Int(BigInt::from(y)),
+        }
+    }
+
+    fn click() -> Event {
+        Event {
+            input: "MouseClicks".to_owned(),
+            value: Value::Unit,
+        }
+    }
+
+    #[test]
+    fn test_lift() {
+        let mut r = reactor("lift1 (\\x: int. x * 2): MouseX\n");
+        assert_eq!(&format!("{:?}", r.value().unwrap()), "0");
+
+        let msg = r.dispatch(&mouse_x(21)).unwrap();
+        assert!(msg.is_change());
+        assert_eq!(&format!("{:?}", msg.value()), "42");
+
+        // Unrelated events leave the value untouched
+        let msg = r.dispatch(&click()).unwrap();
+        assert!(!msg.is_change());
+        assert_eq!(&format!("{:?}", msg.value()), "42");
+    }
+
+    #[test]
+    fn test_foldp() {
+        let mut r = reactor("foldp (\\x: unit. \\acc: int. acc + 1) 0 MouseClicks\n");
+        assert_eq!(&format!("{:?}", r.value().unwrap()), "0");
+
+        r.dispatch(&click()).unwrap();
+        r.dispatch(&mouse_x(3)).unwrap();
+        r.dispatch(&click()).unwrap();
+        let msg = r.dispatch(&click()).unwrap();
+        assert!(msg.is_change());
+        assert_eq!(&format!("{:?}", msg.value()), "3");
+
+        let msg = r.dispatch(&mouse_x(5)).unwrap();
+        assert!(!msg.is_change());
+        assert_eq!(&format!("{:?}", msg.value()), "3");
+    }
+
+    #[test]
+    fn test_foldp_of_lift() {
+        let mut r = reactor(
+            "let dx = lift1 (\\x: int. x / 2): MouseX in foldp (\\x: int. \\acc: int. acc + x) 0 dx\n",
+        );
+
+        r.dispatch(&mouse_x(4)).unwrap();
+        r.dispatch(&mouse_x(10)).unwrap();
+        assert_eq!(&format!("{:?}", r.value().unwrap()), "7");
+
+        // The same value still counts as a change of the input
+        r.dispatch(&mouse_x(10)).unwrap();
+        assert_eq!(&format!("{:?}", r.value().unwrap()), "12");
+    }
+
+    #[test]
+    fn test_undefined_input() {
+        let mut r = reactor("MouseX\n");
+        let event = Event {
+            input: "Keyboard".to_owned(),
+            value: Value::Unit,
+        };
+        assert!(r.dispatch(&event).is_err());
+    }
+
+    #[test]
+    fn test_shared_foldp() {
+        // Both arguments read the same accumulator, which is only folded once per event
+        let mut r = reactor(
+            "let c = foldp (\\x: unit. \\acc: int. acc + 1) 0 MouseClicks in \
+             lift2 (\\x: int. \\y: int. x * y): c c\n",
+        );
+
+        r.dispatch(&click()).unwrap();
+        r.dispatch(&click()).unwrap();
+        r.dispatch(&click()).unwrap();
+        assert_eq!(&format!("{:?}", r.value().unwrap()), "9");
+    }
+
+    #[test]
+    fn test_async() {
+        let mut r = reactor(
+            "let slow = async (lift1 (\\x: int. x * 100): MouseX) in lift2 (\\x: int. \\y: int. x + y): slow MouseY\n",
+        );
+        assert_eq!(&format!("{:?}", r.value().unwrap()), "0");
+
+        // The main graph answers the events right away without waiting for the async signal,
+        // whose result arrives as an event of its own.
+        let msg = r.dispatch(&mouse_x(2)).unwrap();
+        assert!(!msg.is_change());
+        assert_eq!(&format!("{:?}", msg.value()), "0");
+        let msg = r.dispatch(&mouse_y(1)).unwrap();
+        assert_eq!(&format!("{:?}", msg.value()), "1");
+
+        let msgs = r.settle().unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert!(msgs[0].is_change());
+        assert_eq!(&format!("{:?}", msgs[0].value()), "201");
+
+        // Events that do not reach the async subgraph produce no extra event
+        r.dispatch(&mouse_y(3)).unwrap();
+        assert!(r.settle().unwrap().is_empty());
+        assert_eq!(&format!("{:?}", r.value().unwrap()), "203");
+    }
+
+    #[test]
+    fn test_async_shared() {
+        // The subgraph reads the count that the main graph also reads, without folding it twice
+        let mut r = reactor(
+            "let c = foldp (\\x: unit. \\acc: int. acc + 1) 0 MouseClicks in \
+             let d = async (lift1 (\\x: int. x * 2): c) in \
+             lift2 (\\x: int. \\y: int. x * 10 + y): d c\n",
+        );
+        r.dispatch(&click()).unwrap();
+        r.dispatch(&click()).unwrap();
+        assert_eq!(&format!("{:?}", r.value().unwrap()), "2");
+
+        let msgs: Vec<_> = r
+            .settle()
+            .unwrap()
+            .iter()
+            .map(|m| format!("{:?}", m.value()))
+            .collect();
+        assert_eq!(msgs, vec!["22", "42"]);
+    }
+
+    #[test]
+    fn test_async_error() {
+        let mut r = reactor("async (lift1 (\\x: int. 10 / (x - 1)): MouseX)\n");
+        r.dispatch(&mouse_x(1)).unwrap();
+        assert_eq!(&r.settle().unwrap_err().to_string(), "division by zero");
+    }
+
+    #[test]
+    fn test_initial_error() {
+        // The initial value fails, but the first event replaces it
+        let mut r = reactor("lift1 (\\x: int. 10 / x): MouseX\n");
+        let msg = r.dispatch(&mouse_x(5)).unwrap();
+        assert_eq!(&format!("{:?}", msg.value()), "2");
+
+        // The error is reported once the root produces its value
+        let mut r = reactor("lift1 (\\x: int. 10 / x): MouseX\n");
+        assert!(r.value().is_err());
+        assert_eq!(
+            &r.dispatch(&click()).unwrap_err().to_string(),
+            "division by zero"
+        );
+    }
+}
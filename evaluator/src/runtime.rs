@@ -0,0 +1,360 @@
+use crate::error::{RuntimeError, RuntimeErrorType};
+use crate::graph::{NodeId, NodeKind, SignalGraph};
+use crate::reactor::{apply_all, initial_values, Event, Message, Source};
+use crate::value::Value;
+use rustelm_analyzer::typechecker::INPUTS;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+/// What the global event dispatcher is asked to do.
+enum Request {
+    /// Broadcast an input event.
+    Event(Event),
+    /// Broadcast the new value of an async subgraph, as an event of its own.
+    Async(NodeId, Value),
+    Stop,
+}
+
+type Output = Result<Message, RuntimeError>;
+
+/// The value a node holds, or the error it failed to compute.
+type Current = Result<Value, RuntimeError>;
+
+/// Runs a signal graph concurrently, following the second evaluation stage of the paper. Every
+/// node runs in its own thread and talks to its children over channels. A dispatcher thread
+/// broadcasts every event to all input nodes, so each node receives exactly one message from
+/// each parent per event, which keeps the updates synchronous and glitch-free.
+pub struct Runtime {
+    requests: Sender<Request>,
+    output: Receiver<Output>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Runtime {
+    /// Spawn one thread per node of the graph, and the dispatcher.
+    pub fn spawn(graph: SignalGraph) -> Runtime {
+        let values = initial_values(&graph);
+        let (requests, requests_rx) = channel();
+        let (output_tx, output) = channel();
+
+        // One channel per edge, so that a node can wait for each of its parents in turn.
+        let mut senders: Vec<Vec<Sender<Message>>> = graph.nodes().map(|_| vec![]).collect();
+        let mut receivers: Vec<Vec<Receiver<Message>>> = vec![];
+        for (_, node) in graph.nodes() {
+            let mut rxs = vec![];
+            for parent in &node.parents {
+                let (tx, rx) = channel();
+                senders[parent.0].push(tx);
+                rxs.push(rx);
+            }
+            receivers.push(rxs);
+        }
+
+        let mut handles = vec![];
+        let mut sources = vec![];
+        let nodes = graph.nodes().zip(receivers).zip(senders).zip(values);
+        for ((((id, node), parents), children), value) in nodes {
+            let output = if id == graph.root() {
+                Some(output_tx.clone())
+            } else {
+                None
+            };
+            let out = Outbox {
+                children,
+                output,
+                errors: output_tx.clone(),
+            };
+
+            let handle = match node.kind {
+                NodeKind::Input(ref name) => {
+                    let (tx, rx) = channel();
+                    sources.push(tx);
+                    let source = Source::Input(name.clone());
+                    thread::spawn(move || run_source(source, value, rx, out))
+                }
+                NodeKind::Lift(ref func) => {
+                    let func = func.clone();
+                    thread::spawn(move || run_lift(func, value, parents, out))
+                }
+                NodeKind::Foldp(ref func, _) => {
+                    let func = func.clone();
+                    thread::spawn(move || run_foldp(func, value, parents, out))
+                }
+                NodeKind::Async => {
+                    // The subgraph is not waited for, its results are forwarded to the
+                    // dispatcher, and come back to this node as an event of their own.
+                    let requests = requests.clone();
+                    handles.push(thread::spawn(move || run_forward(id, parents, requests)));
+
+                    let (tx, rx) = channel();
+                    sources.push(tx);
+                    thread::spawn(move || run_source(Source::Async(id), value, rx, out))
+                }
+            };
+            handles.push(handle);
+        }
+
+        handles.push(thread::spawn(move || run_dispatcher(requests_rx, sources)));
+
+        Runtime {
+            requests,
+            output,
+            handles,
+        }
+    }
+
+    /// Post an input event to the dispatcher.
+    pub fn send(&self, event: Event) -> Result<(), RuntimeError> {
+        if INPUTS.get(&event.input).is_none() {
+            return Err(RuntimeError(RuntimeErrorType::UndefinedInput));
+        }
+
+        self.requests
+            .send(Request::Event(event))
+            .map_err(|_| RuntimeError(RuntimeErrorType::Disconnected))
+    }
+
+    /// Block until the root of the graph produces its next message. There is one message for
+    /// every input event, and one for every event of an async node.
+    pub fn recv(&self) -> Result<Message, RuntimeError> {
+        match self.output.recv() {
+            Ok(output) => output,
+            Err(_) => Err(RuntimeError(RuntimeErrorType::Disconnected)),
+        }
+    }
+}
+
+/// Stopping the dispatcher closes the channels to the input nodes, which in turn shuts down
+/// every node downstream of them. Events still in flight are dropped.
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        let _ = self.requests.send(Request::Stop);
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Where a node sends its messages.
+struct Outbox {
+    children: Vec<Sender<Message>>,
+    /// Only set for the root of the graph.
+    output: Option<Sender<Output>>,
+    errors: Sender<Output>,
+}
+
+impl Outbox {
+    /// Send the value of the node, or report the error it holds instead. Returns whether the
+    /// node can go on.
+    fn send(&self, changed: bool, value: &Result<Value, RuntimeError>) -> bool {
+        let value = match value {
+            Ok(value) => value,
+            Err(e) => {
+                self.fail(e.clone());
+                return false;
+            }
+        };
+        let msg = if changed {
+            Message::Change(value.clone())
+        } else {
+            Message::NoChange(value.clone())
+        };
+
+        // A child that has stopped is no longer interested in our messages.
+        for child in &self.children {
+            let _ = child.send(msg.clone());
+        }
+        if let Some(output) = &self.output {
+            let _ = output.send(Ok(msg));
+        }
+        true
+    }
+
+    fn fail(&self, e: RuntimeError) {
+        let _ = self.errors.send(Err(e));
+    }
+}
+
+/// Wait for one message from each parent, returns `None` once any of them has stopped.
+fn recv_all(parents: &[Receiver<Message>]) -> Option<(bool, Vec<Value>)> {
+    let mut changed = false;
+    let mut values = vec![];
+    for parent in parents {
+        match parent.recv().ok()? {
+            Message::Change(v) => {
+                changed = true;
+                values.push(v);
+            }
+            Message::NoChange(v) => values.push(v),
+        }
+    }
+
+    Some((changed, values))
+}
+
+fn run_dispatcher(requests: Receiver<Request>, sources: Vec<Sender<(Source, Value)>>) {
+    while let Ok(request) = requests.recv() {
+        let (source, value) = match request {
+            Request::Event(event) => (Source::Input(event.input), event.value),
+            Request::Async(id, value) => (Source::Async(id), value),
+            Request::Stop => break,
+        };
+
+        for tx in &sources {
+            let _ = tx.send((source.clone(), value.clone()));
+        }
+    }
+}
+
+fn run_source(source: Source, mut value: Current, events: Receiver<(Source, Value)>, out: Outbox) {
+    while let Ok((event_source, event_value)) = events.recv() {
+        let changed = event_source == source;
+        if changed {
+            value = Ok(event_value);
+        }
+        if !out.send(changed, &value) {
+            return;
+        }
+    }
+}
+
+fn run_lift(func: Value, mut value: Current, parents: Vec<Receiver<Message>>, out: Outbox) {
+    while let Some((changed, values)) = recv_all(&parents) {
+        // Only recompute when at least one of the arguments changed.
+        if changed {
+            value = apply_all(func.clone(), values);
+        }
+        if !out.send(changed, &value) {
+            return;
+        }
+    }
+}
+
+fn run_foldp(func: Value, mut acc: Current, parents: Vec<Receiver<Message>>, out: Outbox) {
+    while let Some((changed, mut values)) = recv_all(&parents) {
+        if changed {
+            acc = acc.and_then(|acc| apply_all(func.clone(), vec![values.remove(0), acc]));
+        }
+        if !out.send(changed, &acc) {
+            return;
+        }
+    }
+}
+
+fn run_forward(id: NodeId, parents: Vec<Receiver<Message>>, requests: Sender<Request>) {
+    while let Some((changed, mut values)) = recv_all(&parents) {
+        if changed && requests.send(Request::Async(id, values.remove(0))).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Runtime;
+    use crate::evaluator::eval_root;
+    use crate::graph::SignalGraph;
+    use crate::reactor::Event;
+    use crate::value::Value;
+    use num_bigint::BigInt;
+    use rustelm_parser::parser::parse;
+
+    fn runtime(source: &str) -> Runtime {
+        match eval_root(&parse(source).unwrap()).unwrap() {
+            Value::Signal(signal) => Runtime::spawn(SignalGraph::new(&signal)),
+            _ => panic!("expected a signal"),
+        }
+    }
+
+    fn event(input: &str, value: i32) -> Event {
+        Event {
+            input: input.to_owned(),
+            value: Value::Int(BigInt::from(value)),
+        }
+    }
+
+    fn click() -> Event {
+        Event {
+            input: "MouseClicks".to_owned(),
+            value: Value::Unit,
+        }
+    }
+
+    #[test]
+    fn test_lift2() {
+        let rt = runtime("lift2 (\\x: int. \\y: int. x * 10 + y): MouseX MouseY\n");
+
+        rt.send(event("MouseX", 1)).unwrap();
+        rt.send(event("MouseY", 2)).unwrap();
+        rt.send(click()).unwrap();
+
+        let msg = rt.recv().unwrap();
+        assert!(msg.is_change());
+        assert_eq!(&format!("{:?}", msg.value()), "10");
+
+        let msg = rt.recv().unwrap();
+        assert!(msg.is_change());
+        assert_eq!(&format!("{:?}", msg.value()), "12");
+
+        let msg = rt.recv().unwrap();
+        assert!(!msg.is_change());
+        assert_eq!(&format!("{:?}", msg.value()), "12");
+    }
+
+    #[test]
+    fn test_foldp() {
+        let rt = runtime(
+            "let c = foldp (\\x: unit. \\acc: int. acc + 1) 0 MouseClicks in \
+             lift2 (\\x: int. \\y: int. x * y): c c\n",
+        );
+
+        for _ in 0..3 {
+            rt.send(click()).unwrap();
+        }
+        let values: Vec<_> = (0..3)
+            .map(|_| format!("{:?}", rt.recv().unwrap().value()))
+            .collect();
+        assert_eq!(values, vec!["1", "4", "9"]);
+    }
+
+    #[test]
+    fn test_async() {
+        let rt = runtime(
+            "let slow = async (lift1 (\\x: int. x * 100): MouseX) in \
+             lift2 (\\x: int. \\y: int. x + y): slow MouseY\n",
+        );
+
+        // The main graph answers right away, the async result arrives as an event of its own
+        rt.send(event("MouseX", 2)).unwrap();
+        let msg = rt.recv().unwrap();
+        assert!(!msg.is_change());
+        assert_eq!(&format!("{:?}", msg.value()), "0");
+
+        let msg = rt.recv().unwrap();
+        assert!(msg.is_change());
+        assert_eq!(&format!("{:?}", msg.value()), "200");
+    }
+
+    #[test]
+    fn test_error() {
+        let rt = runtime("lift1 (\\x: int. 10 / (x - 1)): MouseX\n");
+        assert!(rt.send(event("Keyboard", 1)).is_err());
+
+        rt.send(event("MouseX", 1)).unwrap();
+        assert!(rt.recv().is_err());
+    }
+
+    #[test]
+    fn test_initial_error() {
+        // The initial value fails, but is only reported when the node first sends it
+        let rt = runtime("lift1 (\\x: int. 10 / x): MouseX\n");
+        rt.send(event("MouseX", 5)).unwrap();
+        rt.send(click()).unwrap();
+        assert_eq!(&format!("{:?}", rt.recv().unwrap().value()), "2");
+        assert_eq!(&format!("{:?}", rt.recv().unwrap().value()), "2");
+
+        let rt = runtime("lift1 (\\x: int. 10 / x): MouseX\n");
+        rt.send(click()).unwrap();
+        assert_eq!(&rt.recv().unwrap_err().to_string(), "division by zero");
+    }
+}
@@ -0,0 +1,270 @@
+use crate::error::{RuntimeError, TraceError, TraceErrorType};
+use crate::graph::SignalGraph;
+use crate::reactor::{Event, Reactor};
+use crate::value::Value;
+use num_bigint::BigInt;
+use rustelm_analyzer::typechecker::INPUTS;
+use rustelm_parser::ast::{SignalType, SimpleType, Types};
+
+/// Parse a scripted sequence of input events from CSV. Each row is one event `input,value`, like
+/// `MouseX,10`, and the value is left empty for unit inputs, `MouseClicks,` or just
+/// `MouseClicks`. Fields may be quoted, and the trace may start with an `input,value` header.
+/// Blank lines are skipped.
+///
+/// The available inputs, and the type of their values, are the ones the typechecker knows.
+pub fn parse_trace(source: &str) -> Result<Vec<Event>, TraceError> {
+    let mut events = vec![];
+
+    for (i, line) in source.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let row = fields(line).map_err(|e| TraceError(i + 1, e))?;
+        let row: Vec<_> = row.iter().map(|field| field.trim()).collect();
+        if i == 0 && row == ["input", "value"] {
+            continue;
+        }
+
+        let event = match row.as_slice() {
+            [input] | [input, ""] => parse_event(input, None),
+            [input, value] => parse_event(input, Some(value)),
+            _ => Err(TraceErrorType::InvalidRow),
+        };
+        events.push(event.map_err(|e| TraceError(i + 1, e))?);
+    }
+
+    Ok(events)
+}
+
+/// Split a CSV row into its fields, a quoted field may hold commas and doubled quotes `""`.
+fn fields(line: &str) -> Result<Vec<String>, TraceErrorType> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().unwrap();
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            ',' if !quoted => fields.push(String::new()),
+            c => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(TraceErrorType::InvalidRow);
+    }
+    Ok(fields)
+}
+
+fn parse_event(input: &str, value: Option<&str>) -> Result<Event, TraceErrorType> {
+    let value = match (INPUTS.get(input), value) {
+        (None, _) => return Err(TraceErrorType::UndefinedInput),
+        (Some(Types::Signal(SignalType::Signal(SimpleType::Unit))), None) => Value::Unit,
+        (Some(Types::Signal(SignalType::Signal(SimpleType::Unit))), Some("()")) => Value::Unit,
+        (Some(Types::Signal(SignalType::Signal(SimpleType::Unit))), Some(_)) => {
+            return Err(TraceErrorType::UnexpectedValue)
+        }
+        (Some(Types::Signal(SignalType::Signal(SimpleType::Int))), Some(value)) => {
+            match value.parse::<BigInt>() {
+                Ok(n) => Value::Int(n),
+                Err(_) => return Err(TraceErrorType::InvalidValue),
+            }
+        }
+        (Some(_), None) => return Err(TraceErrorType::MissingValue),
+        (Some(_), Some(_)) => return Err(TraceErrorType::InvalidValue),
+    };
+
+    Ok(Event {
+        input: input.to_owned(),
+        value,
+    })
+}
+
+/// Run a signal graph against a sequence of events, and return the value of the graph after each
+/// event. The subgraphs of async nodes are waited for before the next event, so the same trace
+/// always produces the same values.
+pub fn simulate(graph: SignalGraph, events: &[Event]) -> Result<Vec<Value>, RuntimeError> {
+    let mut reactor = Reactor::new(graph);
+
+    let mut values = vec![];
+    for event in events {
+        reactor.dispatch(event)?;
+        reactor.settle()?;
+        values.push(reactor.value()?);
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_trace, simulate};
+    use crate::error::TraceErrorType;
+    use crate::evaluator::eval_root;
+    use crate::graph::SignalGraph;
+    use crate::value::Value;
+    use rustelm_analyzer::{modules::check_with, typechecker::typecheck_program};
+    use rustelm_parser::parser::{parse, parse_program};
+    use std::path::Path;
+
+    fn run(source: &str, trace: &str) -> Vec<String> {
+        let graph = match eval_root(&parse(source).unwrap()).unwrap() {
+            Value::Signal(signal) => SignalGraph::new(&signal),
+            _ => panic!("expected a signal"),
+        };
+
+        simulate(graph, &parse_trace(trace).unwrap())
+            .unwrap()
+            .iter()
+            .map(|v| format!("{:?}", v))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_trace() {
+        let events = parse_trace(
+            "input,value\nMouseX,10\nMouseClicks,\n\n\"MouseY\", \"-3\"\nMouseClicks\n",
+        )
+        .unwrap();
+        let events: Vec<_> = events
+            .iter()
+            .map(|e| format!("{}={:?}", e.input, e.value))
+            .collect();
+        assert_eq!(
+            events,
+            vec!["MouseX=10", "MouseClicks=()", "MouseY=-3", "MouseClicks=()"]
+        );
+
+        let err = |trace: &str| parse_trace(trace).unwrap_err();
+        assert!(matches!(
+            err("Keyboard,1").1,
+            TraceErrorType::UndefinedInput
+        ));
+        assert!(matches!(err("MouseX").1, TraceErrorType::MissingValue));
+        assert!(matches!(
+            err("MouseClicks,1").1,
+            TraceErrorType::UnexpectedValue
+        ));
+        assert!(matches!(err("MouseX,ten").1, TraceErrorType::InvalidValue));
+        assert!(matches!(err("MouseX,1,2").1, TraceErrorType::InvalidRow));
+        assert!(matches!(err("MouseX,\"1").1, TraceErrorType::InvalidRow));
+        assert_eq!(err("MouseX,1\nMouseY,1,MouseX").0, 2);
+
+        // The header is only skipped on the first line
+        assert!(matches!(
+            err("MouseX,1\ninput,value").1,
+            TraceErrorType::UndefinedInput
+        ));
+    }
+
+    #[test]
+    fn test_simulate() {
+        assert_eq!(
+            run(
+                "lift2 (\\x: int. \\y: int. x * 10 + y): MouseX MouseY\n",
+                "MouseX,10\nMouseClicks,\nMouseY,3\n"
+            ),
+            vec!["100", "100", "103"]
+        );
+
+        assert_eq!(
+            run(
+                "foldp (\\x: unit. \\acc: int. acc + 1) 0 MouseClicks\n",
+                "MouseClicks\nMouseX,1\nMouseClicks\nMouseClicks\n"
+            ),
+            vec!["1", "1", "2", "3"]
+        );
+
+        // The number of moves and the distance travelled
+        assert_eq!(
+            run(
+                "foldp (\\x: int. \\acc: (int, int). let (n, d) = acc in (n + 1, d + x)) (0, 0) MouseX\n",
+                "MouseX,3\nMouseClicks\nMouseX,4\n"
+            ),
+            vec!["(1, 3)", "(1, 3)", "(2, 7)"]
+        );
+
+        // The history of the positions
+        assert_eq!(
+            run(
+                "foldp (\\x. \\history. x :: history) [] MouseX\n",
+                "MouseX,3\nMouseClicks\nMouseX,4\n"
+            ),
+            vec!["[3]", "[3]", "[4, 3]"]
+        );
+    }
+
+    #[test]
+    fn test_simulate_async() {
+        // The async result has arrived by the time the value is read
+        assert_eq!(
+            run(
+                "let slow = async (lift1 (\\x: int. x * 100): MouseX) in \
+                 lift2 (\\x: int. \\y: int. x + y): slow MouseY\n",
+                "MouseX,2\nMouseY,1\n"
+            ),
+            vec!["200", "201"]
+        );
+    }
+
+    #[test]
+    fn test_simulate_program() {
+        let program = parse_program(
+            "type alias Count = signal int.\n\
+             total : Count\n\
+             total = foldp step 0 clicks\n\
+             \n\
+             clicks = lift1 (\\_. 1): MouseClicks\n\
+             step n acc = acc + n\n\
+             main =\n  lift2 (\\x y. x * y): total MouseX\n",
+        )
+        .unwrap();
+        let graph = match eval_root(&typecheck_program(&program).unwrap()).unwrap() {
+            Value::Signal(signal) => SignalGraph::new(&signal),
+            _ => panic!("expected a signal"),
+        };
+        let values: Vec<_> = simulate(graph, &parse_trace("MouseX,2\nMouseClicks\n").unwrap())
+            .unwrap()
+            .iter()
+            .map(|v| format!("{:?}", v))
+            .collect();
+        assert_eq!(values, vec!["0", "2"]);
+    }
+
+    #[test]
+    fn test_simulate_modules() {
+        let counter = "module Counter exposing (clicks, Step)\n\
+                       type Step = Up | Down\n\
+                       clicks = foldp (\\s n. case s of { Up -> n + 1; Down -> n - 1 }) 0 steps\n\
+                       steps = lift1 (\\_. Up): MouseClicks\n";
+        let main = "import Counter\n\
+                    main = lift2 (\\n x. n * x): Counter.clicks MouseX\n";
+        let mut read = |_: &Path| Ok(counter.to_owned());
+        let expr = check_with(
+            Path::new(""),
+            Path::new("main.elm"),
+            main.to_owned(),
+            &mut read,
+        );
+        let graph = match eval_root(&expr.unwrap()).unwrap() {
+            Value::Signal(signal) => SignalGraph::new(&signal),
+            _ => panic!("expected a signal"),
+        };
+        let values: Vec<_> = simulate(graph, &parse_trace("MouseClicks\nMouseX,3\n").unwrap())
+            .unwrap()
+            .iter()
+            .map(|v| format!("{:?}", v))
+            .collect();
+        assert_eq!(values, vec!["0", "3"]);
+    }
+}
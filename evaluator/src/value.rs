@@ -0,0 +1,192 @@
+use num_bigint::BigInt;
+use rustelm_parser::ast::Expr;
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Error, Formatter};
+use std::sync::Arc;
+
+/// The runtime environment, mapping names to the values they are bound to.
+pub type Env = im::HashMap<String, Value>;
+
+/// Values of the functional fragment,
+/// v ::= () | n | f | b | "s" | (v1, ..., vn) | { x1 = v1, ..., xn = vn } | [v1, ..., vn] | C v1 ... vn
+///     | \x. e (closure) | s
+#[derive(Clone)]
+pub enum Value {
+    Unit,
+    Int(BigInt),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    Tuple(Vec<Value>),
+    Record(BTreeMap<String, Value>),
+    List(Vec<Value>),
+    /// A value of a declared type, its constructor applied to all of its arguments.
+    Data(String, Vec<Value>),
+    /// A constructor still waiting for some of its arguments, the number of arguments it takes
+    /// and the ones given so far.
+    Constructor(String, usize, Vec<Value>),
+    /// A lambda together with the environment it was created in, and its parameter unless it is
+    /// `_`.
+    Closure(Option<String>, Arc<Expr>, Env),
+    /// A closure bound by `let rec`, and its name, which it sees itself as when it is applied.
+    Recursive(String, Arc<Value>),
+    /// A built-in function and the arguments it was given so far.
+    Builtin(Builtin, Vec<Value>),
+    Signal(Arc<Signal>),
+}
+
+/// The functions of the prelude.
+#[derive(Copy, Clone, Debug)]
+pub enum Builtin {
+    Map,
+    Foldl,
+    Filter,
+    Length,
+    Head,
+    Tail,
+    Show,
+    ToFloat,
+    Round,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        match name {
+            "map" => Some(Builtin::Map),
+            "foldl" => Some(Builtin::Foldl),
+            "filter" => Some(Builtin::Filter),
+            "length" => Some(Builtin::Length),
+            "head" => Some(Builtin::Head),
+            "tail" => Some(Builtin::Tail),
+            "show" => Some(Builtin::Show),
+            "toFloat" => Some(Builtin::ToFloat),
+            "round" => Some(Builtin::Round),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Map => "map",
+            Builtin::Foldl => "foldl",
+            Builtin::Filter => "filter",
+            Builtin::Length => "length",
+            Builtin::Head => "head",
+            Builtin::Tail => "tail",
+            Builtin::Show => "show",
+            Builtin::ToFloat => "toFloat",
+            Builtin::Round => "round",
+        }
+    }
+
+    /// The number of arguments the function takes before it runs.
+    pub fn arity(self) -> usize {
+        match self {
+            Builtin::Map | Builtin::Filter => 2,
+            Builtin::Foldl => 3,
+            Builtin::Length
+            | Builtin::Head
+            | Builtin::Tail
+            | Builtin::Show
+            | Builtin::ToFloat
+            | Builtin::Round => 1,
+        }
+    }
+}
+
+/// Signal terms, what signal expressions reduce to after the functional evaluation stage.
+/// s ::= i | liftn v: s1 ... sn | foldp v1 v2 s | async s
+pub enum Signal {
+    Input(String),
+    Lift(Value, Vec<Arc<Signal>>),
+    Foldp(Value, Value, Arc<Signal>),
+    Async(Arc<Signal>),
+}
+
+impl Debug for Value {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        use self::Value::*;
+        match *self {
+            Unit => write!(fmt, "()"),
+            Int(ref big_int) => write!(fmt, "{}", big_int),
+            Float(value) => write!(fmt, "{:?}", value),
+            Bool(true) => write!(fmt, "True"),
+            Bool(false) => write!(fmt, "False"),
+            Str(ref s) => write!(fmt, "{:?}", s),
+            Tuple(ref values) => {
+                write!(fmt, "(")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{:?}", value)?;
+                }
+                write!(fmt, ")")
+            }
+            Record(ref fields) if fields.is_empty() => write!(fmt, "{{}}"),
+            Record(ref fields) => {
+                write!(fmt, "{{ ")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{} = {:?}", name, value)?;
+                }
+                write!(fmt, " }}")
+            }
+            List(ref values) => {
+                write!(fmt, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{:?}", value)?;
+                }
+                write!(fmt, "]")
+            }
+            Data(ref name, ref args) => {
+                write!(fmt, "{}", name)?;
+                for arg in args {
+                    match arg {
+                        Data(_, nested) if !nested.is_empty() => write!(fmt, " ({:?})", arg)?,
+                        _ => write!(fmt, " {:?}", arg)?,
+                    }
+                }
+                Ok(())
+            }
+            Constructor(ref name, _, _) => write!(fmt, "<constructor {}>", name),
+            Closure(Some(ref name), _, _) => write!(fmt, "<function \\{}>", name),
+            Closure(None, _, _) => write!(fmt, "<function \\_>"),
+            Recursive(_, ref func) => write!(fmt, "{:?}", func),
+            Builtin(builtin, _) => write!(fmt, "<function {}>", builtin.name()),
+            Signal(ref signal) => write!(fmt, "{:?}", signal),
+        }
+    }
+}
+
+impl Debug for Signal {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        use self::Signal::*;
+        match *self {
+            Input(ref name) => write!(fmt, "{}", name),
+            Lift(ref func, ref args) => {
+                write!(fmt, "lift{} {:?}:", args.len(), func)?;
+                for arg in args {
+                    match **arg {
+                        Input(_) => write!(fmt, " {:?}", arg)?,
+                        _ => write!(fmt, " ({:?})", arg)?,
+                    }
+                }
+                Ok(())
+            }
+            Foldp(ref func, ref init, ref signal) => match **signal {
+                Input(_) => write!(fmt, "foldp {:?} {:?} {:?}", func, init, signal),
+                _ => write!(fmt, "foldp {:?} {:?} ({:?})", func, init, signal),
+            },
+            Async(ref signal) => match **signal {
+                Input(_) => write!(fmt, "async {:?}", signal),
+                _ => write!(fmt, "async ({:?})", signal),
+            },
+        }
+    }
+}
@@ -1,23 +1,200 @@
 use num_bigint::BigInt;
-use std::fmt::{Debug, Error, Formatter};
+use std::fmt::{Debug, Display, Error, Formatter};
 
-pub enum Expr {
+/// A byte range in the source text, `start` inclusive and `end` exclusive.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// The 1-based line and column where the span starts in `source`.
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        line_col(source, self.start)
+    }
+}
+
+/// Convert a byte offset into `source` to a 1-based line and column. Columns count characters,
+/// not bytes.
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// An AST node, along with the span of source text it was parsed from.
+#[derive(Clone)]
+pub struct Located<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Located<T> {
+    pub fn new(node: T, start: usize, end: usize) -> Located<T> {
+        Located {
+            node,
+            span: Span::new(start, end),
+        }
+    }
+}
+
+pub type Expr = Located<ExprKind>;
+
+#[derive(Clone)]
+pub enum ExprKind {
     // Unit, Num, and Variables
-    Const(Atom),
-    Abs(Atom, Types, Box<Expr>),
+    Const(Located<Atom>),
+    /// The parameter annotation is optional, its type is inferred otherwise.
+    Abs(Located<Atom>, Option<Located<Types>>, Box<Expr>),
+    /// A lambda of several parameters, `\\x (y: int). e`. Only the parser produces it, the
+    /// desugaring pass turns it into nested `Abs`.
+    Lambda(Vec<Located<Pattern>>, Box<Expr>),
     App(Box<Expr>, Box<Expr>),
     BinOp(Box<Expr>, BinOp, Box<Expr>),
+    UnOp(UnOp, Box<Expr>),
     If(Box<Expr>, Box<Expr>, Box<Expr>),
-    Let(Atom, Box<Expr>, Box<Expr>),
-    Lift(usize, Box<Expr>, Vec<Atom>),
+    Let(Located<Pattern>, Box<Expr>, Box<Expr>),
+    /// A function in scope of its own body, `let rec f: int -> int = \\n. ... in e`. The
+    /// annotation is required by the typechecker, the parser accepts it missing to report that.
+    LetRec(
+        Located<String>,
+        Option<Located<Types>>,
+        Box<Expr>,
+        Box<Expr>,
+    ),
+    /// A tuple of two or more components.
+    Tuple(Vec<Expr>),
+    /// A list literal, `[1, 2, 3]`.
+    List(Vec<Expr>),
+    /// A record, `{ x = 1, y = 2 }`.
+    Record(Vec<(Located<String>, Expr)>),
+    /// Access to a field of a record, `r.x`.
+    Field(Box<Expr>, Located<String>),
+    /// A copy of a record with some fields replaced, `{ r | x = 5 }`.
+    Update(Box<Expr>, Vec<(Located<String>, Expr)>),
+    /// A type declaration, in scope of its constructors in the body,
+    /// `type Shape = Circle int | Rect int int in e`.
+    TypeDef(TypeDef, Box<Expr>),
+    /// A type alias, in scope in the body, `type alias Point = (int, int) in e`.
+    TypeAlias(TypeAlias, Box<Expr>),
+    /// Pattern matching, the first branch whose pattern matches the value is taken.
+    Case(Box<Expr>, Vec<(Located<Pattern>, Expr)>),
+    Lift(usize, Box<Expr>, Vec<Located<Atom>>),
     Foldp(Box<Expr>, Box<Expr>, Box<Expr>),
+    Async(Box<Expr>),
 }
 
+#[derive(Clone)]
 pub enum Atom {
     Unit,
     Num(BigInt),
+    Float(f64),
+    Bool(bool),
+    Str(String),
     Var(String),
     Signal(String), // Input
+    /// `_` as the parameter of a function, which binds nothing.
+    Wildcard,
+}
+
+/// What a `let` or a branch of a `case` matches its value against.
+#[derive(Clone)]
+pub enum Pattern {
+    Var(String),
+    /// `_`, matches anything without binding it.
+    Wildcard,
+    /// A unit, integer, boolean or string literal.
+    Literal(Atom),
+    Tuple(Vec<Located<Pattern>>),
+    /// A list of exactly as many elements as there are patterns, `[]` or `[x, y]`.
+    List(Vec<Located<Pattern>>),
+    /// A list of at least one element, its head and its tail, `x :: xs`.
+    Cons(Box<Located<Pattern>>, Box<Located<Pattern>>),
+    /// A constructor applied to a pattern for each of its arguments, `Rect w h`. In a `let`, a
+    /// lowercase name applied to patterns is a function definition instead, `let f x = e`.
+    Constructor(String, Vec<Located<Pattern>>),
+    /// A pattern along with the type of the values it matches, `(x: int)`.
+    Annotated(Box<Located<Pattern>>, Located<Types>),
+}
+
+/// The left hand side of a `let` as it is parsed, before the function definitions become
+/// lambdas.
+pub enum Binding {
+    /// `let p = e`
+    Pattern(Located<Pattern>),
+    /// `let f x y = e`, the name and the parameters.
+    Function(Located<String>, Vec<Located<Pattern>>),
+}
+
+impl Pattern {
+    /// A pattern made of a single name. Like in Elm, capitalized names are constructors and the
+    /// others are variables.
+    pub fn name(name: String) -> Pattern {
+        if name.starts_with(char::is_uppercase) {
+            Pattern::Constructor(name, vec![])
+        } else {
+            Pattern::Var(name)
+        }
+    }
+}
+
+/// A type declaration, `type Maybe a = Nothing | Just a`.
+#[derive(Clone)]
+pub struct TypeDef {
+    pub name: Located<String>,
+    pub params: Vec<Located<String>>,
+    pub constructors: Vec<Constructor>,
+}
+
+/// A new name for a type, `type alias Point = (int, int)`. The alias stands for the type it
+/// names wherever it is used, and its parameters for the arguments it is given.
+#[derive(Clone)]
+pub struct TypeAlias {
+    pub name: Located<String>,
+    pub params: Vec<Located<String>>,
+    pub ty: Located<Types>,
+}
+
+/// A constructor of a declared type, along with the types of its arguments, `Rect int int`.
+#[derive(Clone)]
+pub struct Constructor {
+    pub name: Located<String>,
+    pub args: Vec<Located<SimpleType>>,
+}
+
+/// A whole program, or a module of one, its declarations in the order they are written.
+#[derive(Clone)]
+pub struct Program {
+    pub header: Option<Header>,
+    /// The names of the imported modules, `import Foo`.
+    pub imports: Vec<Located<String>>,
+    pub decls: Vec<Decl>,
+}
+
+/// The name of a module and the names it exposes, all of them for `module Foo exposing (..)`.
+#[derive(Clone)]
+pub struct Header {
+    pub name: Located<String>,
+    pub exposing: Option<Vec<Located<String>>>,
+}
+
+/// A top-level declaration, each of them starts a line.
+#[derive(Clone)]
+pub enum Decl {
+    /// A type declaration, `type Maybe a = Nothing | Just a`.
+    Type(TypeDef),
+    /// A type alias, `type alias Point = (int, int)`.
+    Alias(TypeAlias),
+    /// The type of the definition right after it, `main : signal int.`.
+    Signature(Located<String>, Located<Types>),
+    /// A definition, `x = e`, or `f x y = e` for a function.
+    Value(Located<String>, Expr),
 }
 
 /// Types
@@ -29,12 +206,27 @@ pub enum Types {
 }
 
 /// Simple types,
-/// t ::= unit | int | t -> t'
+/// t ::= unit | int | bool | float | string | t -> t' | (t1, ..., tn) | { x1 : t1, ..., xn : tn } | list t
+///     | T t1 ... tn | a
 #[derive(Clone, PartialEq)]
 pub enum SimpleType {
     Unit,
     Int,
+    Bool,
+    Float,
+    String,
     Abs(Box<SimpleType>, Box<SimpleType>),
+    Tuple(Vec<SimpleType>),
+    /// The fields of a record, sorted by name. A record left open by inference also names the
+    /// type variable standing for its other fields, `{ a | x : int }`.
+    Record(Vec<(String, SimpleType)>, Option<String>),
+    List(Box<SimpleType>),
+    /// A declared type applied to its arguments, `Shape` or `Maybe int`. Inside a type
+    /// declaration, a name without arguments may also be one of its parameters.
+    Named(String, Vec<SimpleType>),
+    /// A type variable left over by inference, e.g. the `a` in `a -> a`. Type variables always
+    /// stand for simple types.
+    Var(String),
 }
 
 /// Signal types,
@@ -45,13 +237,19 @@ pub enum SignalType {
     Signal(SimpleType),
     Abs1(SimpleType, Box<SignalType>),
     Abs2(Box<SignalType>, Box<SignalType>),
+    /// An alias standing for a signal type, `Mouse`. Only inferred types use it, the parser
+    /// reads every name as a simple type and the typechecker expands it.
+    Named(String, Vec<SimpleType>),
 }
 
 #[derive(Copy, Clone)]
 pub enum BinOp {
-    // Arithmetic
+    // Arithmetic, integer division truncates toward zero. `%` is the remainder of that division,
+    // with the sign of the dividend, and `mod` the modulus, with the sign of the divisor.
     Mul,
     Div,
+    Rem,
+    Mod,
     Add,
     Sub,
     // Comparison
@@ -61,28 +259,83 @@ pub enum BinOp {
     Ge,
     Less,
     Greater,
+    // Logical, only the left operand is evaluated if it decides the result
+    And,
+    Or,
+    // Lists
+    Cons,
+    // Strings and lists
+    Append,
 }
 
-impl Debug for Expr {
+#[derive(Copy, Clone)]
+pub enum UnOp {
+    Not,
+    Neg,
+}
+
+/// Spans are left out, a located node prints the same as the node itself.
+impl<T: Debug> Debug for Located<T> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        self.node.fmt(fmt)
+    }
+}
+
+impl Debug for ExprKind {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
-        use self::Expr::*;
+        use self::ExprKind::*;
         match *self {
             Const(ref c) => write!(fmt, "{:?}", c),
-            Abs(ref atom, ref ty, ref e1) => match atom {
-                Atom::Var(ref name) => write!(fmt, "\\{:?}: {:?}. -> {:?}", name, ty, e1),
-                _ => unreachable!(),
+            Abs(ref atom, ref ty, ref e1) => match ty {
+                Some(ty) => write!(fmt, "\\{:?}: {:?}. -> {:?}", atom, ty, e1),
+                None => write!(fmt, "\\{:?}. -> {:?}", atom, e1),
             },
+            Lambda(ref params, ref e) => {
+                write!(fmt, "\\")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, " ")?;
+                    }
+                    write!(fmt, "{:?}", param)?;
+                }
+                write!(fmt, ". -> {:?}", e)
+            }
             App(ref e1, ref e2) => write!(fmt, "({:?} {:?})", e1, e2),
             BinOp(ref e1, op, ref e2) => write!(fmt, "({:?} {:?} {:?})", e1, op, e2),
+            UnOp(op, ref e) => write!(fmt, "({:?} {:?})", op, e),
             If(ref pred, ref e1, ref e2) => write!(
                 fmt,
                 "if ( {:?} ) then {{ {:?} }} else {{ {:?} }}",
                 pred, e1, e2
             ),
-            Let(ref atom, ref e1, ref e2) => match atom {
-                Atom::Var(ref name) => write!(fmt, "let {:?} = {:?} in {:?}", name, e1, e2),
-                _ => unreachable!(),
+            Let(ref pattern, ref e1, ref e2) => {
+                write!(fmt, "let {:?} = {:?} in {:?}", pattern, e1, e2)
+            }
+            LetRec(ref name, ref ty, ref e1, ref e2) => match ty {
+                Some(ty) => write!(
+                    fmt,
+                    "let rec {:?}: {:?} = {:?} in {:?}",
+                    name.node, ty, e1, e2
+                ),
+                None => write!(fmt, "let rec {:?} = {:?} in {:?}", name.node, e1, e2),
             },
+            Tuple(ref es) => fmt_tuple(fmt, es),
+            List(ref es) => fmt_list(fmt, es),
+            Record(ref fields) => fmt_fields(fmt, None, fields, "="),
+            Field(ref e, ref field) => write!(fmt, "{:?}.{}", e, field.node),
+            Update(ref e, ref fields) => fmt_fields(fmt, Some(&format!("{:?}", e)), fields, "="),
+            TypeDef(ref def, ref e) => write!(fmt, "{:?} in {:?}", def, e),
+            TypeAlias(ref def, ref e) => write!(fmt, "{:?} in {:?}", def, e),
+            Case(ref e, ref branches) => {
+                write!(fmt, "case {:?} of {{ ", e)?;
+                for (i, (pattern, body)) in branches.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, "; ")?;
+                    }
+                    write!(fmt, "{:?} -> {:?}", pattern, body)?;
+                }
+                write!(fmt, " }}")
+            }
             Lift(ref n, ref expr, ref vec) => {
                 write!(fmt, "lift{:?} {:?}", n, expr).unwrap();
                 for e in vec {
@@ -90,11 +343,159 @@ impl Debug for Expr {
                 }
                 write!(fmt, "!")
             }
-            Foldp(_, _, _) => write!(fmt, ""),
+            Foldp(ref e1, ref e2, ref e3) => write!(fmt, "foldp {:?} {:?} {:?}", e1, e2, e3),
+            Async(ref e) => write!(fmt, "async {:?}", e),
         }
     }
 }
 
+/// Write `(a, b, c)`, formatting each item with `Debug`.
+fn fmt_tuple<T: Debug>(fmt: &mut Formatter, items: &[T]) -> Result<(), Error> {
+    write!(fmt, "(")?;
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(fmt, ", ")?;
+        }
+        write!(fmt, "{:?}", item)?;
+    }
+    write!(fmt, ")")
+}
+
+/// Write `[a, b, c]`, formatting each item with `Debug`.
+fn fmt_list<T: Debug>(fmt: &mut Formatter, items: &[T]) -> Result<(), Error> {
+    write!(fmt, "[")?;
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(fmt, ", ")?;
+        }
+        write!(fmt, "{:?}", item)?;
+    }
+    write!(fmt, "]")
+}
+
+/// Write `{ x = 1, y = 2 }`, or `{ r | x = 1 }` if there is a `base`.
+fn fmt_fields<N: Display, T: Debug>(
+    fmt: &mut Formatter,
+    base: Option<&str>,
+    fields: &[(N, T)],
+    sep: &str,
+) -> Result<(), Error> {
+    if fields.is_empty() && base.is_none() {
+        return write!(fmt, "{{}}");
+    }
+
+    write!(fmt, "{{ ")?;
+    if let Some(base) = base {
+        write!(fmt, "{} | ", base)?;
+    }
+    for (i, (name, value)) in fields.iter().enumerate() {
+        if i > 0 {
+            write!(fmt, ", ")?;
+        }
+        write!(fmt, "{} {} {:?}", name, sep, value)?;
+    }
+    write!(fmt, " }}")
+}
+
+impl<T: Display> Display for Located<T> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        self.node.fmt(fmt)
+    }
+}
+
+impl Debug for Pattern {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            Pattern::Var(ref name) => write!(fmt, "{:?}", name),
+            Pattern::Wildcard => write!(fmt, "_"),
+            Pattern::Literal(ref atom) => write!(fmt, "{:?}", atom),
+            Pattern::Tuple(ref patterns) => fmt_tuple(fmt, patterns),
+            Pattern::List(ref patterns) => fmt_list(fmt, patterns),
+            Pattern::Cons(ref head, ref tail) => match head.node {
+                Pattern::Cons(_, _) => write!(fmt, "({:?}) :: {:?}", head, tail),
+                _ => write!(fmt, "{:?} :: {:?}", head, tail),
+            },
+            Pattern::Constructor(ref name, ref args) => {
+                write!(fmt, "{}", name)?;
+                for arg in args {
+                    match arg.node {
+                        Pattern::Constructor(_, ref nested) if !nested.is_empty() => {
+                            write!(fmt, " ({:?})", arg)?
+                        }
+                        _ => write!(fmt, " {:?}", arg)?,
+                    }
+                }
+                Ok(())
+            }
+            Pattern::Annotated(ref pattern, ref ty) => write!(fmt, "({:?}: {:?})", pattern, ty),
+        }
+    }
+}
+
+impl Debug for TypeDef {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(fmt, "type {}", self.name)?;
+        for param in &self.params {
+            write!(fmt, " {}", param)?;
+        }
+        for (i, constructor) in self.constructors.iter().enumerate() {
+            write!(
+                fmt,
+                " {} {}",
+                if i == 0 { "=" } else { "|" },
+                constructor.name
+            )?;
+            for arg in &constructor.args {
+                write!(fmt, " ")?;
+                arg.node.fmt_arg(fmt)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Debug for TypeAlias {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(fmt, "type alias {}", self.name)?;
+        for param in &self.params {
+            write!(fmt, " {}", param)?;
+        }
+        write!(fmt, " = {}", self.ty.node)
+    }
+}
+
+/// One declaration per line, after the header and the imports.
+impl Debug for Program {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        if let Some(header) = &self.header {
+            let exposing = match &header.exposing {
+                Some(names) => names
+                    .iter()
+                    .map(|name| name.node.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                None => "..".to_owned(),
+            };
+            writeln!(fmt, "module {} exposing ({})", header.name.node, exposing)?;
+        }
+        for import in &self.imports {
+            writeln!(fmt, "import {}", import.node)?;
+        }
+        for (i, decl) in self.decls.iter().enumerate() {
+            if i > 0 {
+                writeln!(fmt)?;
+            }
+            match decl {
+                Decl::Type(def) => write!(fmt, "{:?}", def)?,
+                Decl::Alias(def) => write!(fmt, "{:?}", def)?,
+                Decl::Signature(name, ty) => write!(fmt, "{} : {:?}", name.node, ty)?,
+                Decl::Value(name, e) => write!(fmt, "{} = {:?}", name.node, e)?,
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Debug for Atom {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         use self::Atom::*;
@@ -105,8 +506,13 @@ impl Debug for Atom {
                 "{:?}",
                 big_int.to_str_radix(10).parse::<i32>().unwrap()
             ),
+            Float(value) => write!(fmt, "{:?}", value),
+            Bool(true) => write!(fmt, "True"),
+            Bool(false) => write!(fmt, "False"),
+            Str(ref str) => write!(fmt, "{:?}", str),
             Var(ref str) => write!(fmt, "{:?}", str),
             Signal(ref str) => write!(fmt, "{:?}", str),
+            Wildcard => write!(fmt, "_"),
         }
     }
 }
@@ -120,6 +526,7 @@ impl Debug for Types {
                 SignalType::Signal(sim_ty) => write!(fmt, "signal {:?}.", sim_ty),
                 SignalType::Abs1(l, r) => write!(fmt, "({:?} -> {:?})", l, r),
                 SignalType::Abs2(l, r) => write!(fmt, "({:?} -> {:?})", l, r),
+                SignalType::Named(..) => write!(fmt, "{}", ty),
             },
         }
     }
@@ -131,7 +538,14 @@ impl Debug for SimpleType {
         match *self {
             Unit => write!(fmt, "unit"),
             Int => write!(fmt, "int"),
+            Bool => write!(fmt, "bool"),
+            Float => write!(fmt, "float"),
+            String => write!(fmt, "string"),
             Abs(ref t1, ref t2) => write!(fmt, "({:?} -> {:?})", t1, t2),
+            Tuple(ref types) => fmt_tuple(fmt, types),
+            Record(ref fields, ref rest) => fmt_fields(fmt, rest.as_deref(), fields, ":"),
+            List(_) | Named(..) => write!(fmt, "{}", self),
+            Var(ref name) => write!(fmt, "{}", name),
         }
     }
 }
@@ -143,6 +557,134 @@ impl Debug for SignalType {
             Signal(ref ty) => write!(fmt, "sig({:?})", ty),
             Abs1(ref l, ref r) => write!(fmt, "({:?} -> {:?})", l, r),
             Abs2(ref l, ref r) => write!(fmt, "({:?} -> {:?})", l, r),
+            Named(..) => write!(fmt, "{}", self),
+        }
+    }
+}
+
+/// Types are displayed the way they are written in the source, e.g. `int -> signal int.`. An
+/// arrow nested in either side of another arrow is put in parentheses.
+impl Display for Types {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            Types::Simple(ref ty) => write!(fmt, "{}", ty),
+            Types::Signal(ref ty) => write!(fmt, "{}", ty),
+        }
+    }
+}
+
+impl SimpleType {
+    fn fmt_operand(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            SimpleType::Abs(_, _) => write!(fmt, "({})", self),
+            _ => write!(fmt, "{}", self),
+        }
+    }
+
+    /// Write the type as the argument of a declared type or a list, where an arrow, a list or a
+    /// declared type applied to arguments is put in parentheses.
+    fn fmt_arg(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            SimpleType::Named(_, ref args) if !args.is_empty() => write!(fmt, "({})", self),
+            SimpleType::List(_) => write!(fmt, "({})", self),
+            _ => self.fmt_operand(fmt),
+        }
+    }
+}
+
+impl Display for SimpleType {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        use self::SimpleType::*;
+        match *self {
+            Unit => write!(fmt, "unit"),
+            Int => write!(fmt, "int"),
+            Bool => write!(fmt, "bool"),
+            Float => write!(fmt, "float"),
+            String => write!(fmt, "string"),
+            Abs(ref t1, ref t2) => {
+                t1.fmt_operand(fmt)?;
+                write!(fmt, " -> ")?;
+                t2.fmt_operand(fmt)
+            }
+            Tuple(ref types) => {
+                write!(fmt, "(")?;
+                for (i, ty) in types.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{}", ty)?;
+                }
+                write!(fmt, ")")
+            }
+            Record(ref fields, ref rest) => {
+                if fields.is_empty() && rest.is_none() {
+                    return write!(fmt, "{{}}");
+                }
+                write!(fmt, "{{ ")?;
+                if let Some(rest) = rest {
+                    write!(fmt, "{} | ", rest)?;
+                }
+                for (i, (name, ty)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{} : {}", name, ty)?;
+                }
+                write!(fmt, " }}")
+            }
+            List(ref ty) => {
+                write!(fmt, "list ")?;
+                ty.fmt_arg(fmt)
+            }
+            Named(ref name, ref args) => {
+                write!(fmt, "{}", name)?;
+                for arg in args {
+                    write!(fmt, " ")?;
+                    arg.fmt_arg(fmt)?;
+                }
+                Ok(())
+            }
+            Var(ref name) => write!(fmt, "{}", name),
+        }
+    }
+}
+
+impl SignalType {
+    fn fmt_operand(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            SignalType::Signal(_) | SignalType::Named(..) => write!(fmt, "{}", self),
+            _ => write!(fmt, "({})", self),
+        }
+    }
+}
+
+impl Display for SignalType {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        use self::SignalType::*;
+        match *self {
+            Signal(ref ty) => {
+                write!(fmt, "signal ")?;
+                ty.fmt_operand(fmt)?;
+                write!(fmt, ".")
+            }
+            Abs1(ref l, ref r) => {
+                l.fmt_operand(fmt)?;
+                write!(fmt, " -> ")?;
+                r.fmt_operand(fmt)
+            }
+            Abs2(ref l, ref r) => {
+                l.fmt_operand(fmt)?;
+                write!(fmt, " -> ")?;
+                r.fmt_operand(fmt)
+            }
+            Named(ref name, ref args) => {
+                write!(fmt, "{}", name)?;
+                for arg in args {
+                    write!(fmt, " ")?;
+                    arg.fmt_arg(fmt)?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -153,6 +695,8 @@ impl Debug for BinOp {
         match *self {
             Mul => write!(fmt, "*"),
             Div => write!(fmt, "/"),
+            Rem => write!(fmt, "%"),
+            Mod => write!(fmt, "mod"),
             Add => write!(fmt, "+"),
             Sub => write!(fmt, "-"),
             Eq => write!(fmt, "=="),
@@ -161,6 +705,19 @@ impl Debug for BinOp {
             Ge => write!(fmt, ">="),
             Less => write!(fmt, "<"),
             Greater => write!(fmt, ">"),
+            And => write!(fmt, "&&"),
+            Or => write!(fmt, "||"),
+            Cons => write!(fmt, "::"),
+            Append => write!(fmt, "++"),
+        }
+    }
+}
+
+impl Debug for UnOp {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            UnOp::Not => write!(fmt, "not"),
+            UnOp::Neg => write!(fmt, "-"),
         }
     }
 }
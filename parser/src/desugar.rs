@@ -0,0 +1,184 @@
+//! Removes the syntactic sugar accepted by the parser, so that the later stages only see the core
+//! language. Lambdas of several parameters `\x y. e`, which the parser also makes of function
+//! definitions `let f x y = e`, become nested `Abs`. The new nodes keep the spans of the source
+//! they come from, so errors still point at what was written.
+
+use crate::ast::{Atom, Decl, Expr, ExprKind, Located, Pattern, Program, Span, Types};
+use crate::parser::ParserError;
+
+pub fn desugar(expr: Expr) -> Result<Expr, ParserError> {
+    let span = expr.span;
+    let node = match expr.node {
+        ExprKind::Const(atom) => ExprKind::Const(atom),
+        ExprKind::Abs(param, ty, e) => ExprKind::Abs(param, ty, boxed(*e)?),
+        ExprKind::Lambda(params, e) => return abstraction(params, desugar(*e)?, span),
+        ExprKind::App(e1, e2) => ExprKind::App(boxed(*e1)?, boxed(*e2)?),
+        ExprKind::BinOp(e1, op, e2) => ExprKind::BinOp(boxed(*e1)?, op, boxed(*e2)?),
+        ExprKind::UnOp(op, e) => ExprKind::UnOp(op, boxed(*e)?),
+        ExprKind::If(e1, e2, e3) => ExprKind::If(boxed(*e1)?, boxed(*e2)?, boxed(*e3)?),
+        ExprKind::Let(pattern, e1, e2) => ExprKind::Let(pattern, boxed(*e1)?, boxed(*e2)?),
+        ExprKind::LetRec(name, ty, e1, e2) => ExprKind::LetRec(name, ty, boxed(*e1)?, boxed(*e2)?),
+        ExprKind::Tuple(es) => ExprKind::Tuple(all(es)?),
+        ExprKind::List(es) => ExprKind::List(all(es)?),
+        ExprKind::Record(fields) => ExprKind::Record(all_fields(fields)?),
+        ExprKind::Field(e, field) => ExprKind::Field(boxed(*e)?, field),
+        ExprKind::Update(e, fields) => ExprKind::Update(boxed(*e)?, all_fields(fields)?),
+        ExprKind::TypeDef(def, e) => ExprKind::TypeDef(def, boxed(*e)?),
+        ExprKind::TypeAlias(def, e) => ExprKind::TypeAlias(def, boxed(*e)?),
+        ExprKind::Case(e, branches) => ExprKind::Case(
+            boxed(*e)?,
+            branches
+                .into_iter()
+                .map(|(pattern, e)| desugar(e).map(|e| (pattern, e)))
+                .collect::<Result<_, _>>()?,
+        ),
+        ExprKind::Lift(n, e, args) => ExprKind::Lift(n, boxed(*e)?, args),
+        ExprKind::Foldp(e1, e2, e3) => ExprKind::Foldp(boxed(*e1)?, boxed(*e2)?, boxed(*e3)?),
+        ExprKind::Async(e) => ExprKind::Async(boxed(*e)?),
+    };
+    Ok(Located { node, span })
+}
+
+pub fn desugar_program(program: Program) -> Result<Program, ParserError> {
+    let decls = program
+        .decls
+        .into_iter()
+        .map(|decl| match decl {
+            Decl::Value(name, e) => desugar(e).map(|e| Decl::Value(name, e)),
+            decl => Ok(decl),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Program { decls, ..program })
+}
+
+fn boxed(expr: Expr) -> Result<Box<Expr>, ParserError> {
+    desugar(expr).map(Box::new)
+}
+
+fn all(exprs: Vec<Expr>) -> Result<Vec<Expr>, ParserError> {
+    exprs.into_iter().map(desugar).collect()
+}
+
+fn all_fields(
+    fields: Vec<(Located<String>, Expr)>,
+) -> Result<Vec<(Located<String>, Expr)>, ParserError> {
+    fields
+        .into_iter()
+        .map(|(name, e)| desugar(e).map(|e| (name, e)))
+        .collect()
+}
+
+/// Nested lambdas taking `params` in order and returning `body`. The outermost one spans `span`,
+/// each inner one spans from its parameter to the end of the body.
+fn abstraction(params: Vec<Located<Pattern>>, body: Expr, span: Span) -> Result<Expr, ParserError> {
+    let mut expr = body;
+    for param in params.into_iter().rev() {
+        let start = param.span.start;
+        let (param, ty) = parameter(param)?;
+        expr = Located::new(ExprKind::Abs(param, ty, Box::new(expr)), start, span.end);
+    }
+    expr.span = span;
+    Ok(expr)
+}
+
+/// The name of a parameter and its annotation, if any.
+fn parameter(
+    param: Located<Pattern>,
+) -> Result<(Located<Atom>, Option<Located<Types>>), ParserError> {
+    let span = param.span;
+    match param.node {
+        Pattern::Var(name) => Ok((
+            Located {
+                node: Atom::Var(name),
+                span,
+            },
+            None,
+        )),
+        Pattern::Wildcard => Ok((
+            Located {
+                node: Atom::Wildcard,
+                span,
+            },
+            None,
+        )),
+        Pattern::Annotated(pattern, ty) => match pattern.node {
+            Pattern::Var(name) => Ok((
+                Located {
+                    node: Atom::Var(name),
+                    span: pattern.span,
+                },
+                Some(ty),
+            )),
+            _ => Err(ParserError::InvalidParameter(span)),
+        },
+        _ => Err(ParserError::InvalidParameter(span)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::{parse, ParserError};
+
+    /// The debug print of a desugared expression.
+    fn debug(source: &str) -> String {
+        format!("{:?}", parse(source).unwrap())
+    }
+
+    #[test]
+    fn test_lambda() {
+        assert_eq!(debug("\\x. x\n"), "\\\"x\". -> \"x\"");
+        assert_eq!(
+            debug("\\x y. x + y\n"),
+            "\\\"x\". -> \\\"y\". -> (\"x\" + \"y\")"
+        );
+        assert_eq!(
+            debug("\\(x: int) y (z: bool). x\n"),
+            "\\\"x\": int. -> \\\"y\". -> \\\"z\": bool. -> \"x\""
+        );
+
+        // Each lambda spans from its parameter to the end of the body
+        let expr = parse("\\x (y: int). y\n").unwrap();
+        assert_eq!((expr.span.start, expr.span.end), (0, 14));
+        match expr.node {
+            crate::ast::ExprKind::Abs(_, _, inner) => {
+                assert_eq!((inner.span.start, inner.span.end), (3, 14))
+            }
+            _ => panic!("expected a lambda"),
+        }
+    }
+
+    #[test]
+    fn test_function() {
+        assert_eq!(
+            debug("let add (x: int) (y: int) = x + y in add 1 2\n"),
+            "let \"add\" = \\\"x\": int. -> \\\"y\": int. -> (\"x\" + \"y\") in ((\"add\" 1) 2)"
+        );
+        assert_eq!(
+            debug("let const x _ = x in const\n"),
+            "let \"const\" = \\\"x\". -> \\_. -> \"x\" in \"const\""
+        );
+
+        // Nested definitions are desugared too
+        assert_eq!(
+            debug("(let f x = x in f, [\\a b. a])\n"),
+            "(let \"f\" = \\\"x\". -> \"x\" in \"f\", [\\\"a\". -> \\\"b\". -> \"a\"])"
+        );
+
+        // A capitalized name is still a constructor pattern
+        assert_eq!(
+            debug("let Point x y = p in x\n"),
+            "let Point \"x\" \"y\" = \"p\" in \"x\""
+        );
+        assert_eq!(
+            debug("let Geo.Point x y :: ps = p in x\n"),
+            "let Geo.Point \"x\" \"y\" :: \"ps\" = \"p\" in \"x\""
+        );
+
+        match parse("let f (x, y) = x in f\n") {
+            Err(e @ ParserError::InvalidParameter(_)) => {
+                assert_eq!((e.span().start, e.span().end), (6, 12))
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
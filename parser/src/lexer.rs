@@ -1,4 +1,6 @@
+use crate::ast::Span;
 use crate::tokens::Token;
+use std::fmt;
 use std::str::CharIndices;
 
 use num_bigint::BigInt;
@@ -6,10 +8,10 @@ use num_traits::Num;
 
 /// Some helper functions used in Lexer
 fn is_symbol(ch: char) -> bool {
-    match ch {
-        '!' | ':' | ',' | '=' | '/' | '>' | '<' | '-' | '+' | '*' => true,
-        _ => false,
-    }
+    matches!(
+        ch,
+        '!' | ':' | ',' | '=' | '/' | '%' | '>' | '<' | '-' | '+' | '*' | '&' | '|'
+    )
 }
 
 /// In our case, we can assume the source code is ASCII.
@@ -22,12 +24,48 @@ fn is_ident_continue(ch: char) -> bool {
 }
 
 fn is_dec_digit(ch: char) -> bool {
-    ch.is_digit(10)
+    ch.is_ascii_digit()
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum LexicalError {
-    UnexpectedCharacter,
+    /// A character that can not start any token, and where it is.
+    UnexpectedCharacter(usize, char),
+    /// A run of symbols that is not an operator, and where it starts.
+    UnknownOperator(usize, String),
+    /// A string literal missing its closing quote on the same line, and where it starts.
+    UnterminatedString(usize),
+    /// An escape sequence that does not exist, where its backslash is and the character after it.
+    InvalidEscape(usize, char),
+    /// A block comment missing its closing `-}`, and where it starts.
+    UnterminatedComment(usize),
+}
+
+impl LexicalError {
+    /// The span of the offending input.
+    pub fn span(&self) -> Span {
+        match self {
+            LexicalError::UnexpectedCharacter(start, ch) => {
+                Span::new(*start, start + ch.len_utf8())
+            }
+            LexicalError::UnknownOperator(start, op) => Span::new(*start, start + op.len()),
+            LexicalError::UnterminatedString(start) => Span::new(*start, start + 1),
+            LexicalError::InvalidEscape(start, ch) => Span::new(*start, start + 1 + ch.len_utf8()),
+            LexicalError::UnterminatedComment(start) => Span::new(*start, start + 2),
+        }
+    }
+}
+
+impl fmt::Display for LexicalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexicalError::UnexpectedCharacter(_, ch) => write!(f, "unexpected character {:?}", ch),
+            LexicalError::UnknownOperator(_, op) => write!(f, "unknown operator '{}'", op),
+            LexicalError::UnterminatedString(_) => write!(f, "unterminated string literal"),
+            LexicalError::InvalidEscape(_, ch) => write!(f, "unknown escape sequence '\\{}'", ch),
+            LexicalError::UnterminatedComment(_) => write!(f, "unterminated block comment"),
+        }
+    }
 }
 
 pub type Spanned<Tok, Loc, Error> = Result<(Loc, Tok, Loc), Error>;
@@ -38,6 +76,11 @@ pub struct Lexer<'input> {
     source: &'input str,
     lookahead: Option<(usize, char)>,
     chars: CharIndices<'input>,
+    /// Whether a token at the start of a line begins a new declaration.
+    layout: bool,
+    /// The position of the token the last `NewLine` was yielded before.
+    line_break: Option<usize>,
+    emitted: bool,
 }
 
 impl<'input> Lexer<'input> {
@@ -49,6 +92,20 @@ impl<'input> Lexer<'input> {
             source,
             lookahead: chars.next(),
             chars,
+            layout: false,
+            line_break: None,
+            emitted: false,
+        }
+    }
+
+    /// Create a lexer for the source of a whole program. Like in Elm, declarations start at the
+    /// beginning of a line and the lines continuing them are indented, so a `NewLine` token is
+    /// yielded before each token in the first column, except the first token. Comments do not
+    /// count, a token following a comment on its line continues the declaration.
+    pub fn program(source: &'input str) -> Self {
+        Lexer {
+            layout: true,
+            ..Lexer::new(source)
         }
     }
 
@@ -70,7 +127,7 @@ impl<'input> Lexer<'input> {
     where
         F: FnMut(char) -> bool,
     {
-        self.lookahead.map_or(false, |(_, ch)| pred(ch))
+        self.lookahead.is_some_and(|(_, ch)| pred(ch))
     }
 
     /// Consume characters while the predicate matches for the current character, then return the
@@ -99,25 +156,64 @@ impl<'input> Lexer<'input> {
             }
         }
 
-        (0, self.chars.as_str())
+        let end = self.source.len();
+        (end, self.slice(start, end))
     }
 
-    /// Consume an identifier, or they might be keywords.
+    /// Consume an identifier, or they might be keywords. Capitalized names joined by dots are a
+    /// qualified name instead, like `Foo.Bar.Just`. A dot before a lowercase name is left to the
+    /// parser, it qualifies a value `Foo.bar` or ends a lambda annotation `\\s: Shape.s`.
     fn lex_ident(&mut self, start: usize) -> (usize, Token, usize) {
-        let (end, ident) = self.take_while(start, is_ident_continue);
+        let (mut end, mut ident) = self.take_while(start, is_ident_continue);
+        let mut qualified = false;
+        loop {
+            let rest = &self.source[end..];
+            let module = ident.rsplit('.').next().unwrap();
+            if !(module.starts_with(char::is_uppercase)
+                && rest.starts_with('.')
+                && rest[1..].starts_with(char::is_uppercase))
+            {
+                break;
+            }
+            self.bump();
+            let (next, next_ident) = self.take_while(start, is_ident_continue);
+            end = next;
+            ident = next_ident;
+            qualified = true;
+        }
+        if qualified {
+            return (start, Token::QualifiedName(ident.to_string()), end);
+        }
 
         let token = match ident {
             "if" => Token::If,
             "else" => Token::Else,
             "then" => Token::Then,
             "let" => Token::Let,
+            "rec" => Token::Rec,
             "in" => Token::In,
             "int" => Token::Int,
+            "bool" => Token::Bool,
+            "float" => Token::Float,
+            "string" => Token::String,
+            "list" => Token::List,
+            "True" => Token::True,
+            "False" => Token::False,
+            "not" => Token::Not,
+            "mod" => Token::Mod,
+            "type" => Token::Type,
+            "alias" => Token::Alias,
+            "case" => Token::Case,
+            "of" => Token::Of,
+            "module" => Token::Module,
+            "exposing" => Token::Exposing,
+            "import" => Token::Import,
+            "_" => Token::Underscore,
             "unit" => Token::Unit,
             "signal" => Token::Signal,
             "async" => Token::Async,
             "foldp" => Token::Foldp,
-            ident if ident.starts_with("lift") => {
+            ident if ident.starts_with("lift") && ident[4..].parse::<usize>().is_ok() => {
                 Token::LiftN(ident[4..].parse::<usize>().unwrap())
             }
             ident => Token::Name(ident.to_string()),
@@ -126,11 +222,112 @@ impl<'input> Lexer<'input> {
         (start, token, end)
     }
 
-    /// Consume a decimal literal
+    /// Consume a string literal, whose opening quote at `start` is consumed already. Strings end
+    /// on the line they start, and may contain the escapes `\n`, `\t`, `\r`, `\\` and `\"`.
+    fn lex_string(&mut self, start: usize) -> Spanned<Token, usize, LexicalError> {
+        let mut value = String::new();
+        let mut error = None;
+
+        while let Some((pos, ch)) = self.bump() {
+            match ch {
+                '"' => {
+                    return match error {
+                        Some(error) => Err(error),
+                        None => Ok((start, Token::LitString(value), pos + 1)),
+                    }
+                }
+                '\n' => break,
+                '\\' => match self.bump() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, 'r')) => value.push('\r'),
+                    Some((_, '\\')) => value.push('\\'),
+                    Some((_, '"')) => value.push('"'),
+                    // Keep going until the end of the string, reporting the first bad escape
+                    Some((_, ch)) if ch != '\n' => {
+                        error.get_or_insert(LexicalError::InvalidEscape(pos, ch));
+                    }
+                    _ => break,
+                },
+                ch => value.push(ch),
+            }
+        }
+
+        Err(LexicalError::UnterminatedString(start))
+    }
+
+    /// Consume the comment at the lookahead, if any, returning whether there was one. A line
+    /// comment `-- ...` ends with the line, before its newline, and a block comment `{- ... -}`
+    /// may contain other block comments.
+    fn skip_comment(&mut self) -> Result<bool, LexicalError> {
+        let start = match self.lookahead {
+            Some((start, _)) => start,
+            None => return Ok(false),
+        };
+
+        let rest = &self.source[start..];
+        if rest.starts_with("--") {
+            self.take_until(start, |ch| ch == '\n');
+            return Ok(true);
+        }
+        if !rest.starts_with("{-") {
+            return Ok(false);
+        }
+
+        let mut depth = 0;
+        while let Some((pos, _)) = self.lookahead {
+            let rest = &self.source[pos..];
+            if rest.starts_with("{-") {
+                depth += 1;
+                self.bump();
+            } else if rest.starts_with("-}") {
+                depth -= 1;
+                self.bump();
+                if depth == 0 {
+                    self.bump();
+                    return Ok(true);
+                }
+            }
+            self.bump();
+        }
+        Err(LexicalError::UnterminatedComment(start))
+    }
+
+    /// Consume a decimal literal, an integer unless it has a fraction or an exponent, like `3.14`
+    /// or `1e-3`. Both must be followed by digits, so `1.` is still the integer before a dot.
     fn lex_number(&mut self, start: usize) -> (usize, Token, usize) {
-        let (end, src) = self.take_while(start, is_dec_digit);
-        let value = BigInt::from_str_radix(src, 10).unwrap();
-        (start, Token::LitInt(value), end)
+        let (mut end, mut src) = self.take_while(start, is_dec_digit);
+        let mut float = false;
+
+        let rest = &self.source[end..];
+        if rest.starts_with('.') && rest[1..].starts_with(is_dec_digit) {
+            self.bump();
+            let (next, next_src) = self.take_while(start, is_dec_digit);
+            end = next;
+            src = next_src;
+            float = true;
+        }
+
+        let rest = &self.source[end..];
+        if rest.starts_with(['e', 'E']) {
+            let sign = rest[1..].starts_with(['+', '-']) as usize;
+            if rest[1 + sign..].starts_with(is_dec_digit) {
+                for _ in 0..=sign {
+                    self.bump();
+                }
+                let (next, next_src) = self.take_while(start, is_dec_digit);
+                end = next;
+                src = next_src;
+                float = true;
+            }
+        }
+
+        let token = if float {
+            Token::LitFloat(src.parse().unwrap())
+        } else {
+            Token::LitInt(BigInt::from_str_radix(src, 10).unwrap())
+        };
+        (start, token, end)
     }
 }
 
@@ -142,10 +339,32 @@ impl<'input> Iterator for Lexer<'input> {
     /// The basic idea is that we will take a look at the next character, if any, and decide upon
     /// the next steps.
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some((start, ch)) = self.bump() {
+        loop {
+            // Comments are skipped like whitespace
+            match self.skip_comment() {
+                Ok(true) => continue,
+                Ok(false) => (),
+                Err(e) => return Some(Err(e)),
+            }
+
+            if let Some((pos, ch)) = self.lookahead {
+                let first_column = pos == 0 || self.source[..pos].ends_with('\n');
+                if self.layout
+                    && self.emitted
+                    && first_column
+                    && !ch.is_whitespace()
+                    && self.line_break != Some(pos)
+                {
+                    self.line_break = Some(pos);
+                    return Some(Ok((pos, Token::NewLine, pos)));
+                }
+            }
+
+            // We have reached the end.
+            let (start, ch) = self.bump()?;
             let end = start + 1;
 
-            return Some(match ch {
+            let token = match ch {
                 ch if is_symbol(ch) => {
                     let (end, symbol) = self.take_while(start, is_symbol);
 
@@ -154,7 +373,10 @@ impl<'input> Iterator for Lexer<'input> {
                         "-" => Ok((start, Token::Minus, end)),
                         "*" => Ok((start, Token::Mul, end)),
                         "/" => Ok((start, Token::Div, end)),
+                        "%" => Ok((start, Token::Percent, end)),
                         ":" => Ok((start, Token::Colon, end)),
+                        "::" => Ok((start, Token::ColonColon, end)),
+                        "++" => Ok((start, Token::PlusPlus, end)),
                         "," => Ok((start, Token::Comma, end)),
                         "=" => Ok((start, Token::Eq, end)),
                         "->" => Ok((start, Token::LArrow, end)),
@@ -164,26 +386,34 @@ impl<'input> Iterator for Lexer<'input> {
                         "<=" => Ok((start, Token::Leq, end)),
                         "==" => Ok((start, Token::EqEqual, end)),
                         "!=" => Ok((start, Token::Ne, end)),
-                        _ => Err(LexicalError::UnexpectedCharacter),
+                        "&&" => Ok((start, Token::AndAnd, end)),
+                        "||" => Ok((start, Token::OrOr, end)),
+                        "|" => Ok((start, Token::Bar, end)),
+                        _ => Err(LexicalError::UnknownOperator(start, symbol.to_owned())),
                     }
                 }
                 '.' => Ok((start, Token::Dot, end)),
+                '"' => self.lex_string(start),
                 '\\' => Ok((start, Token::BSlash, end)),
                 '(' if self.test_lookahead(|c| c == ')') => {
                     self.bump();
-                    Ok((start, Token::LitUnit, end))
+                    Ok((start, Token::LitUnit, end + 1))
                 }
                 '(' => Ok((start, Token::LParen, end)),
                 ')' => Ok((start, Token::RParen, end)),
+                '{' => Ok((start, Token::LBrace, end)),
+                '}' => Ok((start, Token::RBrace, end)),
+                ';' => Ok((start, Token::Semi, end)),
+                '[' => Ok((start, Token::LBracket, end)),
+                ']' => Ok((start, Token::RBracket, end)),
                 ch if is_ident_start(ch) => Ok(self.lex_ident(start)),
                 ch if is_dec_digit(ch) => Ok(self.lex_number(start)),
                 ch if ch.is_whitespace() => continue,
-                _ => Err(LexicalError::UnexpectedCharacter),
-            });
+                ch => Err(LexicalError::UnexpectedCharacter(start, ch)),
+            };
+            self.emitted = true;
+            return Some(token);
         }
-
-        // We have reached the end.
-        None
     }
 }
 
@@ -214,6 +444,48 @@ mod test {
             LitInt(BigInt::from(333)),
             LitInt(BigInt::from(4444))
         }
+
+        test! {
+            "2.75 0.5 1e-3 2E+2 1e3\n",
+            LitFloat(2.75),
+            LitFloat(0.5),
+            LitFloat(0.001),
+            LitFloat(200.0),
+            LitFloat(1000.0)
+        }
+
+        // The dot of a signal type and a field are not fractions, nor is a name an exponent
+        test! {
+            "1. 2.x 3e 4e-\n",
+            LitInt(BigInt::from(1)),
+            Dot,
+            LitInt(BigInt::from(2)),
+            Dot,
+            Name("x".to_owned()),
+            LitInt(BigInt::from(3)),
+            Name("e".to_owned()),
+            LitInt(BigInt::from(4)),
+            Name("e".to_owned()),
+            Minus
+        }
+    }
+
+    #[test]
+    fn test_strings() {
+        test! {
+            "\"\" \"hello, world\" \"\\\"\\n\\t\\r\\\\\" \"a ++ b\"\n",
+            LitString("".to_owned()),
+            LitString("hello, world".to_owned()),
+            LitString("\"\n\t\r\\".to_owned()),
+            LitString("a ++ b".to_owned())
+        }
+
+        test! {
+            "s ++ \"!\"\n",
+            Name("s".to_owned()),
+            PlusPlus,
+            LitString("!".to_owned())
+        }
     }
 
     #[test]
@@ -231,6 +503,36 @@ mod test {
             Async,
             Foldp
         }
+
+        test! {
+            "bool float True False not mod rec\n",
+            Bool,
+            Float,
+            True,
+            False,
+            Not,
+            Mod,
+            Rec
+        }
+
+        test! {
+            "type alias case of _ _x list string\n",
+            Type,
+            Alias,
+            Case,
+            Of,
+            Underscore,
+            Name("_x".to_owned()),
+            List,
+            String
+        }
+
+        test! {
+            "module exposing import\n",
+            Module,
+            Exposing,
+            Import
+        }
     }
 
     #[test]
@@ -239,17 +541,59 @@ mod test {
             "Elm4Rust_\n",
             Name("Elm4Rust_".to_owned())
         }
+
+        // Only capitalized names are joined, the parser decides what a dot before a lowercase
+        // name means
+        test! {
+            "Foo.bar Foo.Bar.Just r.x Foo. X\n",
+            Name("Foo".to_owned()),
+            Dot,
+            Name("bar".to_owned()),
+            QualifiedName("Foo.Bar.Just".to_owned()),
+            Name("r".to_owned()),
+            Dot,
+            Name("x".to_owned()),
+            Name("Foo".to_owned()),
+            Dot,
+            Name("X".to_owned())
+        }
+
+        // So the lexer needs no context to leave the dot ending a lambda annotation alone
+        test! {
+            "\\s: Shape.s \\p: Geo.Point.x \\m: signal Geo.T..m\n",
+            BSlash,
+            Name("s".to_owned()),
+            Colon,
+            Name("Shape".to_owned()),
+            Dot,
+            Name("s".to_owned()),
+            BSlash,
+            Name("p".to_owned()),
+            Colon,
+            QualifiedName("Geo.Point".to_owned()),
+            Dot,
+            Name("x".to_owned()),
+            BSlash,
+            Name("m".to_owned()),
+            Colon,
+            Signal,
+            QualifiedName("Geo.T".to_owned()),
+            Dot,
+            Dot,
+            Name("m".to_owned())
+        }
     }
 
     #[test]
     fn test_symbols() {
         test! {
-            "() + - * / \\ , . -> = > < >= <= == !=\n",
+            "() + - * / % \\ , . -> = > < >= <= == != && || ::\n",
             LitUnit,
             Plus,
             Minus,
             Mul,
             Div,
+            Percent,
             BSlash,
             Comma,
             Dot,
@@ -260,16 +604,120 @@ mod test {
             Geq,
             Leq,
             EqEqual,
-            Ne
+            Ne,
+            AndAnd,
+            OrOr,
+            ColonColon
         }
     }
 
     #[test]
     fn test_delimiters() {
         test! {
-            "( )\n",
+            "( ) { } | ; [ ]\n",
             LParen,
-            RParen
+            RParen,
+            LBrace,
+            RBrace,
+            Bar,
+            Semi,
+            LBracket,
+            RBracket
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        use super::LexicalError::*;
+
+        let errors: Vec<_> = Lexer::new("1 ' x => y\n").filter_map(Result::err).collect();
+        assert_eq!(
+            errors,
+            vec![
+                UnexpectedCharacter(2, '\''),
+                UnknownOperator(6, "=>".to_owned())
+            ]
+        );
+
+        // A bad escape does not end the string
+        let errors: Vec<_> = Lexer::new("\"a\\qb\" \"c\nd\n")
+            .filter_map(Result::err)
+            .collect();
+        assert_eq!(errors, vec![InvalidEscape(2, 'q'), UnterminatedString(7)]);
+
+        // Only `lift` followed by a number is a keyword
+        test! {
+            "lift2 lifted lift\n",
+            LiftN(2),
+            Name("lifted".to_owned()),
+            Name("lift".to_owned())
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        use super::LexicalError::*;
+
+        test! {
+            "1 -- one\n- {- two {- nested -} -} 2 --\n{--}3-1\n",
+            LitInt(BigInt::from(1)),
+            Minus,
+            LitInt(BigInt::from(2)),
+            LitInt(BigInt::from(3)),
+            Minus,
+            LitInt(BigInt::from(1))
+        }
+
+        // An unterminated block comment is reported where it starts
+        let errors: Vec<_> = Lexer::new("1 {- a {- b -}\n-} {- c\n")
+            .filter_map(Result::err)
+            .collect();
+        assert_eq!(errors, vec![UnterminatedComment(18)]);
+        let error = Lexer::new("x {- {- -}\n").find_map(Result::err).unwrap();
+        assert_eq!((error.span().start, error.span().end), (2, 4));
+        assert_eq!(error.to_string(), "unterminated block comment");
+
+        // Lines starting with a comment do not start a declaration
+        let tokens: Vec<_> = Lexer::program("-- x\nx = 1 -- one\n{- a\nb -}\n  -- c\ny = x\n")
+            .map(|x| x.unwrap().1)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Name("x".to_owned()),
+                Eq,
+                LitInt(BigInt::from(1)),
+                NewLine,
+                Name("y".to_owned()),
+                Eq,
+                Name("x".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_program() {
+        let tokens: Vec<_> = Lexer::program("\nx = 1\n\nmain =\n  x\n")
+            .map(|x| x.unwrap())
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (1, Name("x".to_owned()), 2),
+                (3, Eq, 4),
+                (5, LitInt(BigInt::from(1)), 6),
+                (8, NewLine, 8),
+                (8, Name("main".to_owned()), 12),
+                (13, Eq, 14),
+                (17, Name("x".to_owned()), 18)
+            ]
+        );
+
+        // Only programs are split into declarations
+        test! {
+            "x\ny\n",
+            Name("x".to_owned()),
+            Name("y".to_owned())
         }
     }
 }
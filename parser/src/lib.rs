@@ -3,11 +3,12 @@ extern crate lalrpop_util;
 use lalrpop_util::lalrpop_mod;
 
 pub mod ast;
+pub mod desugar;
 pub mod lexer;
 pub mod parser;
 pub mod tokens;
 
 lalrpop_mod!(
-    #[allow(clippy::all)]
+    #[allow(clippy::all, unused_parens)]
     pub elm
 );
@@ -1,25 +1,152 @@
-// use lalrpop_util::ParseError as LalrParseError;
+use lalrpop_util::ParseError as LalrParseError;
+use std::{error, fmt};
 
+use crate::ast::Span;
+use crate::desugar::{desugar, desugar_program};
+use crate::lexer::LexicalError;
+use crate::tokens::Token;
 use crate::{ast, elm, lexer};
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum ParserError {
-    LalrError,
+    /// Found `token` where one of the `expected` tokens should be.
+    UnexpectedToken {
+        token: Token,
+        span: Span,
+        expected: Vec<String>,
+    },
+    /// The input ended where one of the `expected` tokens should be.
+    UnexpectedEof {
+        location: usize,
+        expected: Vec<String>,
+    },
+    /// Found `token` after a complete expression.
+    ExtraToken { token: Token, span: Span },
+    /// The input at the start of the span is not a token.
+    InvalidToken(Span),
+    /// The lexer could not make a token out of the input.
+    Lexical(LexicalError),
+    /// A parameter of a function definition is not a name, `let f (x, y) = e`.
+    InvalidParameter(Span),
+    /// A function definition is annotated, `let f x : t = e`.
+    AnnotatedDefinition(Span),
+}
+
+impl ParserError {
+    /// The span of the offending input.
+    pub fn span(&self) -> Span {
+        match self {
+            ParserError::UnexpectedToken { span, .. } | ParserError::ExtraToken { span, .. } => {
+                *span
+            }
+            ParserError::UnexpectedEof { location, .. } => Span::new(*location, *location),
+            ParserError::Lexical(e) => e.span(),
+            ParserError::InvalidToken(span)
+            | ParserError::InvalidParameter(span)
+            | ParserError::AnnotatedDefinition(span) => *span,
+        }
+    }
+}
+
+impl From<LalrParseError<usize, Token, ParserError>> for ParserError {
+    fn from(e: LalrParseError<usize, Token, ParserError>) -> Self {
+        match e {
+            LalrParseError::InvalidToken { location } => {
+                ParserError::InvalidToken(Span::new(location, location))
+            }
+            LalrParseError::UnrecognizedEOF { location, expected } => {
+                ParserError::UnexpectedEof { location, expected }
+            }
+            LalrParseError::UnrecognizedToken {
+                token: (start, token, end),
+                expected,
+            } => ParserError::UnexpectedToken {
+                token,
+                span: Span::new(start, end),
+                expected,
+            },
+            LalrParseError::ExtraToken {
+                token: (start, token, end),
+            } => ParserError::ExtraToken {
+                token,
+                span: Span::new(start, end),
+            },
+            LalrParseError::User { error } => error,
+        }
+    }
+}
+
+fn fmt_expected(f: &mut fmt::Formatter<'_>, expected: &[String]) -> fmt::Result {
+    match expected.len() {
+        0 => Ok(()),
+        1 => write!(f, ", expected {}", expected[0]),
+        _ => write!(f, ", expected one of {}", expected.join(", ")),
+    }
+}
+
+impl fmt::Display for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParserError::UnexpectedToken {
+                token, expected, ..
+            } => {
+                write!(f, "unexpected token {}", token)?;
+                fmt_expected(f, expected)
+            }
+            ParserError::UnexpectedEof { expected, .. } => {
+                write!(f, "unexpected end of input")?;
+                fmt_expected(f, expected)
+            }
+            ParserError::ExtraToken { token, .. } => write!(f, "unexpected token {}", token),
+            ParserError::InvalidToken(_) => write!(f, "invalid token"),
+            ParserError::Lexical(e) => write!(f, "{}", e),
+            ParserError::InvalidParameter(_) => write!(
+                f,
+                "a parameter must be a name, optionally with a type `(x: t)`"
+            ),
+            ParserError::AnnotatedDefinition(_) => write!(
+                f,
+                "a function definition can not be annotated, annotate a lambda instead \
+                 `let f : t = \\x. e`"
+            ),
+        }
+    }
+}
+
+impl error::Error for ParserError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
 }
 
 pub fn parse(input: &str) -> Result<Box<ast::Expr>, ParserError> {
     assert!(input.ends_with('\n'));
 
-    let lxr = lexer::Lexer::new(input);
-    match elm::ExprParser::new().parse(lxr) {
-        Err(_) => Err(ParserError::LalrError),
-        Ok(value) => Ok(value),
-    }
+    let lxr = lexer::Lexer::new(input).map(|token| token.map_err(ParserError::Lexical));
+    let expr = elm::ExprParser::new().parse(lxr)?;
+    desugar(*expr).map(Box::new)
+}
+
+/// Parse the source of a whole program, a declaration per line.
+pub fn parse_program(input: &str) -> Result<ast::Program, ParserError> {
+    let lxr = lexer::Lexer::program(input).map(|token| token.map_err(ParserError::Lexical));
+    let program = elm::ProgramParser::new().parse(lxr)?;
+    desugar_program(program)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::parse;
+    use super::{parse, parse_program};
+
+    /// The debug print of a parsed expression.
+    fn debug(source: &str) -> String {
+        format!("{:?}", parse(source).unwrap())
+    }
+
+    /// The debug print of a parsed program.
+    fn debug_program(source: &str) -> String {
+        format!("{:?}", parse_program(source).unwrap())
+    }
 
     #[test]
     fn test_constant() {
@@ -52,6 +179,12 @@ mod tests {
             "\\\"x\": (int -> (int -> int)). -> \"x\""
         );
 
+        // Without annotation
+        assert_eq!(
+            &format!("{:?}", parse("\\x. \\y: int. x\n").unwrap()),
+            "\\\"x\". -> \\\"y\": int. -> \"x\""
+        );
+
         // Signal Types
         assert_eq!(
             &format!("{:?}", parse("\\x: signal unit.. x\n").unwrap()),
@@ -75,6 +208,11 @@ mod tests {
             &format!("{:?}", parse("(\\ x: int. 1) 1\n").unwrap()),
             "(\\\"x\": int. -> 1 1)"
         );
+
+        // Application binds tighter than the binary operators
+        assert_eq!(debug("n * f (n - 1)\n"), "(\"n\" * (\"f\" (\"n\" - 1)))");
+        assert_eq!(debug("f x + g y\n"), "((\"f\" \"x\") + (\"g\" \"y\"))");
+        assert_eq!(debug("f -1\n"), "(\"f\" - 1)");
     }
 
     #[test]
@@ -116,7 +254,32 @@ mod tests {
         assert_eq!(
             &format!("{:?}", parse("1 + 2 * 3 == 3 * 2 + 1\n").unwrap()),
             "((1 + (2 * 3)) == ((3 * 2) + 1))"
-        )
+        );
+
+        // Logical operators bind looser than comparisons, `not` tighter than anything
+        assert_eq!(
+            &format!("{:?}", parse("not a || b && 1 < 2\n").unwrap()),
+            "((not \"a\") || (\"b\" && (1 < 2)))"
+        );
+        assert_eq!(
+            &format!("{:?}", parse("True && not (False)\n").unwrap()),
+            "(True && (not False))"
+        );
+
+        // `%` and `mod` bind like `*`, unary minus like `not`
+        assert_eq!(
+            &format!("{:?}", parse("a + b % 3 * c mod 2\n").unwrap()),
+            "(\"a\" + (((\"b\" % 3) * \"c\") mod 2))"
+        );
+        assert_eq!(
+            &format!("{:?}", parse("-5 - -x * 2\n").unwrap()),
+            "((- 5) - ((- \"x\") * 2))"
+        );
+        assert_eq!(&format!("{:?}", parse("f -1\n").unwrap()), "(\"f\" - 1)");
+        assert_eq!(
+            &format!("{:?}", parse("case n of { -1 -> 0; _ -> n }\n").unwrap()),
+            "case \"n\" of { -1 -> 0; _ -> \"n\" }"
+        );
     }
 
     #[test]
@@ -134,8 +297,47 @@ mod tests {
             "let \"x\" = 1 in let \"y\" = 2 in let \"z\" = 3 in ((\"x\" + \"y\") + \"z\")"
         );
 
-        assert!(parse("let 1 = 1 + 2 in x\n").is_err());
+        assert!(parse("let x + 1 = 1 + 2 in x\n").is_err());
         assert!(parse("let 1 = 1 + 2\n").is_err());
+
+        assert_eq!(
+            &format!(
+                "{:?}",
+                parse("let rec f: int -> int = \\n. f n in f 1\n").unwrap()
+            ),
+            "let rec \"f\": (int -> int) = \\\"n\". -> (\"f\" \"n\") in (\"f\" 1)"
+        );
+        assert_eq!(
+            &format!("{:?}", parse("let rec f = f in f\n").unwrap()),
+            "let rec \"f\" = \"f\" in \"f\""
+        );
+        assert!(parse("let rec (f, g) = (1, 2) in f\n").is_err());
+
+        // A binding may be annotated with its type
+        assert_eq!(
+            &format!("{:?}", parse("let speed : int = 1 in speed\n").unwrap()),
+            "let (\"speed\": int) = 1 in \"speed\""
+        );
+        assert_eq!(
+            &format!(
+                "{:?}",
+                parse("let (x, y) : (int, bool) = p in x\n").unwrap()
+            ),
+            "let ((\"x\", \"y\"): (int, bool)) = \"p\" in \"x\""
+        );
+        let expr = parse("let s : signal int. = MouseX in s\n").unwrap();
+        match expr.node {
+            crate::ast::ExprKind::Let(pattern, _, _) => {
+                assert_eq!((pattern.span.start, pattern.span.end), (4, 19))
+            }
+            _ => panic!("expected a let"),
+        }
+        match parse("let f x : int = x in f\n") {
+            Err(e @ super::ParserError::AnnotatedDefinition(_)) => {
+                assert_eq!((e.span().start, e.span().end), (4, 13))
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
     }
 
     #[test]
@@ -150,4 +352,414 @@ mod tests {
             "lift2 \\\"x\": int. -> \\\"y\": int. -> () \"MouseX\" \"MouseY\"!"
         );
     }
+
+    #[test]
+    fn test_foldp() {
+        assert_eq!(
+            &format!(
+                "{:?}",
+                parse("foldp (\\x: unit. \\acc: int. acc + 1) 0 MouseClicks\n").unwrap()
+            ),
+            "foldp \\\"x\": unit. -> \\\"acc\": int. -> (\"acc\" + 1) 0 \"MouseClicks\""
+        );
+
+        assert_eq!(
+            &format!("{:?}", parse("let f = foldp f (1 + 1) s in f\n").unwrap()),
+            "let \"f\" = foldp \"f\" (1 + 1) \"s\" in \"f\""
+        );
+
+        assert!(parse("foldp f 0\n").is_err());
+        assert!(parse("foldp f 0 s t\n").is_err());
+    }
+
+    #[test]
+    fn test_async() {
+        assert_eq!(
+            &format!("{:?}", parse("async MouseX\n").unwrap()),
+            "async \"MouseX\""
+        );
+
+        assert_eq!(
+            &format!(
+                "{:?}",
+                parse("async (lift1 (\\x: int. x): MouseX)\n").unwrap()
+            ),
+            "async lift1 \\\"x\": int. -> \"x\" \"MouseX\"!"
+        );
+
+        assert!(parse("async\n").is_err());
+        assert!(parse("async MouseX MouseY\n").is_err());
+    }
+
+    #[test]
+    fn test_span() {
+        use crate::ast::{ExprKind, Span};
+
+        let source = "let x = 1 + 2 in\n  \\y: int. x\n";
+        let expr = parse(source).unwrap();
+        assert_eq!(expr.span, Span::new(0, source.len() - 1));
+
+        match expr.node {
+            ExprKind::Let(ref x, ref e1, ref e2) => {
+                assert_eq!(x.span, Span::new(4, 5));
+                assert_eq!(e1.span, Span::new(8, 13));
+                assert_eq!(e2.span, Span::new(19, 29));
+                assert_eq!(e2.span.line_col(source), (2, 3));
+
+                match e2.node {
+                    ExprKind::Abs(_, Some(ref ty), ref body) => {
+                        assert_eq!(&source[ty.span.start..ty.span.end], "int");
+                        assert_eq!(body.span.line_col(source), (2, 12));
+                    }
+                    _ => panic!("expected a lambda"),
+                }
+            }
+            _ => panic!("expected a let"),
+        }
+
+        // Unit is two characters long
+        assert_eq!(parse("()\n").unwrap().span, Span::new(0, 2));
+    }
+
+    #[test]
+    fn test_errors() {
+        use super::ParserError;
+        use crate::ast::Span;
+        use crate::lexer::LexicalError;
+        use crate::tokens::Token;
+
+        match parse("if 1 then 2 in 3\n").unwrap_err() {
+            ParserError::UnexpectedToken {
+                token,
+                span,
+                expected,
+            } => {
+                assert_eq!(token, Token::In);
+                assert_eq!(span, Span::new(12, 14));
+                assert!(expected.contains(&"\"else\"".to_owned()));
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+
+        let e = parse("let x = 1 in\n").unwrap_err();
+        assert_eq!(e.span(), Span::new(12, 12));
+        assert!(format!("{}", e).starts_with("unexpected end of input, expected one of"));
+
+        let e = parse("x ' y\n").unwrap_err();
+        assert!(matches!(
+            e,
+            ParserError::Lexical(LexicalError::UnexpectedCharacter(2, '\''))
+        ));
+        assert_eq!(format!("{}", e), "unexpected character '\\''");
+
+        // A bad character inside an expression is reported where it is, not as the end of input
+        let e = parse("1 + ' 2\n").unwrap_err();
+        assert_eq!(e.span(), Span::new(4, 5));
+        assert_eq!(format!("{}", e), "unexpected character '\\''");
+        let e = ParserError::from(lalrpop_util::ParseError::InvalidToken { location: 4 });
+        assert!(matches!(e, ParserError::InvalidToken(_)));
+        assert_eq!(e.span(), Span::new(4, 4));
+        assert_eq!(format!("{}", e), "invalid token");
+
+        let e = parse("())\n").unwrap_err();
+        assert!(matches!(e, ParserError::UnexpectedToken { .. }));
+        assert_eq!(format!("{}", e), "unexpected token ')'");
+
+        let e = parse("x ++ \"abc\n").unwrap_err();
+        assert_eq!(e.span(), Span::new(5, 6));
+        assert_eq!(format!("{}", e), "unterminated string literal");
+
+        let e = parse("\"a\\b\"\n").unwrap_err();
+        assert_eq!(e.span(), Span::new(2, 4));
+        assert_eq!(format!("{}", e), "unknown escape sequence '\\b'");
+    }
+
+    #[test]
+    fn test_display_types() {
+        use crate::ast::ExprKind;
+
+        let display = |source: &str| match parse(source).unwrap().node {
+            ExprKind::Abs(_, Some(ref ty), _) => format!("{}", ty.node),
+            _ => panic!("expected a lambda"),
+        };
+
+        assert_eq!(
+            display("\\x: int -> int -> int. x\n"),
+            "(int -> int) -> int"
+        );
+        assert_eq!(
+            display("\\x: int -> (int -> int). x\n"),
+            "int -> (int -> int)"
+        );
+        assert_eq!(display("\\x: signal unit.. x\n"), "signal unit.");
+        assert_eq!(
+            display("\\x: (int -> int) -> signal int -> int.. x\n"),
+            "(int -> int) -> signal (int -> int)."
+        );
+        assert_eq!(
+            display("\\x: signal int. -> signal int.. x\n"),
+            "signal int. -> signal int."
+        );
+    }
+
+    #[test]
+    fn test_tuple() {
+        assert_eq!(
+            &format!("{:?}", parse("(1, (2, x))\n").unwrap()),
+            "(1, (2, \"x\"))"
+        );
+        assert_eq!(
+            &format!("{:?}", parse("f (1, 2)\n").unwrap()),
+            "(\"f\" (1, 2))"
+        );
+        assert_eq!(
+            &format!("{:?}", parse("let (x, (y, z)) = p in x\n").unwrap()),
+            "let (\"x\", (\"y\", \"z\")) = \"p\" in \"x\""
+        );
+        assert_eq!(
+            &format!("{:?}", parse("\\p: (int, bool -> int). p\n").unwrap()),
+            "\\\"p\": (int, (bool -> int)). -> \"p\""
+        );
+
+        assert!(parse("(1,)\n").is_err());
+        assert!(parse("let (x + 1, y) = p in x\n").is_err());
+    }
+
+    #[test]
+    fn test_record() {
+        assert_eq!(debug("{ x = 1, y = True }\n"), "{ x = 1, y = True }");
+        assert_eq!(debug("{}\n"), "{}");
+        assert_eq!(debug("r.x.y\n"), "\"r\".x.y");
+        assert_eq!(debug("f r.x\n"), "(\"f\" \"r\".x)");
+        assert_eq!(
+            debug("{ r | x = r.x + 1 }\n"),
+            "{ \"r\" | x = (\"r\".x + 1) }"
+        );
+
+        // Field access and the dot ending lambda parameters and signal types
+        assert_eq!(debug("\\r. r.x\n"), "\\\"r\". -> \"r\".x");
+        assert_eq!(
+            debug("\\r: { y : int, x : bool }. r.x\n"),
+            "\\\"r\": { x : bool, y : int }. -> \"r\".x"
+        );
+        assert_eq!(
+            debug("\\s: signal { x : int }.. s\n"),
+            "\\\"s\": signal { x : int }.. -> \"s\""
+        );
+
+        assert!(parse("{ x = 1 | y = 2 }\n").is_err());
+        assert!(parse("r.1\n").is_err());
+    }
+
+    #[test]
+    fn test_type_def() {
+        assert_eq!(
+            debug("type Shape = Circle int | Rect int int in Circle 1\n"),
+            "type Shape = Circle int | Rect int int in (\"Circle\" 1)"
+        );
+        assert_eq!(
+            debug("type Maybe a = Nothing | Just a in Nothing\n"),
+            "type Maybe a = Nothing | Just a in \"Nothing\""
+        );
+        assert_eq!(
+            debug("type T = A (int -> int) (Maybe int) { x : int } in A\n"),
+            "type T = A (int -> int) (Maybe int) { x : int } in \"A\""
+        );
+
+        // Declared types in annotations
+        assert_eq!(
+            debug("\\m: Maybe (Maybe int) -> Shape. m\n"),
+            "\\\"m\": (Maybe (Maybe int) -> Shape). -> \"m\""
+        );
+        assert_eq!(
+            debug("type Shape = Sq int in \\s: Shape.s\n"),
+            "type Shape = Sq int in \\\"s\": Shape. -> \"s\""
+        );
+
+        assert!(parse("type Shape in 1\n").is_err());
+        assert!(parse("type Shape = Circle int\n").is_err());
+    }
+
+    #[test]
+    fn test_type_alias() {
+        assert_eq!(
+            debug("type alias Point = (int, int) in \\p: Point. p\n"),
+            "type alias Point = (int, int) in \\\"p\": Point. -> \"p\""
+        );
+        assert_eq!(
+            debug("type alias Scaled a = a -> signal a. in 1\n"),
+            "type alias Scaled a = a -> signal a. in 1"
+        );
+        assert_eq!(
+            debug("type alias Pair a = (a, a) in \\p: Pair (Maybe int). p\n"),
+            "type alias Pair a = (a, a) in \\\"p\": Pair (Maybe int). -> \"p\""
+        );
+
+        assert!(parse("type alias Point in 1\n").is_err());
+        assert!(parse("type alias Point = A | B in 1\n").is_err());
+        assert!(parse("let alias = 1 in alias\n").is_err());
+    }
+
+    #[test]
+    fn test_case() {
+        assert_eq!(
+            debug("case s of { Circle r -> r * r; Rect w h -> w * h }\n"),
+            "case \"s\" of { Circle \"r\" -> (\"r\" * \"r\"); Rect \"w\" \"h\" -> (\"w\" * \"h\") }"
+        );
+        assert_eq!(
+            debug("case m of { Just (Just _) -> 1; Just Nothing -> 2; x -> 3; }\n"),
+            "case \"m\" of { Just (Just _) -> 1; Just Nothing -> 2; \"x\" -> 3 }"
+        );
+        assert_eq!(
+            debug("case p of { (0, True) -> (); (_, ()) -> () }\n"),
+            "case \"p\" of { (0, True) -> (); (_, ()) -> () }"
+        );
+
+        // A nested case is closed by its braces
+        assert_eq!(
+            debug("case a of { X -> case b of { Y -> 1 }; Z -> 2 }\n"),
+            "case \"a\" of { X -> case \"b\" of { Y -> 1 }; Z -> 2 }"
+        );
+
+        assert!(parse("case s of Circle r -> r\n").is_err());
+        assert!(parse("case s of { Circle (r) s -> r }\n").is_ok());
+        assert!(parse("case s of { _ x -> 1 }\n").is_err());
+    }
+
+    #[test]
+    fn test_list() {
+        assert_eq!(debug("[1, 2, 3]\n"), "[1, 2, 3]");
+        assert_eq!(debug("[]\n"), "[]");
+
+        // `::` associates to the right, and binds looser than arithmetic
+        assert_eq!(debug("1 :: 2 :: xs\n"), "(1 :: (2 :: \"xs\"))");
+        assert_eq!(debug("x + 1 :: xs\n"), "((\"x\" + 1) :: \"xs\")");
+        assert_eq!(debug("x :: xs == ys\n"), "((\"x\" :: \"xs\") == \"ys\")");
+
+        assert_eq!(
+            debug("\\l: list (list int). l\n"),
+            "\\\"l\": list (list int). -> \"l\""
+        );
+        assert_eq!(
+            debug("case l of { [] -> 0; [x] -> x; x :: y :: rest -> x }\n"),
+            "case \"l\" of { [] -> 0; [\"x\"] -> \"x\"; \"x\" :: \"y\" :: \"rest\" -> \"x\" }"
+        );
+        assert_eq!(
+            debug("case l of { Just x :: _ -> x; (a :: b) :: c -> a }\n"),
+            "case \"l\" of { Just \"x\" :: _ -> \"x\"; (\"a\" :: \"b\") :: \"c\" -> \"a\" }"
+        );
+
+        assert!(parse("[1, 2\n").is_err());
+        assert!(parse("\\l: list. l\n").is_err());
+    }
+
+    #[test]
+    fn test_string() {
+        assert_eq!(debug("\"a\\tb\"\n"), "\"a\\tb\"");
+        assert_eq!(
+            debug("\"x = \" ++ (show x) ++ \"!\"\n"),
+            "(\"x = \" ++ ((\"show\" \"x\") ++ \"!\"))"
+        );
+        assert_eq!(
+            debug("\\s: string. case s of { \"\" -> 0; _ -> 1 }\n"),
+            "\\\"s\": string. -> case \"s\" of { \"\" -> 0; _ -> 1 }"
+        );
+    }
+
+    #[test]
+    fn test_float() {
+        assert_eq!(debug("1.5 * 2e-1\n"), "(1.5 * 0.2)");
+        assert_eq!(
+            debug("\\x: float. x / 2.0\n"),
+            "\\\"x\": float. -> (\"x\" / 2.0)"
+        );
+        assert_eq!(
+            debug("\\x: signal int.. x\n"),
+            "\\\"x\": signal int.. -> \"x\""
+        );
+
+        // Floats can not be patterns
+        assert!(parse("case x of { 1.5 -> 0; _ -> 1 }\n").is_err());
+    }
+
+    #[test]
+    fn test_program() {
+        assert_eq!(debug_program("main = 1\n"), "main = 1");
+        assert_eq!(
+            debug_program("type T = A | B int\n\nx : int\nx =\n  1 +\n    2\nmain = x\n"),
+            "type T = A | B int\nx : int\nx = (1 + 2)\nmain = \"x\""
+        );
+        assert_eq!(
+            debug_program("type alias Mouse = signal int.\nmain : Mouse\nmain = MouseX\n"),
+            "type alias Mouse = signal int.\nmain : Mouse\nmain = \"MouseX\""
+        );
+        assert_eq!(
+            debug_program("add (x: int) y = x + y\nconst _ = 0\n"),
+            "add = \\\"x\": int. -> \\\"y\". -> (\"x\" + \"y\")\nconst = \\_. -> 0"
+        );
+
+        // A definition spans its body, a function also its parameters
+        let program = parse_program("f x = x\n").unwrap();
+        match &program.decls[0] {
+            crate::ast::Decl::Value(name, e) => {
+                assert_eq!((name.span.start, name.span.end), (0, 1));
+                assert_eq!((e.span.start, e.span.end), (2, 7));
+            }
+            _ => panic!("expected a definition"),
+        }
+
+        // Declarations start at the beginning of a line
+        assert!(parse_program("x = 1\n  y = 2\n").is_err());
+        assert!(parse_program("x = 1 y = 2\n").is_err());
+        assert!(parse_program("x =\n1\n").is_err());
+        assert!(parse_program("\n").is_err());
+
+        // Only the column of the first token after the comments counts
+        assert_eq!(debug_program("{- header -}\nmain = 1\n"), "main = 1");
+        assert_eq!(
+            debug_program("x = 1\n-- note\n{- block -}\ny = x\n"),
+            "x = 1\ny = \"x\""
+        );
+        assert_eq!(debug_program("x =\n{- note -} 1\n"), "x = 1");
+        assert!(parse_program("x = 1\n{- note -} y = 2\n").is_err());
+        assert!(parse_program("x = 1 {- note\n-}y = 2\n").is_err());
+    }
+
+    #[test]
+    fn test_module() {
+        assert_eq!(
+            debug_program(
+                "module Shapes.Circle exposing (..)\nimport Shapes\nimport Geo.Point\nr = 1\n"
+            ),
+            "module Shapes.Circle exposing (..)\nimport Shapes\nimport Geo.Point\nr = 1"
+        );
+        assert_eq!(
+            debug_program("module Foo exposing (x, T)\nx = 1\n"),
+            "module Foo exposing (x, T)\nx = 1"
+        );
+
+        // Qualified names refer to values, constructors and types of other modules
+        assert_eq!(
+            debug_program("x : Foo.T int\nx = case Foo.y of { Foo.A n -> Foo.f n; Foo.B -> r.z }\n"),
+            "x : Foo.T int\nx = case \"Foo.y\" of { Foo.A \"n\" -> (\"Foo.f\" \"n\"); Foo.B -> \"r\".z }"
+        );
+
+        // The header comes first, then the imports
+        assert!(parse_program("import Foo\nmodule Bar exposing (..)\nx = 1\n").is_err());
+        assert!(parse_program("x = 1\nimport Foo\n").is_err());
+        assert!(parse_program("module Foo exposing ()\nx = 1\n").is_err());
+        assert!(parse_program("Foo.x = 1\n").is_err());
+
+        // The lexer only joins capitalized names, the grammar qualifies the value after them
+        assert_eq!(debug("Geo.Point.x r.Geo\n"), "(\"Geo.Point.x\" \"r\".Geo)");
+        assert_eq!(debug("\\s: Geo.Shape.s\n"), "\\\"s\": Geo.Shape. -> \"s\"");
+        assert_eq!(
+            debug("\\s: Shape. Circle 3\n"),
+            "\\\"s\": Shape. -> (\"Circle\" 3)"
+        );
+        assert_eq!(
+            debug("lift1 (\\x: int. x): Counter.clicks\n"),
+            "lift1 \\\"x\": int. -> \"x\" \"Counter.clicks\"!"
+        );
+    }
 }
@@ -6,40 +6,73 @@ pub enum Token {
     // Literals
     LitUnit,
     LitInt(BigInt),
+    LitFloat(f64),
+    LitString(String),
     Name(String),
+    /// A name qualified by the module it is defined in, `Foo.bar`.
+    QualifiedName(String),
+    True,
+    False,
     // Keywords
     If,
     Then,
     Else,
     Let,
+    Rec,
     In,
     Unit,
     Int,
+    Bool,
+    Float,
+    String,
+    List,
     Signal,
     LiftN(usize),
     Foldp,
     Async,
+    Not,
+    Mod,
+    Type,
+    Alias,
+    Case,
+    Of,
+    Module,
+    Exposing,
+    Import,
     // Symbols
-    BSlash, // \
-    Comma,  // ,
-    Dot,    // .
-    Colon,  // :
-    LArrow, // ->
-    Eq,     // =
+    BSlash,     // \
+    Comma,      // ,
+    Dot,        // .
+    Colon,      // :
+    ColonColon, // ::
+    LArrow,     // ->
+    Eq,         // =
+    Bar,        // |
+    Semi,       // ;
+    Underscore, // _
+    NewLine,    // a line starting a declaration
     // Delimiters
-    LParen, // (
-    RParen, // )
+    LParen,   // (
+    RParen,   // )
+    LBrace,   // {
+    RBrace,   // }
+    LBracket, // [
+    RBracket, // ]
     // BinOp
     Plus,
+    PlusPlus, // ++
     Minus,
     Mul,
     Div,
+    Percent, // %
     Great,   // >
     Less,    // <
     Leq,     // <=
     Geq,     // >=
     EqEqual, // ==
-    Ne,      // >=
+    Ne,      // !=
+    AndAnd,  // &&
+    OrOr,    // ||
 }
 
 impl fmt::Display for Token {
@@ -48,36 +81,67 @@ impl fmt::Display for Token {
         match *self {
             LitUnit => write!(f, "'()'"),
             LitInt(ref value) => write!(f, "'{}'", value),
-            Name(ref name) => write!(f, "'{}'", name),
+            LitFloat(value) => write!(f, "'{:?}'", value),
+            LitString(ref value) => write!(f, "{:?}", value),
+            Name(ref name) | QualifiedName(ref name) => write!(f, "'{}'", name),
+            True => write!(f, "'True'"),
+            False => write!(f, "'False'"),
             If => write!(f, "'if'"),
             Then => write!(f, "'then'"),
             Else => write!(f, "'else'"),
             Let => write!(f, "'let'"),
+            Rec => write!(f, "'rec'"),
             In => write!(f, "'in'"),
             Unit => write!(f, "'unit'"),
             Int => write!(f, "'int'"),
+            Bool => write!(f, "'bool'"),
+            Float => write!(f, "'float'"),
+            String => write!(f, "'string'"),
+            List => write!(f, "'list'"),
             Signal => write!(f, "'signal'"),
             LiftN(ref value) => write!(f, "'lift_{}'", value),
             Foldp => write!(f, "'foldp'"),
             Async => write!(f, "'async'"),
+            Not => write!(f, "'not'"),
+            Mod => write!(f, "'mod'"),
+            Type => write!(f, "'type'"),
+            Alias => write!(f, "'alias'"),
+            Case => write!(f, "'case'"),
+            Of => write!(f, "'of'"),
+            Module => write!(f, "'module'"),
+            Exposing => write!(f, "'exposing'"),
+            Import => write!(f, "'import'"),
             BSlash => write!(f, "'\\'"),
             Colon => write!(f, "':'"),
+            ColonColon => write!(f, "'::'"),
             LArrow => write!(f, "'->'"),
             Eq => write!(f, "'='"),
             LParen => write!(f, "'('"),
             RParen => write!(f, "')'"),
+            LBrace => write!(f, "'{{'"),
+            RBrace => write!(f, "'}}'"),
+            LBracket => write!(f, "'['"),
+            RBracket => write!(f, "']'"),
+            Bar => write!(f, "'|'"),
+            Semi => write!(f, "';'"),
+            Underscore => write!(f, "'_'"),
+            NewLine => write!(f, "a new line"),
             Plus => write!(f, "'+'"),
+            PlusPlus => write!(f, "'++'"),
             Minus => write!(f, "'-'"),
             Comma => write!(f, "','"),
             Dot => write!(f, "'.'"),
             Mul => write!(f, "'*'"),
             Div => write!(f, "'/'"),
+            Percent => write!(f, "'%'"),
             Great => write!(f, "'>'"),
             Less => write!(f, "'<'"),
             Leq => write!(f, "'<='"),
             Geq => write!(f, "'>='"),
             EqEqual => write!(f, "'=='"),
             Ne => write!(f, "'!='"),
+            AndAnd => write!(f, "'&&'"),
+            OrOr => write!(f, "'||'"),
         }
     }
 }
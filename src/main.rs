@@ -3,34 +3,128 @@ extern crate clap;
 
 use ansi_term::Colour;
 use clap::{App, Arg, SubCommand};
+use std::fs;
 use std::io::{self, Write};
+use std::path::Path;
 
 // Workspace
-use rustelm_analyzer::typechecker;
-use rustelm_parser::parser;
+use rustelm_analyzer::{
+    error::TypeCheckError,
+    modules::{self, ModuleError, ModuleErrorKind},
+    typechecker,
+};
+use rustelm_evaluator::{evaluator, graph::SignalGraph, simulator, value::Value};
+use rustelm_parser::{ast::Span, parser};
 
 /// Prompt input from user, return the string
 fn input() -> io::Result<String> {
-    io::stdout().write("> ".as_bytes()).unwrap();
+    io::stdout().write_all("> ".as_bytes()).unwrap();
+    io::stdout().flush().unwrap();
     let mut input = String::new();
     io::stdin().read_line(&mut input)?;
     Ok(input)
 }
 
-fn eval(mut input: String) -> Result<String, &'static str> {
+/// Render an error located at `span` in `source`: the message, followed by the offending line
+/// with a caret underneath.
+fn report(source: &str, span: Span, title: &str, message: &str) -> String {
+    let (line, col) = span.line_col(source);
+    let text = source.lines().nth(line - 1).unwrap_or("");
+    let width = source[span.start..span.end]
+        .lines()
+        .next()
+        .map_or(0, |s| s.chars().count())
+        .max(1);
+    let gutter = Colour::RGB(128, 128, 128);
+
+    format!(
+        "{}: {}\n{} {}\n{} {}{}",
+        Colour::Red.bold().paint(title),
+        message,
+        gutter.paint(format!("{:>4} |", line)),
+        text,
+        gutter.paint("     |"),
+        " ".repeat(col - 1),
+        Colour::Red.bold().paint("^".repeat(width)),
+    )
+}
+
+fn report_parse_error(source: &str, e: &parser::ParserError) -> String {
+    report(source, e.span(), "parse error", &e.to_string())
+}
+
+fn report_type_error(source: &str, e: &TypeCheckError) -> String {
+    report(source, e.span(), "type error", &e.to_string())
+}
+
+/// Render an error in one of the modules of a program, followed by the file it is in.
+fn report_module_error(e: &ModuleError) -> String {
+    let title = match *e.kind {
+        ModuleErrorKind::Parse(_) => "parse error",
+        ModuleErrorKind::Type(_) => "type error",
+    };
+    let (line, col) = e.span().line_col(&e.source);
+    format!(
+        "{}\n{} {}:{}:{}",
+        report(&e.source, e.span(), title, &e.to_string()),
+        Colour::RGB(128, 128, 128).paint("    -->"),
+        e.path.display(),
+        line,
+        col
+    )
+}
+
+fn eval(mut input: String) -> Result<String, String> {
     input.push('\n');
 
     match parser::parse(input.as_str()) {
-        Ok(expr) => match typechecker::typecheck_root(expr) {
-            Ok(ty) => Ok(format!("{:?}", ty)),
-            Err(_) => Err("Typecheck Error"),
+        Ok(expr) => match typechecker::typecheck_root(&expr) {
+            Ok(ty) => match evaluator::eval_root(&expr) {
+                Ok(value) => Ok(format!("{:?} : {:?}", value, ty)),
+                Err(e) => Err(format!(
+                    "{}: {}",
+                    Colour::Red.bold().paint("runtime error"),
+                    e
+                )),
+            },
+            Err(e) => Err(report_type_error(input.as_str(), &e)),
         },
-        Err(_) => Err("Parse Error"),
+        Err(e) => Err(report_parse_error(input.as_str(), &e)),
     }
 }
 
+/// Run the program in the file `program`, whose modules are under `root`, against the events in
+/// the file `trace`, returning the value of the program after each event.
+fn simulate(program: &str, root: Option<&str>, trace: &str) -> Result<Vec<String>, String> {
+    let path = Path::new(program);
+    let mut source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    source.push('\n');
+
+    // The modules are next to the program, unless told otherwise
+    let root = match root {
+        Some(root) => Path::new(root),
+        None => path.parent().unwrap_or_else(|| Path::new("")),
+    };
+    let expr = modules::check(root, path, source).map_err(|e| report_module_error(&e))?;
+    let signal = match evaluator::eval_root(&expr).map_err(|e| e.to_string())? {
+        Value::Signal(signal) => signal,
+        _ => return Err("the program is not a signal".to_owned()),
+    };
+
+    let trace = fs::read_to_string(trace).map_err(|e| e.to_string())?;
+    let events = simulator::parse_trace(trace.as_str()).map_err(|e| e.to_string())?;
+    let values =
+        simulator::simulate(SignalGraph::new(&signal), &events).map_err(|e| e.to_string())?;
+
+    Ok(events
+        .iter()
+        .zip(values)
+        .map(|(event, value)| format!("{}={:?} => {:?}", event.input, event.value, value))
+        .collect())
+}
+
 fn main() {
-    let _matches = App::new("Featherweight Elm Repl")
+    let matches = App::new("Featherweight Elm Repl")
         .version("1.0")
         .author("Yanwen Xu. <xuyanwen2012@gmail.com>")
         .about("Does awesome things")
@@ -45,8 +139,38 @@ fn main() {
                         .help("print debug information verbosely"),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("simulate")
+                .about("runs a program against a trace of input events")
+                .arg(
+                    Arg::with_name("PROGRAM")
+                        .help("the program to run")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("TRACE")
+                        .help("a CSV file of input events, one `input,value` row each, e.g. `MouseX,10`")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("root")
+                        .long("root")
+                        .takes_value(true)
+                        .help("the directory of the imported modules, the program's by default"),
+                ),
+        )
         .get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("simulate") {
+        let program = matches.value_of("PROGRAM").unwrap();
+        let trace = matches.value_of("TRACE").unwrap();
+        match simulate(program, matches.value_of("root"), trace) {
+            Ok(lines) => lines.iter().for_each(|line| println!("{}", line)),
+            Err(e) => println!("{}", e),
+        }
+        return;
+    }
+
     println!(
         "{} {} {}\n{}\n{}",
         Colour::RGB(128, 128, 128).paint("----"),
@@ -62,10 +186,32 @@ fn main() {
     loop {
         let source = input().unwrap();
 
+        // End of input
+        if source.is_empty() {
+            break;
+        }
+
         // Eval
         match eval(source) {
-            Ok(ty_str) => println!("{:?}", ty_str),
-            Err(e) => println!("{:?}", e),
+            Ok(output) => println!("{}", output),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::eval;
+
+    #[test]
+    fn test_runtime_errors() {
+        let err = |source: &str| eval(source.to_owned()).unwrap_err();
+
+        for source in ["1 / 0", "5 % 0", "1 mod 0"] {
+            let e = err(source);
+            assert!(e.contains("runtime error"), "{}", e);
+            assert!(e.ends_with("division by zero"), "{}", e);
         }
+        assert!(err("head []").ends_with("the list is empty"));
     }
 }